@@ -0,0 +1,85 @@
+//! Compare `decode()` with `str::from_utf8()`, and `count_code_points()` with counting the
+//! `char`s of a validated `&str`.
+//!
+//! Run with `cargo bench --bench validate`. Reports the throughput of each over a few
+//! kinds of input, best of several runs.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const INPUT_LEN: usize = 1 << 20;
+const RUNS: usize = 20;
+
+fn repeat_to_len(s: &str) -> Vec<u8> {
+    s.as_bytes()
+        .iter()
+        .copied()
+        .cycle()
+        .take(INPUT_LEN)
+        .collect()
+}
+
+fn best_of<F: FnMut()>(mut f: F) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(name: &str, input: &[u8], f: impl FnMut()) {
+    let time = best_of(f);
+    let mib_per_s = input.len() as f64 / time.as_secs_f64() / (1 << 20) as f64;
+    println!("{:<32} {:>10.1} MiB/s", name, mib_per_s);
+}
+
+fn main() {
+    let inputs = [
+        (
+            "ascii",
+            repeat_to_len("The quick brown fox jumps over the lazy dog. "),
+        ),
+        (
+            "log",
+            repeat_to_len(
+                "2024-05-01T12:00:00Z INFO request served path=/index.html status=200 \
+                 user=\"José\" elapsed_ms=12\n",
+            ),
+        ),
+        (
+            "json",
+            repeat_to_len(
+                r#"{"id":12345,"name":"widget","tags":["a","b","c"],"price":9.99,"city":"Zürich"},"#,
+            ),
+        ),
+        (
+            "latin",
+            repeat_to_len("Vous êtes déjà là, à côté du café. "),
+        ),
+        ("cjk", repeat_to_len("中华人民共和国的首都是北京。")),
+        ("emoji", repeat_to_len("\u{1F30D}\u{1F680}\u{1F600} ")),
+    ];
+    for (kind, input) in &inputs {
+        // Cut at a code point boundary so that the whole input is valid.
+        let len = (0..=input.len())
+            .rev()
+            .find(|&len| std::str::from_utf8(&input[..len]).is_ok())
+            .unwrap();
+        let input = &input[..len];
+        report(&format!("{}/str::from_utf8", kind), input, || {
+            black_box(std::str::from_utf8(black_box(input)).is_ok());
+        });
+        report(&format!("{}/decode", kind), input, || {
+            black_box(utf8_zero::decode(black_box(input)).is_ok());
+        });
+        report(&format!("{}/str::chars().count()", kind), input, || {
+            black_box(std::str::from_utf8(black_box(input)).map_or(0, |s| s.chars().count()));
+        });
+        report(&format!("{}/count_code_points", kind), input, || {
+            black_box(utf8_zero::count_code_points(black_box(input)).unwrap_or(0));
+        });
+    }
+}
@@ -0,0 +1,46 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::str;
+use utf8_zero::{count_code_points, DecodeError};
+
+/// What `str::from_utf8` reports, with the code point count when the input is valid.
+fn expected(data: &[u8]) -> (Result<usize, usize>, Option<usize>) {
+    match str::from_utf8(data) {
+        Ok(s) => (Ok(s.chars().count()), None),
+        Err(error) => (Err(error.valid_up_to()), error.error_len()),
+    }
+}
+
+/// The same, as reported by `count_code_points`.
+fn counted(data: &[u8]) -> (Result<usize, usize>, Option<usize>) {
+    match count_code_points(data) {
+        Ok(count) => (Ok(count), None),
+        Err(DecodeError::Invalid {
+            valid_prefix,
+            invalid_sequence,
+            ..
+        }) => (Err(valid_prefix.len()), Some(invalid_sequence.len())),
+        Err(DecodeError::Incomplete { valid_prefix, .. }) => (Err(valid_prefix.len()), None),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    // count_code_points validates with its own state machine, which must report exactly
+    // what std does.
+    assert_eq!(counted(data), expected(data));
+
+    // And so must every suffix, which starts the state machine mid-sequence.
+    for start in 1..data.len().min(8) {
+        let suffix = &data[start..];
+        assert_eq!(counted(suffix), expected(suffix));
+    }
+
+    // Behind a run of ASCII, so that the input reaches the ASCII fast path at every
+    // offset into a word.
+    if let Some((&pad, rest)) = data.split_first() {
+        let mut padded = vec![b'a'; usize::from(pad % 64)];
+        padded.extend_from_slice(rest);
+        assert_eq!(counted(&padded), expected(&padded));
+    }
+});
@@ -6,6 +6,12 @@ use std::str;
 fuzz_target!(|data: &[u8]| {
     let result = utf8_zero::decode(data);
 
+    // decode_nopanic must agree with decode (and, like everything here, not panic).
+    assert_eq!(
+        format!("{:?}", utf8_zero::decode_nopanic(data)),
+        format!("{:?}", result)
+    );
+
     match result {
         Ok(valid) => {
             // If decode says OK, std must agree and the entire input is valid UTF-8.
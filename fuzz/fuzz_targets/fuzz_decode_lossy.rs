@@ -0,0 +1,29 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::borrow::Cow;
+
+fuzz_target!(|data: &[u8]| {
+    let expected = String::from_utf8_lossy(data);
+    let output = utf8_zero::decode_lossy(data);
+
+    assert_eq!(
+        output, expected,
+        "decode_lossy output differs from String::from_utf8_lossy"
+    );
+
+    // Only input that needed no replacement may be borrowed.
+    assert_eq!(
+        matches!(output, Cow::Borrowed(_)),
+        matches!(expected, Cow::Borrowed(_))
+    );
+
+    // count_replacements must match the U+FFFD inserted by from_utf8_lossy. Every
+    // `EF BF BD` in the input is a U+FFFD that was already there: `EF` is a lead
+    // byte, so it can't be part of another sequence.
+    let already_there = data.windows(3).filter(|w| *w == b"\xEF\xBF\xBD").count();
+    assert_eq!(
+        utf8_zero::count_replacements(data),
+        expected.matches('\u{FFFD}').count() - already_there
+    );
+});
@@ -0,0 +1,24 @@
+//! Finding the length of a run of ASCII, for the fast path of the code point counting DFA.
+//!
+//! This scans a word at a time. A vectorized scan behind a `simd` feature was tried, for
+//! [`decode()`](crate::decode) and [`validate()`](crate::validate) as well, and dropped:
+//! `str::from_utf8` already skips ASCII a word at a time, fast enough that an SSE2 scan in
+//! front of it made `decode()` slower on every input in `benches/validate.rs`, ASCII-heavy
+//! logs and JSON included.
+
+/// The number of leading ASCII bytes in `input`.
+#[inline]
+pub(crate) fn ascii_len(input: &[u8]) -> usize {
+    const WORD: usize = core::mem::size_of::<usize>();
+    const HIGH_BITS: usize = usize::from_ne_bytes([0x80; WORD]);
+
+    let mut i = 0;
+    while let Some(word) = input.get(i..i + WORD) {
+        let word = usize::from_ne_bytes(word.try_into().unwrap());
+        if word & HIGH_BITS != 0 {
+            break;
+        }
+        i += WORD;
+    }
+    i + input[i..].iter().take_while(|b| b.is_ascii()).count()
+}
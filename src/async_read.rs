@@ -0,0 +1,139 @@
+use super::*;
+use crate::read::{next_chunk, BytesSource, NextChunk};
+use core::future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_io::AsyncBufRead;
+use std::borrow::ToOwned;
+use std::io;
+use std::string::String;
+
+/// Wraps a `futures_io::AsyncBufRead` buffered byte stream and decodes it as UTF-8.
+///
+/// The asynchronous counterpart of [`BufReadDecoder`], for sockets and other readers
+/// that must not block. It shares its decoding with `BufReadDecoder`, so a code point
+/// split across reads is pieced together the same way and the chunks are the same. As
+/// they cannot borrow the decoder across an `.await`, they are returned as `String`s.
+///
+/// Requires the `futures` feature. Tokio readers can be adapted with `tokio-util`'s
+/// `compat` module. A reader that is not `Unpin` can be pinned with `Box::pin`.
+///
+/// ```
+/// use futures::executor::block_on;
+/// use futures::io::Cursor;
+/// use utf8_zero::AsyncBufReadDecoder;
+///
+/// let mut decoder = AsyncBufReadDecoder::new(Cursor::new(b"caf\xC3\xA9\xFF!".to_vec()));
+/// let mut output = String::new();
+/// block_on(async {
+///     while let Some(chunk) = decoder.next_lossy().await {
+///         output.push_str(&chunk.unwrap());
+///     }
+/// });
+/// assert_eq!(output, "caf\u{E9}\u{FFFD}!");
+/// ```
+///
+/// It is also a `Stream` of the same chunks, for use with `StreamExt` combinators:
+///
+/// ```
+/// use futures::executor::block_on;
+/// use futures::io::Cursor;
+/// use futures::{StreamExt, TryStreamExt};
+/// use utf8_zero::AsyncBufReadDecoder;
+///
+/// let decoder = AsyncBufReadDecoder::new(Cursor::new(b"caf\xC3\xA9\xE2\x82".to_vec()));
+/// let output: String = block_on(decoder.try_collect()).unwrap();
+/// assert_eq!(output, "caf\u{E9}\u{FFFD}");
+///
+/// let decoder = AsyncBufReadDecoder::new(Cursor::new(b"a\xFFb".to_vec()));
+/// let lens: Vec<usize> = block_on(decoder.map(|chunk| chunk.unwrap().len()).collect());
+/// assert_eq!(lens, [1, 3, 1]);
+/// ```
+pub struct AsyncBufReadDecoder<R> {
+    reader: R,
+    bytes_consumed: usize,
+    incomplete: Incomplete,
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufReadDecoder<R> {
+    /// Wrap `reader` in a new decoder.
+    pub fn new(reader: R) -> Self {
+        AsyncBufReadDecoder {
+            reader,
+            bytes_consumed: 0,
+            incomplete: Incomplete::empty(),
+        }
+    }
+
+    /// Decode and consume the next chunk of input, replacing UTF-8 errors with U+FFFD.
+    ///
+    /// Returns `None` at the end of the stream. Each invalid sequence is its own chunk,
+    /// `"\u{FFFD}"`, as is an incomplete code point at the end of the stream. An I/O
+    /// error is returned as is, and decoding can continue after it.
+    pub async fn next_lossy(&mut self) -> Option<io::Result<String>> {
+        future::poll_fn(|cx| self.poll_next_lossy(cx)).await
+    }
+
+    /// Poll for the next chunk of input, as returned by
+    /// [`next_lossy()`](Self::next_lossy).
+    pub fn poll_next_lossy(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<String>>> {
+        loop {
+            if self.bytes_consumed > 0 {
+                Pin::new(&mut self.reader).consume(self.bytes_consumed);
+                self.bytes_consumed = 0;
+            }
+            let buf = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(buf)) => buf,
+                Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error))),
+                Poll::Pending => return Poll::Pending,
+            };
+            let (source, valid) =
+                match next_chunk(buf, &mut self.incomplete, &mut self.bytes_consumed) {
+                    NextChunk::Found(source, valid) => (source, valid),
+                    NextChunk::NeedMore => continue,
+                    NextChunk::Eof => return Poll::Ready(None),
+                };
+            let bytes = match source {
+                BytesSource::BufRead(byte_count) => {
+                    self.bytes_consumed = byte_count;
+                    &buf[..byte_count]
+                }
+                BytesSource::Incomplete => self.incomplete.take_buffer(),
+            };
+            let chunk = if valid {
+                unsafe { str::from_utf8_unchecked(bytes) }.to_owned()
+            } else {
+                REPLACEMENT_CHARACTER.to_owned()
+            };
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+    }
+
+    /// Get a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwrap the decoder, returning the underlying reader.
+    ///
+    /// The bytes of the last chunk returned are consumed from it first. A code point
+    /// split across fills of its buffer may be partly held by the decoder, as for
+    /// [`BufReadDecoder::into_inner()`], and is returned as the [`Incomplete`].
+    pub fn into_inner(mut self) -> (R, Incomplete) {
+        if self.bytes_consumed > 0 {
+            Pin::new(&mut self.reader).consume(self.bytes_consumed);
+        }
+        (self.reader, self.incomplete)
+    }
+}
+
+/// Yields the chunks of [`next_lossy()`](AsyncBufReadDecoder::next_lossy), ending with
+/// one U+FFFD if the stream ends in the middle of a code point.
+impl<R: AsyncBufRead + Unpin> Stream for AsyncBufReadDecoder<R> {
+    type Item = io::Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_lossy(cx)
+    }
+}
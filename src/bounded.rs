@@ -0,0 +1,221 @@
+use super::*;
+
+/// An incomplete byte sequence, like [`Incomplete`], with room for at most `N` bytes.
+///
+/// For input known never to contain sequences longer than `N` bytes (see
+/// [`decode_max_len()`]), this stores carried-over state in `N + 1` bytes instead of 5.
+/// `N` must be between 2 and 4; other values fail to compile. Two values compare equal if
+/// their occupied bytes are equal, as for `Incomplete`.
+///
+/// ```
+/// use utf8_zero::{decode, DecodeError, IncompleteN};
+///
+/// let Err(DecodeError::Incomplete { incomplete_suffix, .. }) = decode(b"ab\xE2\x82") else {
+///     unreachable!()
+/// };
+/// let carried = IncompleteN::<3>::from_incomplete(&incomplete_suffix).unwrap();
+/// assert_eq!(carried.as_bytes(), b"\xE2\x82");
+/// assert_eq!(core::mem::size_of_val(&carried), 4);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct IncompleteN<const N: usize> {
+    /// Internal buffer holding the incomplete bytes (up to `N`).
+    pub buffer: [u8; N],
+    /// How many bytes in `buffer` are occupied.
+    pub buffer_len: u8,
+}
+
+impl<const N: usize> PartialEq for IncompleteN<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> Eq for IncompleteN<N> {}
+
+impl<const N: usize> IncompleteN<N> {
+    const VALID_N: () = assert!(N >= 2 && N <= 4, "IncompleteN requires 2 <= N <= 4");
+
+    /// Create an empty `IncompleteN` with no buffered bytes.
+    pub fn empty() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_N;
+        IncompleteN {
+            buffer: [0; N],
+            buffer_len: 0,
+        }
+    }
+
+    /// Returns `true` if no bytes are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.buffer_len == 0
+    }
+
+    /// Create an `IncompleteN` pre-filled with the given bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is longer than `N` bytes.
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut incomplete = Self::empty();
+        incomplete.buffer[..bytes.len()].copy_from_slice(bytes);
+        incomplete.buffer_len = bytes.len() as u8;
+        incomplete
+    }
+
+    /// The buffered bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..cmp::min(self.buffer_len as usize, N)]
+    }
+
+    /// Copy the bytes buffered in `incomplete`, or `None` if there are more than `N`.
+    pub fn from_incomplete(incomplete: &Incomplete) -> Option<Self> {
+        let bytes = incomplete.as_bytes();
+        if bytes.len() > N {
+            return None;
+        }
+        Some(Self::new(bytes))
+    }
+}
+
+impl<const N: usize> From<IncompleteN<N>> for Incomplete {
+    fn from(incomplete: IncompleteN<N>) -> Self {
+        Incomplete::new(incomplete.as_bytes())
+    }
+}
+
+/// Error from [`decode_max_len()`].
+///
+/// Like [`DecodeError`], with an incomplete suffix that fits in an [`IncompleteN<MAX>`],
+/// plus [`TooLong`](MaxLenError::TooLong) for a sequence that is rejected by the limit
+/// rather than by UTF-8.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaxLenError<'a, const MAX: usize> {
+    /// An invalid sequence, as in `DecodeError::Invalid`.
+    Invalid {
+        /// The leading valid UTF-8 portion of the input.
+        valid_prefix: &'a str,
+        /// The bytes that form the invalid sequence.
+        invalid_sequence: &'a [u8],
+        /// The bytes after the invalid sequence, not yet decoded.
+        remaining_input: &'a [u8],
+    },
+
+    /// The input ends in the middle of a code point, as in `DecodeError::Incomplete`.
+    Incomplete {
+        /// The leading valid UTF-8 portion of the input.
+        valid_prefix: &'a str,
+        /// The trailing bytes that start a code point of at most `MAX` bytes.
+        incomplete_suffix: IncompleteN<MAX>,
+    },
+
+    /// A lead byte starts a sequence longer than `MAX` bytes. The sequence may be valid
+    /// UTF-8; it is rejected by the limit either way.
+    TooLong {
+        /// The valid, accepted text before the lead byte.
+        valid_prefix: &'a str,
+        /// The length of the sequence that the lead byte starts, more than `MAX`.
+        sequence_len: usize,
+    },
+}
+
+impl<'a, const MAX: usize> fmt::Display for MaxLenError<'a, MAX> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MaxLenError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            } => DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            }
+            .fmt(f),
+            MaxLenError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            } => DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix: incomplete_suffix.into(),
+            }
+            .fmt(f),
+            MaxLenError::TooLong {
+                valid_prefix,
+                sequence_len,
+            } => write!(
+                f,
+                "found {}-byte sequence, longer than {} bytes, after {} valid bytes",
+                sequence_len,
+                MAX,
+                valid_prefix.len()
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const MAX: usize> std::error::Error for MaxLenError<'a, MAX> {}
+
+/// Same as [`decode()`], but rejecting code points that take more than `MAX` bytes.
+///
+/// A lead byte that starts a sequence longer than `MAX` bytes is reported as
+/// [`MaxLenError::TooLong`], whether or not the rest of the sequence follows. This both
+/// enforces a policy (for example `MAX = 3` keeps to the Basic Multilingual Plane) and
+/// guarantees that an incomplete suffix needs at most `MAX - 1` bytes, so it is returned
+/// as an [`IncompleteN<MAX>`](IncompleteN) that takes `MAX + 1` bytes instead of the 5 of
+/// an [`Incomplete`]. The cost is that such input can't be decoded at all rather than
+/// lossily. `MAX` must be between 2 and 4; other values fail to compile.
+///
+/// ```
+/// use utf8_zero::{decode_max_len, MaxLenError};
+///
+/// assert_eq!(decode_max_len::<3>("€".as_bytes()).unwrap(), "€");
+/// assert_eq!(
+///     decode_max_len::<3>("a\u{1F30D}".as_bytes()),
+///     Err(MaxLenError::TooLong { valid_prefix: "a", sequence_len: 4 })
+/// );
+/// ```
+pub fn decode_max_len<const MAX: usize>(input: &[u8]) -> Result<&str, MaxLenError<'_, MAX>> {
+    #[allow(clippy::let_unit_value)]
+    let () = IncompleteN::<MAX>::VALID_N;
+    let result = decode(input);
+    let valid = match result {
+        Ok(valid) => valid,
+        Err(DecodeError::Invalid { valid_prefix, .. })
+        | Err(DecodeError::Incomplete { valid_prefix, .. }) => valid_prefix,
+    };
+    let too_long = valid
+        .char_indices()
+        .map(|(i, c)| (i, c.len_utf8()))
+        .find(|&(_, len)| len > MAX)
+        .or_else(|| {
+            let len = sequence_len(*input.get(valid.len())?)?;
+            (len > MAX).then_some((valid.len(), len))
+        });
+    if let Some((i, sequence_len)) = too_long {
+        return Err(MaxLenError::TooLong {
+            valid_prefix: &valid[..i],
+            sequence_len,
+        });
+    }
+    match result {
+        Ok(valid) => Ok(valid),
+        Err(DecodeError::Invalid {
+            valid_prefix,
+            invalid_sequence,
+            remaining_input,
+        }) => Err(MaxLenError::Invalid {
+            valid_prefix,
+            invalid_sequence,
+            remaining_input,
+        }),
+        Err(DecodeError::Incomplete {
+            valid_prefix,
+            incomplete_suffix,
+        }) => Err(MaxLenError::Incomplete {
+            valid_prefix,
+            incomplete_suffix: IncompleteN::new(incomplete_suffix.as_bytes()),
+        }),
+    }
+}
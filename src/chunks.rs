@@ -0,0 +1,177 @@
+use super::*;
+
+/// A valid run of UTF-8 and the invalid bytes that follow it, from [`utf8_chunks()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Utf8Chunk<'a> {
+    /// The valid UTF-8 text, possibly empty.
+    pub valid: &'a str,
+    /// The invalid sequence after `valid`: a maximal invalid subpart, or an incomplete
+    /// sequence at the end of the input. Empty only in the last chunk.
+    pub invalid: &'a [u8],
+}
+
+/// Iterator over the [`Utf8Chunk`]s of a byte slice. Created by [`utf8_chunks()`].
+#[derive(Debug, Clone)]
+pub struct Utf8Chunks<'a> {
+    remaining: &'a [u8],
+}
+
+/// Iterate over `input` as alternating valid runs and invalid sequences.
+///
+/// This is the [`decode()`] loop over a whole buffer: each chunk holds the valid text up
+/// to the next error and the invalid bytes of that error, where lossy decoding would
+/// insert one U+FFFD. Concatenating all chunks gives back `input`. An empty input gives
+/// no chunks. Does not allocate.
+///
+/// ```
+/// use utf8_zero::utf8_chunks;
+///
+/// let mut lossy = String::new();
+/// for chunk in utf8_chunks(b"a\xFFb\xC0\x80c") {
+///     lossy.push_str(chunk.valid);
+///     if !chunk.invalid.is_empty() {
+///         lossy.push('\u{FFFD}');
+///     }
+/// }
+/// assert_eq!(lossy, "a\u{FFFD}b\u{FFFD}\u{FFFD}c");
+/// ```
+pub fn utf8_chunks(input: &[u8]) -> Utf8Chunks<'_> {
+    Utf8Chunks { remaining: input }
+}
+
+impl<'a> Iterator for Utf8Chunks<'a> {
+    type Item = Utf8Chunk<'a>;
+
+    fn next(&mut self) -> Option<Utf8Chunk<'a>> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let input = self.remaining;
+        Some(match decode(input) {
+            Ok(valid) => {
+                self.remaining = &[];
+                Utf8Chunk {
+                    valid,
+                    invalid: &[],
+                }
+            }
+            Err(DecodeError::Incomplete { valid_prefix, .. }) => {
+                self.remaining = &[];
+                Utf8Chunk {
+                    valid: valid_prefix,
+                    invalid: &input[valid_prefix.len()..],
+                }
+            }
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            }) => {
+                self.remaining = remaining_input;
+                Utf8Chunk {
+                    valid: valid_prefix,
+                    invalid: invalid_sequence,
+                }
+            }
+        })
+    }
+}
+
+impl core::iter::FusedIterator for Utf8Chunks<'_> {}
+
+/// One region of a byte slice, from [`decode_steps()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DecodeStep<'a> {
+    /// A non-empty run of valid UTF-8.
+    Valid(&'a str),
+    /// A maximal invalid subpart, where lossy decoding would insert one U+FFFD.
+    Invalid(&'a [u8]),
+    /// The start of a code point that the slice ends in the middle of. Always the last
+    /// step, and more input could complete it.
+    Incomplete(Incomplete),
+}
+
+/// Iterator over the [`DecodeStep`]s of a byte slice. Created by [`decode_steps()`].
+#[derive(Debug, Clone)]
+pub struct DecodeSteps<'a> {
+    remaining: &'a [u8],
+    /// An invalid sequence found after the `Valid` step just returned.
+    pending_invalid: Option<&'a [u8]>,
+    /// A trailing incomplete sequence found after the `Valid` step just returned.
+    pending_incomplete: Option<Incomplete>,
+}
+
+/// Iterate over every region of `input`: valid runs, invalid sequences, and an
+/// incomplete code point at the end.
+///
+/// Unlike [`utf8_chunks()`], the end of the input says whether it was invalid or only
+/// cut short: a final [`DecodeStep::Incomplete`] means more bytes could complete it, so
+/// it is worth reading more before deciding. An empty input gives no steps. Does not
+/// allocate.
+///
+/// ```
+/// use utf8_zero::{decode_steps, DecodeStep, Incomplete};
+///
+/// let steps: Vec<_> = decode_steps(b"a\xFFb\xE2\x82").collect();
+/// assert_eq!(
+///     steps,
+///     [
+///         DecodeStep::Valid("a"),
+///         DecodeStep::Invalid(b"\xFF"),
+///         DecodeStep::Valid("b"),
+///         DecodeStep::Incomplete(Incomplete::new(b"\xE2\x82")),
+///     ]
+/// );
+/// ```
+pub fn decode_steps(input: &[u8]) -> DecodeSteps<'_> {
+    DecodeSteps {
+        remaining: input,
+        pending_invalid: None,
+        pending_incomplete: None,
+    }
+}
+
+impl<'a> Iterator for DecodeSteps<'a> {
+    type Item = DecodeStep<'a>;
+
+    fn next(&mut self) -> Option<DecodeStep<'a>> {
+        if let Some(invalid) = self.pending_invalid.take() {
+            return Some(DecodeStep::Invalid(invalid));
+        }
+        if let Some(incomplete) = self.pending_incomplete.take() {
+            return Some(DecodeStep::Incomplete(incomplete));
+        }
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let valid = match decode(self.remaining) {
+            Ok(valid) => {
+                self.remaining = &[];
+                valid
+            }
+            Err(DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            }) => {
+                self.remaining = &[];
+                self.pending_incomplete = Some(incomplete_suffix);
+                valid_prefix
+            }
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            }) => {
+                self.remaining = remaining_input;
+                self.pending_invalid = Some(invalid_sequence);
+                valid_prefix
+            }
+        };
+        if valid.is_empty() {
+            return self.next();
+        }
+        Some(DecodeStep::Valid(valid))
+    }
+}
+
+impl core::iter::FusedIterator for DecodeSteps<'_> {}
@@ -0,0 +1,127 @@
+//! A table-driven UTF-8 validator, in the style of Björn Höhrmann's DFA.
+//!
+//! This is not what [`decode()`](crate::decode) or [`Incomplete`](crate::Incomplete)
+//! validate with. Measured against `str::from_utf8`, which has its own word-at-a-time
+//! ASCII path, the state machine was slower on every kind of input, and `Incomplete`
+//! only ever validates the few bytes of one code point, so there was no re-scan cost to
+//! save there. It stays crate-private for the one job std can't do in the same pass:
+//! counting code points while validating, for
+//! [`count_code_points()`](crate::count_code_points).
+
+use crate::ascii::ascii_len;
+
+/// Byte classes. Bytes in a class are interchangeable for every state.
+const ASCII: u8 = 0; // 00..=7F
+const CONT_LOW: u8 = 1; // 80..=8F
+const CONT_MID: u8 = 2; // 90..=9F
+const CONT_HIGH: u8 = 3; // A0..=BF
+const NEVER: u8 = 4; // C0, C1, F5..=FF
+const LEAD_2: u8 = 5; // C2..=DF
+const LEAD_E0: u8 = 6; // E0
+const LEAD_3: u8 = 7; // E1..=EC, EE, EF
+const LEAD_ED: u8 = 8; // ED
+const LEAD_F0: u8 = 9; // F0
+const LEAD_4: u8 = 10; // F1..=F3
+const LEAD_F4: u8 = 11; // F4
+const CLASS_COUNT: usize = 12;
+
+/// States, named for what they expect next.
+const ACCEPT: u8 = 0; // a new code point
+const REJECT: u8 = 1; // nothing: the sequence is invalid
+const NEED_1: u8 = 2; // one continuation byte
+const NEED_2: u8 = 3; // two continuation bytes
+const NEED_3: u8 = 4; // three continuation bytes
+const AFTER_E0: u8 = 5; // A0..=BF, then one continuation byte (no overlongs)
+const AFTER_ED: u8 = 6; // 80..=9F, then one continuation byte (no surrogates)
+const AFTER_F0: u8 = 7; // 90..=BF, then two continuation bytes (no overlongs)
+const AFTER_F4: u8 = 8; // 80..=8F, then two continuation bytes (nothing above U+10FFFF)
+const STATE_COUNT: usize = 9;
+
+static CLASSES: [u8; 256] = {
+    let mut classes = [NEVER; 256];
+    let mut b = 0;
+    while b < 256 {
+        classes[b] = match b as u8 {
+            0x00..=0x7F => ASCII,
+            0x80..=0x8F => CONT_LOW,
+            0x90..=0x9F => CONT_MID,
+            0xA0..=0xBF => CONT_HIGH,
+            0xC2..=0xDF => LEAD_2,
+            0xE0 => LEAD_E0,
+            0xED => LEAD_ED,
+            0xE1..=0xEF => LEAD_3,
+            0xF0 => LEAD_F0,
+            0xF1..=0xF3 => LEAD_4,
+            0xF4 => LEAD_F4,
+            _ => NEVER,
+        };
+        b += 1;
+    }
+    classes
+};
+
+static TRANSITIONS: [[u8; CLASS_COUNT]; STATE_COUNT] = {
+    let mut transitions = [[REJECT; CLASS_COUNT]; STATE_COUNT];
+    let accept = &mut transitions[ACCEPT as usize];
+    accept[ASCII as usize] = ACCEPT;
+    accept[LEAD_2 as usize] = NEED_1;
+    accept[LEAD_E0 as usize] = AFTER_E0;
+    accept[LEAD_3 as usize] = NEED_2;
+    accept[LEAD_ED as usize] = AFTER_ED;
+    accept[LEAD_F0 as usize] = AFTER_F0;
+    accept[LEAD_4 as usize] = NEED_3;
+    accept[LEAD_F4 as usize] = AFTER_F4;
+    let mut class = CONT_LOW;
+    while class <= CONT_HIGH {
+        transitions[NEED_1 as usize][class as usize] = ACCEPT;
+        transitions[NEED_2 as usize][class as usize] = NEED_1;
+        transitions[NEED_3 as usize][class as usize] = NEED_2;
+        class += 1;
+    }
+    transitions[AFTER_E0 as usize][CONT_HIGH as usize] = NEED_1;
+    transitions[AFTER_ED as usize][CONT_LOW as usize] = NEED_1;
+    transitions[AFTER_ED as usize][CONT_MID as usize] = NEED_1;
+    transitions[AFTER_F0 as usize][CONT_MID as usize] = NEED_2;
+    transitions[AFTER_F0 as usize][CONT_HIGH as usize] = NEED_2;
+    transitions[AFTER_F4 as usize][CONT_LOW as usize] = NEED_2;
+    transitions
+};
+
+/// Validate `input` as UTF-8, counting the code points in its valid prefix.
+///
+/// Returns what `str::from_utf8` reports, plus the count: how many leading bytes are
+/// valid, the length of the invalid sequence that follows them or `None` if there is
+/// none, and how many code points the valid bytes hold. As with
+/// `Utf8Error::error_len()`, `None` with fewer than `input.len()` valid bytes means that
+/// `input` ends with an incomplete sequence.
+pub(crate) fn validate_counting(input: &[u8]) -> (usize, Option<usize>, usize) {
+    let mut i = 0;
+    let mut count = 0;
+    while i < input.len() {
+        // Between code points: skip any run of ASCII in one go.
+        if input[i] < 0x80 {
+            let run = ascii_len(&input[i..]);
+            i += run;
+            count += run;
+            continue;
+        }
+        // Run the state machine over one multi-byte code point.
+        let start = i;
+        let mut state = ACCEPT;
+        loop {
+            let Some(&byte) = input.get(i) else {
+                return (start, None, count);
+            };
+            state = TRANSITIONS[state as usize][CLASSES[byte as usize] as usize];
+            if state == REJECT {
+                return (start, Some((i - start).max(1)), count);
+            }
+            i += 1;
+            if state == ACCEPT {
+                count += 1;
+                break;
+            }
+        }
+    }
+    (input.len(), None, count)
+}
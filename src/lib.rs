@@ -16,13 +16,67 @@
 #[cfg(feature = "std")]
 extern crate std;
 
+mod ascii;
+#[cfg(feature = "futures")]
+mod async_read;
+mod bounded;
+mod chunks;
+mod dfa;
 mod lossy;
 #[cfg(feature = "std")]
+mod owned;
+mod policy;
+#[cfg(feature = "std")]
+mod profile;
+#[cfg(feature = "std")]
 mod read;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod stream;
+mod strict;
+#[cfg(feature = "std")]
+mod string;
+#[cfg(any(
+    feature = "unicode-bidi",
+    feature = "unicode-normalization",
+    feature = "unicode-segmentation"
+))]
+mod unicode;
+#[cfg(feature = "std")]
+mod write;
 
-pub use lossy::LossyDecoder;
+#[cfg(feature = "futures")]
+pub use async_read::AsyncBufReadDecoder;
+pub use bounded::{decode_max_len, IncompleteN, MaxLenError};
+pub use chunks::{decode_steps, utf8_chunks, DecodeStep, DecodeSteps, Utf8Chunk, Utf8Chunks};
+pub use lossy::{FmtWriteSink, LossyDecoder, Utf8Sink};
+#[cfg(feature = "std")]
+pub use owned::OwnedDecodeError;
+pub use policy::{
+    decode_in_ranges, decode_without_nul, PolicyError, PolicyReason, PolicyRejection,
+};
+#[cfg(feature = "std")]
+pub use profile::{decode_profiled, ConformanceProfile, ProfileError};
+#[cfg(feature = "std")]
+pub use read::{
+    decode_to_channel, BufReadDecoder, BufReadDecoderError, ReadToStringError, Utf8LossyReader,
+};
+pub use stream::StreamDecoder;
+pub use strict::{StrictDecoder, StrictDecoderError};
 #[cfg(feature = "std")]
-pub use read::{BufReadDecoder, BufReadDecoderError};
+pub use string::{
+    decode_latin1_fallback, decode_lossy, decode_lossy_drop_incomplete, decode_modified_utf8,
+    decode_or_message, decode_skipping_reported, decode_to_json_string, decode_validity_mask,
+    decode_wtf8, error_ranges, repair_double_encoded, retain_valid_in_place, valid_runs,
+};
+#[cfg(feature = "unicode-segmentation")]
+pub use unicode::decode_grapheme_count;
+#[cfg(feature = "unicode-normalization")]
+pub use unicode::ends_with_combining;
+#[cfg(feature = "unicode-bidi")]
+pub use unicode::{decode_text_direction, TextDirection};
+#[cfg(feature = "std")]
+pub use write::Utf8LossyWriter;
 
 use core::cmp;
 use core::fmt;
@@ -31,8 +85,79 @@ use core::str;
 /// The replacement character, U+FFFD. In lossy decoding, insert it for every decoding error.
 pub const REPLACEMENT_CHARACTER: &str = "\u{FFFD}";
 
+/// What a streaming decoder does with invalid sequences.
+///
+/// Accepted by [`LossyDecoder::with_policy()`] and [`StrictDecoder::with_policy()`], so
+/// the choice can be made in one place. A trailing incomplete sequence at the end of the
+/// input is treated like an invalid one, except by `TruncationMarker`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ReplacementPolicy {
+    /// Push this string in place of each invalid sequence, that is each maximal invalid
+    /// subpart. The default is `Lossy(REPLACEMENT_CHARACTER)`.
+    Lossy(&'static str),
+    /// Push this string once for every byte of each invalid sequence.
+    LossyPerByte(&'static str),
+    /// Push this string once for each run of adjacent invalid sequences, however long.
+    LossyCoalesced(&'static str),
+    /// Push `replacement` in place of each invalid sequence like `Lossy`, but `marker`
+    /// for a sequence that is incomplete at the end of the input, which was cut off
+    /// rather than corrupted.
+    TruncationMarker {
+        /// Pushed for an invalid sequence.
+        replacement: &'static str,
+        /// Pushed for an incomplete sequence at the end of the input.
+        marker: &'static str,
+    },
+    /// Drop invalid sequences from the output.
+    Skip,
+    /// Stop decoding at the first invalid sequence and report it.
+    ///
+    /// The decoder has then consumed the input up to the end of the invalid sequence,
+    /// and the rest of the chunk is not decoded. Feeding more input carries on from
+    /// there, so to skip the bad bytes, feed the rest of the chunk.
+    Strict,
+}
+
+impl Default for ReplacementPolicy {
+    fn default() -> Self {
+        ReplacementPolicy::Lossy(REPLACEMENT_CHARACTER)
+    }
+}
+
+impl ReplacementPolicy {
+    /// What a streaming decoder pushes for the bad sequence of `invalid_len` bytes at
+    /// `offset`: a string and how many times to push it, or `None` to stop for `Strict`.
+    ///
+    /// `last_invalid_end` is where the previous bad sequence ended, kept by the decoder
+    /// across calls so that `LossyCoalesced` can tell adjacent sequences apart.
+    pub(crate) fn replacement(
+        self,
+        last_invalid_end: &mut Option<usize>,
+        offset: usize,
+        invalid_len: usize,
+        kind: DecodeErrorKind,
+    ) -> Option<(&'static str, usize)> {
+        let adjacent = *last_invalid_end == Some(offset);
+        *last_invalid_end = Some(offset + invalid_len);
+        match self {
+            ReplacementPolicy::Lossy(replacement) => Some((replacement, 1)),
+            ReplacementPolicy::LossyCoalesced(_) if adjacent => Some(("", 0)),
+            ReplacementPolicy::LossyCoalesced(replacement) => Some((replacement, 1)),
+            ReplacementPolicy::LossyPerByte(replacement) => Some((replacement, invalid_len)),
+            ReplacementPolicy::TruncationMarker { marker, .. }
+                if kind == DecodeErrorKind::Incomplete =>
+            {
+                Some((marker, 1))
+            }
+            ReplacementPolicy::TruncationMarker { replacement, .. } => Some((replacement, 1)),
+            ReplacementPolicy::Skip => Some(("", 0)),
+            ReplacementPolicy::Strict => None,
+        }
+    }
+}
+
 /// Error from [`decode()`] when the input is not entirely valid UTF-8.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DecodeError<'a> {
     /// In lossy decoding insert `valid_prefix`, then `"\u{FFFD}"`,
     /// then call `decode()` again with `remaining_input`.
@@ -55,6 +180,203 @@ pub enum DecodeError<'a> {
     },
 }
 
+/// Which variant a [`DecodeError`] is, without its data. Returned by [`DecodeError::kind()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DecodeErrorKind {
+    /// An invalid byte sequence, as in `DecodeError::Invalid`.
+    Invalid,
+    /// The input ended in the middle of a code point, as in `DecodeError::Incomplete`.
+    Incomplete,
+}
+
+/// Why a byte sequence is invalid, as classified by [`DecodeError::invalid_reason()`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum InvalidReason {
+    /// A non-shortest form encoding, such as `C0 80` for U+0000.
+    Overlong,
+    /// An encoded UTF-16 surrogate code point, U+D800 to U+DFFF (`ED A0..=BF ..`).
+    Surrogate,
+    /// A code point above U+10FFFF (`F4 90..=BF ..` or an `F5`..`F7` lead byte).
+    OutOfRange,
+    /// A continuation byte (`80`..`BF`) without a lead byte before it.
+    UnexpectedContinuation,
+    /// A multi-byte sequence cut short by a byte that can't continue it.
+    UnexpectedEnd,
+    /// A byte that never appears in UTF-8: `F8`..`FF`.
+    InvalidByte,
+}
+
+impl<'a> DecodeError<'a> {
+    /// Returns `true` for `DecodeError::Invalid`.
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, DecodeError::Invalid { .. })
+    }
+
+    /// Returns `true` for `DecodeError::Incomplete`.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, DecodeError::Incomplete { .. })
+    }
+
+    /// Which variant this is.
+    ///
+    /// ```
+    /// use utf8_zero::{decode, DecodeErrorKind};
+    ///
+    /// let kinds: Vec<_> = [&b"a\xFF"[..], b"a\xC3"]
+    ///     .iter()
+    ///     .map(|input| decode(input).unwrap_err().kind())
+    ///     .collect();
+    /// assert_eq!(kinds, [DecodeErrorKind::Invalid, DecodeErrorKind::Incomplete]);
+    /// ```
+    pub fn kind(&self) -> DecodeErrorKind {
+        match self {
+            DecodeError::Invalid { .. } => DecodeErrorKind::Invalid,
+            DecodeError::Incomplete { .. } => DecodeErrorKind::Incomplete,
+        }
+    }
+
+    /// Classify why the sequence is invalid, or `None` for `Incomplete`.
+    ///
+    /// This looks at `invalid_sequence` and, since an invalid sequence can be a single
+    /// lead byte, at the first byte of `remaining_input`.
+    ///
+    /// ```
+    /// use utf8_zero::{decode, InvalidReason};
+    ///
+    /// let reason = |input| decode(input).unwrap_err().invalid_reason();
+    /// assert_eq!(reason(b"\xC0\x80"), Some(InvalidReason::Overlong));
+    /// assert_eq!(reason(b"\xED\xA0\x80"), Some(InvalidReason::Surrogate));
+    /// assert_eq!(reason(b"a\xC3"), None);
+    /// ```
+    pub fn invalid_reason(&self) -> Option<InvalidReason> {
+        let (invalid_sequence, remaining_input) = match *self {
+            DecodeError::Invalid {
+                invalid_sequence,
+                remaining_input,
+                ..
+            } => (invalid_sequence, remaining_input),
+            DecodeError::Incomplete { .. } => return None,
+        };
+        let lead = *invalid_sequence.first()?;
+        let next = invalid_sequence
+            .get(1)
+            .or_else(|| remaining_input.first())
+            .copied();
+        Some(match (lead, next) {
+            (0x80..=0xBF, _) => InvalidReason::UnexpectedContinuation,
+            (0xF8..=0xFF, _) => InvalidReason::InvalidByte,
+            (0xF5..=0xF7, _) | (0xF4, Some(0x90..=0xBF)) => InvalidReason::OutOfRange,
+            (0xED, Some(0xA0..=0xBF)) => InvalidReason::Surrogate,
+            _ if overlong_len(&[lead, next.unwrap_or(0)]).is_some() => InvalidReason::Overlong,
+            _ => InvalidReason::UnexpectedEnd,
+        })
+    }
+
+    /// The valid text before the error, for either variant.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// assert_eq!(decode(b"ab\xFFcd").unwrap_err().valid_prefix(), "ab");
+    /// ```
+    pub fn valid_prefix(&self) -> &'a str {
+        match *self {
+            DecodeError::Invalid { valid_prefix, .. }
+            | DecodeError::Incomplete { valid_prefix, .. } => valid_prefix,
+        }
+    }
+
+    /// The bytes after the error that have not been decoded yet: `remaining_input` for
+    /// `Invalid`, and empty for `Incomplete`.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// assert_eq!(decode(b"ab\xFFcd").unwrap_err().remaining(), b"cd");
+    /// assert_eq!(decode(b"ab\xC3").unwrap_err().remaining(), b"");
+    /// ```
+    pub fn remaining(&self) -> &'a [u8] {
+        match *self {
+            DecodeError::Invalid {
+                remaining_input, ..
+            } => remaining_input,
+            DecodeError::Incomplete { .. } => &[],
+        }
+    }
+
+    /// The invalid bytes for `Invalid`, or `None` for `Incomplete`.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// assert_eq!(decode(b"ab\xFFcd").unwrap_err().invalid_sequence(), Some(&b"\xFF"[..]));
+    /// assert_eq!(decode(b"ab\xC3").unwrap_err().invalid_sequence(), None);
+    /// ```
+    pub fn invalid_sequence(&self) -> Option<&'a [u8]> {
+        match *self {
+            DecodeError::Invalid {
+                invalid_sequence, ..
+            } => Some(invalid_sequence),
+            DecodeError::Incomplete { .. } => None,
+        }
+    }
+
+    /// The byte offset in the decoded input where the error starts: the length of
+    /// `valid_prefix`.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// assert_eq!(decode(b"ab\xFFcd").unwrap_err().error_offset(), 2);
+    /// assert_eq!(decode(b"abc\xE2\x82").unwrap_err().error_offset(), 3);
+    /// ```
+    pub fn error_offset(&self) -> usize {
+        self.valid_prefix().len()
+    }
+
+    /// The length of `invalid_sequence` for `Invalid`, or `None` for `Incomplete`.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// assert_eq!(decode(b"ab\xF0\x9F!").unwrap_err().invalid_len(), Some(2));
+    /// assert_eq!(decode(b"ab\xF0\x9F").unwrap_err().invalid_len(), None);
+    /// ```
+    pub fn invalid_len(&self) -> Option<usize> {
+        self.invalid_sequence().map(<[u8]>::len)
+    }
+
+    /// The offset in the decoded input where decoding can resume after this error.
+    ///
+    /// For `Invalid` this is where `remaining_input` starts, i.e.
+    /// `valid_prefix.len() + invalid_sequence.len()`. For `Incomplete` it is the length of
+    /// the input, since nothing after the incomplete sequence is left to decode.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// let input = b"ab\xFFcd";
+    /// let offset = decode(input).unwrap_err().next_valid_offset();
+    /// assert_eq!(offset, 3);
+    /// assert_eq!(decode(&input[offset..]).unwrap(), "cd");
+    /// ```
+    pub fn next_valid_offset(&self) -> usize {
+        match *self {
+            DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                ..
+            } => valid_prefix.len() + invalid_sequence.len(),
+            DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            } => valid_prefix.len() + incomplete_suffix.buffer_len as usize,
+        }
+    }
+}
+
 impl<'a> fmt::Display for DecodeError<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -91,6 +413,9 @@ impl<'a> std::error::Error for DecodeError<'a> {}
 /// An incomplete byte sequence for a multi-byte UTF-8 code point.
 ///
 /// Feed more bytes via [`try_complete()`](Incomplete::try_complete) to finish decoding.
+///
+/// Two values compare equal, and hash the same, if their occupied bytes,
+/// `buffer[..buffer_len]`, are equal; whatever is left in the rest of `buffer` is ignored.
 #[derive(Debug, Copy, Clone)]
 pub struct Incomplete {
     /// Internal buffer holding the incomplete bytes (up to 4).
@@ -99,6 +424,27 @@ pub struct Incomplete {
     pub buffer_len: u8,
 }
 
+impl PartialEq for Incomplete {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for Incomplete {}
+
+impl core::hash::Hash for Incomplete {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
+impl Default for Incomplete {
+    /// Same as [`Incomplete::empty()`].
+    fn default() -> Self {
+        Incomplete::empty()
+    }
+}
+
 /// Decode a byte slice as UTF-8, returning the valid prefix on error.
 ///
 /// Unlike [`std::str::from_utf8()`], this distinguishes between invalid and
@@ -128,32 +474,1009 @@ pub struct Incomplete {
 ///     _ => unreachable!(),
 /// }
 /// ```
+#[inline]
 pub fn decode(input: &[u8]) -> Result<&str, DecodeError<'_>> {
-    let error = match str::from_utf8(input) {
-        Ok(valid) => return Ok(valid),
-        Err(error) => error,
-    };
+    decode_nopanic(input)
+}
 
-    // FIXME: separate function from here to guide inlining?
-    let (valid, after_valid) = input.split_at(error.valid_up_to());
+/// Returns `true` if `input` is entirely valid UTF-8.
+///
+/// Equivalent to `decode(input).is_ok()`, but stops at the first error without building
+/// a [`DecodeError`]. A trailing incomplete sequence makes the input invalid.
+///
+/// ```
+/// assert!(utf8_zero::validate("caf\u{E9}".as_bytes()));
+/// assert!(!utf8_zero::validate(b"caf\xC3"));
+/// ```
+#[inline]
+pub fn validate(input: &[u8]) -> bool {
+    str::from_utf8(input).is_ok()
+}
+
+/// Returns the offset of the first byte that is not part of valid UTF-8, or `None` if
+/// all of `input` is valid.
+///
+/// Invalid and incomplete sequences are treated alike: the offset is the length of the
+/// error's `valid_prefix`, like `Utf8Error::valid_up_to()`.
+///
+/// ```
+/// use utf8_zero::first_invalid_offset;
+///
+/// assert_eq!(first_invalid_offset(b"abc"), None);
+/// assert_eq!(first_invalid_offset(b"ab\xFFc"), Some(2));
+/// assert_eq!(first_invalid_offset(b"ab\xE2\x82"), Some(2));
+/// ```
+pub fn first_invalid_offset(input: &[u8]) -> Option<usize> {
+    decode(input).err().map(|error| error.error_offset())
+}
+
+/// Same as [`decode()`], with a guarantee that it never panics and never allocates.
+///
+/// Suitable for restricted contexts such as signal handlers. This is the implementation
+/// of [`decode()`], so the two always agree; the guarantee is spelled out here. The
+/// invariants it relies on (offsets reported by `str::from_utf8` lying within the input,
+/// an incomplete suffix being at most 3 bytes) are checked instead of assumed. Should
+/// one ever fail, the rest of the input is reported as `DecodeError::Invalid` rather
+/// than panicking.
+///
+/// ```
+/// use utf8_zero::{decode, decode_nopanic};
+///
+/// let input = b"caf\xC3\xA9 \xFF \xF0\x9F";
+/// assert_eq!(
+///     format!("{:?}", decode_nopanic(input)),
+///     format!("{:?}", decode(input)),
+/// );
+/// ```
+pub fn decode_nopanic(input: &[u8]) -> Result<&str, DecodeError<'_>> {
+    match str::from_utf8(input) {
+        Ok(valid) => Ok(valid),
+        Err(error) => Err(decode_error(input, error.valid_up_to(), error.error_len())),
+    }
+}
+
+/// Build the error for `input`, given what a validator reported the way `Utf8Error` does.
+fn decode_error(input: &[u8], valid_up_to: usize, error_len: Option<usize>) -> DecodeError<'_> {
+    let (valid, after_valid) = match (input.get(..valid_up_to), input.get(valid_up_to..)) {
+        (Some(valid), Some(after_valid)) => (valid, after_valid),
+        _ => {
+            return DecodeError::Invalid {
+                valid_prefix: "",
+                invalid_sequence: input,
+                remaining_input: &[],
+            }
+        }
+    };
     let valid = unsafe { str::from_utf8_unchecked(valid) };
 
-    match error.error_len() {
-        Some(invalid_sequence_length) => {
-            let (invalid, rest) = after_valid.split_at(invalid_sequence_length);
-            Err(DecodeError::Invalid {
+    let invalid_sequence_length = match error_len {
+        None if after_valid.len() < 4 => {
+            let mut incomplete = Incomplete::empty();
+            for (slot, &byte) in incomplete.buffer.iter_mut().zip(after_valid) {
+                *slot = byte;
+            }
+            incomplete.buffer_len = after_valid.len() as u8;
+            return DecodeError::Incomplete {
                 valid_prefix: valid,
-                invalid_sequence: invalid,
-                remaining_input: rest,
+                incomplete_suffix: incomplete,
+            };
+        }
+        None => after_valid.len(),
+        Some(invalid_sequence_length) => invalid_sequence_length,
+    };
+    let (invalid, rest) = match (
+        after_valid.get(..invalid_sequence_length),
+        after_valid.get(invalid_sequence_length..),
+    ) {
+        (Some(invalid), Some(rest)) => (invalid, rest),
+        _ => (after_valid, &[][..]),
+    };
+    DecodeError::Invalid {
+        valid_prefix: valid,
+        invalid_sequence: invalid,
+        remaining_input: rest,
+    }
+}
+
+/// Decode `input` from `resume_byte` on, resuming from a previously validated position.
+///
+/// `resume_byte` must be a code point boundary, and everything before it is assumed
+/// to have been validated by an earlier run. A safe value is the `valid_prefix.len()`
+/// (the `valid_up_to` position) of a prior [`decode()`] of the same input, or any
+/// checkpoint taken from it. Only `input[resume_byte..]` is scanned.
+///
+/// The returned string and error cover `input[resume_byte..]`, so their offsets are
+/// relative to `resume_byte`: add it to `valid_prefix.len()` to get the absolute offset
+/// of an error in `input`. Reporting absolute offsets would make `valid_prefix` span
+/// `input[..resume_byte]` too, and as a `&str` that means either re-validating the
+/// bytes this function exists to skip, or trusting the checkpoint with unchecked
+/// conversion, which is not sound for a safe function. Hence also the name, which says
+/// where decoding starts, rather than `decode_resume_at`.
+///
+/// ```
+/// use utf8_zero::{decode_from, DecodeError};
+///
+/// let input = b"checkpoint \xE2\x82\xAC then \xFF";
+/// match decode_from(input, 11) {
+///     Err(DecodeError::Invalid { valid_prefix, .. }) => {
+///         assert_eq!(valid_prefix, "\u{20AC} then ");
+///         assert_eq!(11 + valid_prefix.len(), 20);
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if `resume_byte > input.len()`. In debug builds, also panics if
+/// `resume_byte` points at a UTF-8 continuation byte.
+pub fn decode_from(input: &[u8], resume_byte: usize) -> Result<&str, DecodeError<'_>> {
+    let rest = &input[resume_byte..];
+    debug_assert!(
+        rest.first().is_none_or(|&b| b & 0xC0 != 0x80),
+        "resume_byte {} is not a code point boundary",
+        resume_byte
+    );
+    decode(rest)
+}
+
+/// Decode `input` and count the code points for which `pred` returns `true`.
+///
+/// Returns the count together with the decoded string. Nothing is counted if the
+/// input is not entirely valid UTF-8.
+///
+/// ```
+/// use utf8_zero::decode_count_matching;
+///
+/// let (controls, s) = decode_count_matching(b"a\tb\x07c", char::is_control).unwrap();
+/// assert_eq!(controls, 2);
+/// assert_eq!(s, "a\tb\x07c");
+/// ```
+pub fn decode_count_matching<F: FnMut(char) -> bool>(
+    input: &[u8],
+    mut pred: F,
+) -> Result<(usize, &str), DecodeError<'_>> {
+    let s = decode(input)?;
+    Ok((s.chars().filter(|&c| pred(c)).count(), s))
+}
+
+/// Decode `input`, calling `f` with each code point in order.
+///
+/// This feeds a per-character state machine, such as a rolling hash, without building a
+/// string. On error, `f` has been called for every code point of the error's
+/// `valid_prefix` and no others.
+///
+/// ```
+/// use utf8_zero::decode_with_rolling;
+///
+/// let mut hash = 0u32;
+/// decode_with_rolling("h\u{E9}".as_bytes(), |c| hash = hash.rotate_left(5) ^ c as u32).unwrap();
+/// assert_eq!(hash, ('h' as u32).rotate_left(5) ^ 0xE9);
+/// ```
+pub fn decode_with_rolling<F: FnMut(char)>(input: &[u8], f: F) -> Result<(), DecodeError<'_>> {
+    let (valid, result) = match decode(input) {
+        Ok(s) => (s, Ok(())),
+        Err(error) => match error {
+            DecodeError::Invalid { valid_prefix, .. }
+            | DecodeError::Incomplete { valid_prefix, .. } => (valid_prefix, Err(error)),
+        },
+    };
+    valid.chars().for_each(f);
+    result
+}
+
+/// Decode one chunk of a stream, carrying incomplete code points across calls in `state`.
+///
+/// Any bytes buffered in `state` are completed from the front of `input` first, then the
+/// rest is decoded and the valid parts are passed to `push`. An incomplete sequence at the
+/// end of `input` is stashed in `state` for the next call instead of being reported.
+///
+/// On `DecodeError::Invalid` the `valid_prefix` has already been passed to `push`; call
+/// again with `remaining_input` to keep going. If the bytes carried in `state` turn out
+/// to be invalid, the error's `invalid_sequence` only holds the part of the sequence that
+/// was taken from `input` (possibly empty).
+///
+/// ```
+/// use utf8_zero::{decode_stateful, Incomplete};
+///
+/// let mut state = Incomplete::empty();
+/// let mut output = String::new();
+/// decode_stateful(&mut state, b"caf\xC3", |s| output.push_str(s)).unwrap();
+/// decode_stateful(&mut state, b"\xA9!", |s| output.push_str(s)).unwrap();
+/// assert_eq!(output, "caf\u{E9}!");
+/// assert!(state.is_empty());
+/// ```
+pub fn decode_stateful<'a>(
+    state: &mut Incomplete,
+    mut input: &'a [u8],
+    mut push: impl FnMut(&str),
+) -> Result<(), DecodeError<'a>> {
+    if !state.is_empty() {
+        let (consumed, opt_result) = state.try_complete_offsets(input);
+        let (taken, rest) = input.split_at(consumed);
+        match opt_result {
+            None => return Ok(()),
+            Some(Ok(())) => push(unsafe { str::from_utf8_unchecked(state.take_buffer()) }),
+            Some(Err(())) => {
+                state.take_buffer();
+                return Err(DecodeError::Invalid {
+                    valid_prefix: "",
+                    invalid_sequence: taken,
+                    remaining_input: rest,
+                });
+            }
+        }
+        input = rest;
+    }
+    match decode(input) {
+        Ok(s) => {
+            push(s);
+            Ok(())
+        }
+        Err(DecodeError::Incomplete {
+            valid_prefix,
+            incomplete_suffix,
+        }) => {
+            push(valid_prefix);
+            *state = incomplete_suffix;
+            Ok(())
+        }
+        Err(error @ DecodeError::Invalid { valid_prefix, .. }) => {
+            push(valid_prefix);
+            Err(error)
+        }
+    }
+}
+
+/// Error from [`decode_slices()`], locating the failure within the slices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SliceError {
+    /// Index of the slice in which the error is located.
+    pub slice_index: usize,
+    /// Byte offset of the error within that slice.
+    pub local_offset: usize,
+    /// Whether the input was invalid or ended with an incomplete code point.
+    pub kind: DecodeErrorKind,
+}
+
+impl fmt::Display for SliceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            DecodeErrorKind::Invalid => "invalid",
+            DecodeErrorKind::Incomplete => "incomplete",
+        };
+        write!(
+            f,
+            "found {} byte sequence in slice {} at offset {}",
+            kind, self.slice_index, self.local_offset
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SliceError {}
+
+/// Validate the concatenation of `slices`, as for a vectored write, without copying.
+///
+/// Code points split across slice boundaries are pieced together. An invalid sequence
+/// is located at its first byte, which may be in an earlier slice than the byte that
+/// made it invalid. If the input ends with an incomplete code point, the error points at
+/// the end of the last slice, where more bytes were expected.
+///
+/// ```
+/// use utf8_zero::{decode_slices, DecodeErrorKind, SliceError};
+///
+/// assert!(decode_slices(&[b"caf", b"\xC3", b"\xA9"]).is_ok());
+/// assert_eq!(
+///     decode_slices(&[b"ok", b"o\xFFk"]),
+///     Err(SliceError { slice_index: 1, local_offset: 1, kind: DecodeErrorKind::Invalid })
+/// );
+/// ```
+pub fn decode_slices(slices: &[&[u8]]) -> Result<(), SliceError> {
+    let mut incomplete = Incomplete::empty();
+    // Where the bytes buffered in `incomplete` started.
+    let mut incomplete_start = (0, 0);
+    for (slice_index, &slice) in slices.iter().enumerate() {
+        let mut input = slice;
+        if !incomplete.is_empty() {
+            match incomplete.try_complete(input) {
+                None => continue,
+                Some((Ok(_), remaining)) => input = remaining,
+                Some((Err(_), _)) => {
+                    let (slice_index, local_offset) = incomplete_start;
+                    return Err(SliceError {
+                        slice_index,
+                        local_offset,
+                        kind: DecodeErrorKind::Invalid,
+                    });
+                }
+            }
+        }
+        let input_offset = slice.len() - input.len();
+        match decode(input) {
+            Ok(_) => {}
+            Err(DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            }) => {
+                incomplete = incomplete_suffix;
+                incomplete_start = (slice_index, input_offset + valid_prefix.len());
+            }
+            Err(DecodeError::Invalid { valid_prefix, .. }) => {
+                return Err(SliceError {
+                    slice_index,
+                    local_offset: input_offset + valid_prefix.len(),
+                    kind: DecodeErrorKind::Invalid,
+                })
+            }
+        }
+    }
+    if incomplete.is_empty() {
+        Ok(())
+    } else {
+        Err(SliceError {
+            slice_index: slices.len() - 1,
+            local_offset: slices[slices.len() - 1].len(),
+            kind: DecodeErrorKind::Incomplete,
+        })
+    }
+}
+
+/// Decode the first line of `input`, returning it along with the raw bytes after it.
+///
+/// The line ends at the first `\n`, which is not included in the returned string and is
+/// skipped in the returned remainder. Only the line itself is validated: the remaining
+/// bytes are passed through untouched, whether or not they are valid UTF-8. If there is
+/// no newline, the whole input is decoded and the remainder is empty.
+///
+/// ```
+/// use utf8_zero::decode_first_line;
+///
+/// let (line, rest) = decode_first_line(b"Content-Type: text/plain\n\x89PNG\xFF").unwrap();
+/// assert_eq!(line, "Content-Type: text/plain");
+/// assert_eq!(rest, b"\x89PNG\xFF");
+/// ```
+///
+/// A code point cut short by the newline is reported as `DecodeError::Invalid`, and only
+/// an input without a newline can yield `DecodeError::Incomplete`.
+pub fn decode_first_line(input: &[u8]) -> Result<(&str, &[u8]), DecodeError<'_>> {
+    match input.iter().position(|&b| b == b'\n') {
+        Some(newline) => decode_until(input, newline).map(|line| (line, &input[newline + 1..])),
+        None => decode(input).map(|line| (line, &input[input.len()..])),
+    }
+}
+
+/// Decode the first `n_chars` code points of `input`.
+///
+/// Only as much of `input` as is needed is validated, which makes this cheap for
+/// previewing the start of a large buffer. If `input` holds fewer than `n_chars` code
+/// points, all of it is decoded. Errors within the head are reported as by
+/// [`decode()`].
+///
+/// ```
+/// use utf8_zero::decode_head;
+///
+/// assert_eq!(decode_head("Привет, мир".as_bytes(), 6).unwrap(), "Привет");
+/// // The invalid bytes after the head are never looked at.
+/// assert_eq!(decode_head(b"abc\xFF\xFF", 3).unwrap(), "abc");
+/// ```
+pub fn decode_head(input: &[u8], n_chars: usize) -> Result<&str, DecodeError<'_>> {
+    let end = input
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b & 0xC0 != 0x80)
+        .nth(n_chars)
+        .map_or(input.len(), |(i, _)| i);
+    decode_until(input, end)
+}
+
+/// Decode at most `max_bytes` bytes from the start of `input`.
+///
+/// The result is cut on a code point boundary: if the limit falls inside a multi-byte
+/// code point, it is moved back to the start of that code point instead of reporting an
+/// error. Only bytes before the limit are validated. Errors before the limit are
+/// reported as by [`decode()`].
+///
+/// ```
+/// use utf8_zero::decode_up_to;
+///
+/// // "€" is 3 bytes, so a limit of 4 stops after "ab".
+/// assert_eq!(decode_up_to("ab€cd".as_bytes(), 4).unwrap(), "ab");
+/// assert_eq!(decode_up_to("ab€cd".as_bytes(), 5).unwrap(), "ab€");
+/// assert_eq!(decode_up_to(b"abc\xFF", 3).unwrap(), "abc");
+/// ```
+pub fn decode_up_to(input: &[u8], max_bytes: usize) -> Result<&str, DecodeError<'_>> {
+    if max_bytes >= input.len() {
+        return decode(input);
+    }
+    // Back up over the continuation bytes of a code point cut by the limit.
+    let mut end = max_bytes;
+    while end > 0 && max_bytes - end < 3 && input[end] & 0xC0 == 0x80 {
+        end -= 1;
+    }
+    decode_until(input, end)
+}
+
+/// Returns the length of the first `n` lines of `input`, including their `\n`.
+///
+/// `input[..len]` is validated, so it is known to be valid UTF-8. If there are fewer
+/// than `n` newlines, the whole input is validated and its length returned. Errors
+/// within the lines are reported as by [`decode()`].
+///
+/// ```
+/// use utf8_zero::byte_len_of_lines;
+///
+/// let input = "één\ntwee\ndrie".as_bytes();
+/// assert_eq!(byte_len_of_lines(input, 1).unwrap(), 6);
+/// assert_eq!(byte_len_of_lines(input, 2).unwrap(), 11);
+/// assert_eq!(byte_len_of_lines(input, 5).unwrap(), input.len());
+/// ```
+pub fn byte_len_of_lines(input: &[u8], n: usize) -> Result<usize, DecodeError<'_>> {
+    if n == 0 {
+        return Ok(0);
+    }
+    match input
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'\n')
+        .nth(n - 1)
+    {
+        Some((newline, _)) => decode_until(input, newline + 1).map(str::len),
+        None => decode(input).map(str::len),
+    }
+}
+
+/// Decode `input[..end]`, reporting errors as [`decode()`] would for all of `input`.
+///
+/// `end` must be `input.len()` or the index of a byte that can't continue a sequence
+/// (such as an ASCII byte or a lead byte), so that a code point cut short at `end` is
+/// invalid rather than incomplete.
+fn decode_until(input: &[u8], end: usize) -> Result<&str, DecodeError<'_>> {
+    let head = &input[..end];
+    match decode(head) {
+        Ok(head) => Ok(head),
+        Err(DecodeError::Incomplete { valid_prefix, .. }) if end < input.len() => {
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence: &head[valid_prefix.len()..],
+                remaining_input: &input[end..],
+            })
+        }
+        Err(DecodeError::Invalid {
+            valid_prefix,
+            invalid_sequence,
+            ..
+        }) => Err(DecodeError::Invalid {
+            valid_prefix,
+            invalid_sequence,
+            remaining_input: &input[valid_prefix.len() + invalid_sequence.len()..],
+        }),
+        Err(error) => Err(error),
+    }
+}
+
+/// Iterate over the byte offsets at which code points start in `input`.
+///
+/// Yields `Ok(offset)` for the start of each valid code point and `Err(offset)` for the
+/// start of each invalid sequence (including an incomplete sequence at the end), in the
+/// same places where lossy decoding would insert U+FFFD. Does not allocate.
+///
+/// ```
+/// use utf8_zero::code_point_offsets;
+///
+/// let offsets: Vec<_> = code_point_offsets(b"a\xC3\xA9\xFFz").collect();
+/// assert_eq!(offsets, [Ok(0), Ok(1), Err(3), Ok(4)]);
+/// ```
+pub fn code_point_offsets(input: &[u8]) -> impl Iterator<Item = Result<usize, usize>> + '_ {
+    let mut rest = input;
+    let mut run_start = 0;
+    let mut chars = "".char_indices();
+    let mut pending_error = None;
+    core::iter::from_fn(move || loop {
+        if let Some((i, _)) = chars.next() {
+            return Some(Ok(run_start + i));
+        }
+        if let Some(offset) = pending_error.take() {
+            return Some(Err(offset));
+        }
+        if rest.is_empty() {
+            return None;
+        }
+        let (valid, error_len) = match decode(rest) {
+            Ok(valid) => (valid, 0),
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                ..
+            }) => (valid_prefix, invalid_sequence.len()),
+            Err(DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            }) => (valid_prefix, incomplete_suffix.buffer_len as usize),
+        };
+        run_start = input.len() - rest.len();
+        chars = valid.char_indices();
+        if error_len > 0 {
+            pending_error = Some(run_start + valid.len());
+        }
+        rest = &rest[valid.len() + error_len..];
+    })
+}
+
+/// Returns how many trailing bytes of `input` form an incomplete code point.
+///
+/// This is 1 to 3 if `input` ends with the start of a multi-byte sequence that more
+/// bytes could complete, and 0 if it ends with a complete code point or with a byte
+/// that can never be completed. Only the end of `input` is inspected, so everything
+/// before `input.len() - trailing_incomplete_len(input)` can be decoded right away
+/// while the rest is held for the next read.
+///
+/// ```
+/// use utf8_zero::trailing_incomplete_len;
+///
+/// assert_eq!(trailing_incomplete_len(b"abc"), 0);
+/// assert_eq!(trailing_incomplete_len(b"abc\xE2\x82"), 2);
+/// assert_eq!(trailing_incomplete_len(b"abc\xFF"), 0);
+/// ```
+pub fn trailing_incomplete_len(input: &[u8]) -> usize {
+    let tail = &input[input.len().saturating_sub(3)..];
+    let lead = match tail.iter().rposition(|&b| b & 0xC0 != 0x80) {
+        Some(lead) => lead,
+        None => return 0,
+    };
+    match str::from_utf8(&tail[lead..]) {
+        Err(error) if error.valid_up_to() == 0 && error.error_len().is_none() => tail.len() - lead,
+        _ => 0,
+    }
+}
+
+/// The UTF-8 encoding of U+FEFF, used as a byte order mark.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Returns `input` without a leading UTF-8 byte order mark, `EF BB BF`, if it has one.
+///
+/// Only a BOM at the very start is removed; one later on is U+FEFF content.
+///
+/// ```
+/// use utf8_zero::skip_bom;
+///
+/// assert_eq!(skip_bom(b"\xEF\xBB\xBFtext"), b"text");
+/// assert_eq!(skip_bom(b"text"), b"text");
+/// ```
+#[inline]
+pub fn skip_bom(input: &[u8]) -> &[u8] {
+    input.strip_prefix(BOM).unwrap_or(input)
+}
+
+/// Decode the first code point of `input`, for tokenizers that advance one at a time.
+///
+/// Returns the code point and its length in bytes, or `None` if `input` is empty. Only
+/// the first code point is validated. Errors are reported as by [`decode()`], with an
+/// empty `valid_prefix`: `DecodeError::Incomplete` if `input` ends in the middle of the
+/// code point, `DecodeError::Invalid` if it starts with an invalid sequence.
+///
+/// ```
+/// use utf8_zero::next_code_point;
+///
+/// let mut input = "a\u{E9}".as_bytes();
+/// let mut chars = Vec::new();
+/// while let Some((c, len)) = next_code_point(input).unwrap() {
+///     chars.push(c);
+///     input = &input[len..];
+/// }
+/// assert_eq!(chars, ['a', '\u{E9}']);
+/// assert!(next_code_point(b"\xE2\x82").unwrap_err().is_incomplete());
+/// ```
+pub fn next_code_point(input: &[u8]) -> Result<Option<(char, usize)>, DecodeError<'_>> {
+    if input.is_empty() {
+        return Ok(None);
+    }
+    let head = &input[..cmp::min(4, input.len())];
+    let valid = match decode(head) {
+        Ok(valid) => valid,
+        Err(DecodeError::Invalid {
+            valid_prefix: "",
+            invalid_sequence,
+            ..
+        }) => {
+            return Err(DecodeError::Invalid {
+                valid_prefix: "",
+                invalid_sequence,
+                remaining_input: &input[invalid_sequence.len()..],
             })
         }
-        None => Err(DecodeError::Incomplete {
-            valid_prefix: valid,
-            incomplete_suffix: Incomplete::new(after_valid),
+        Err(
+            error @ DecodeError::Incomplete {
+                valid_prefix: "", ..
+            },
+        ) => return Err(error),
+        Err(error) => error.valid_prefix(),
+    };
+    let c = valid.chars().next().unwrap();
+    Ok(Some((c, c.len_utf8())))
+}
+
+/// Decode the last code point of `input`, for parsers that work from the end backward.
+///
+/// Returns the code point and its length in bytes, so that it starts at
+/// `input.len() - len`, or `None` if `input` is empty. Only the last few bytes are
+/// inspected; the rest of `input` is not validated.
+///
+/// If `input` ends with the start of a multi-byte sequence, the error is
+/// `DecodeError::Incomplete` for those bytes. Otherwise, if the end is not a valid code
+/// point (such as a continuation byte without its lead), the error is
+/// `DecodeError::Invalid` with just the last byte as `invalid_sequence`, so that a lossy
+/// caller can drop that byte and call again. In both cases `valid_prefix` is empty.
+///
+/// ```
+/// use utf8_zero::last_char;
+///
+/// assert_eq!(last_char("ab\u{20AC}".as_bytes()).unwrap(), Some(('\u{20AC}', 3)));
+/// assert_eq!(last_char(b"").unwrap(), None);
+/// assert!(last_char(b"ab\x82").is_err());
+/// ```
+pub fn last_char(input: &[u8]) -> Result<Option<(char, usize)>, DecodeError<'_>> {
+    let tail = &input[input.len().saturating_sub(4)..];
+    let start = match tail.iter().rposition(|&b| b & 0xC0 != 0x80) {
+        Some(start) => start,
+        None if tail.is_empty() => return Ok(None),
+        None => tail.len() - 1,
+    };
+    let tail = &tail[start..];
+    match decode(tail) {
+        Ok(s) => {
+            let mut chars = s.chars();
+            let c = chars.next().unwrap();
+            debug_assert!(chars.next().is_none());
+            Ok(Some((c, tail.len())))
+        }
+        Err(
+            error @ DecodeError::Incomplete {
+                valid_prefix: "", ..
+            },
+        ) => Err(error),
+        Err(_) => Err(DecodeError::Invalid {
+            valid_prefix: "",
+            invalid_sequence: &tail[tail.len() - 1..],
+            remaining_input: &[],
         }),
     }
 }
 
+/// Same as [`decode()`], plus how many more bytes an incomplete result needs.
+///
+/// The hint is `Some(n)` when the result is `DecodeError::Incomplete`, where `n` is the
+/// number of bytes still missing from the buffered code point according to its lead
+/// byte. It is `None` for valid input and for `DecodeError::Invalid`.
+///
+/// ```
+/// use utf8_zero::decode_with_hint;
+///
+/// // U+1F30D is 4 bytes long, so one more is needed.
+/// let (result, hint) = decode_with_hint(b"Hello \xF0\x9F\x8C");
+/// assert!(result.is_err());
+/// assert_eq!(hint, Some(1));
+/// ```
+pub fn decode_with_hint(input: &[u8]) -> (Result<&str, DecodeError<'_>>, Option<usize>) {
+    let result = decode(input);
+    let hint = match result {
+        Err(DecodeError::Incomplete {
+            incomplete_suffix, ..
+        }) => sequence_len(incomplete_suffix.buffer[0])
+            .map(|len| len.saturating_sub(incomplete_suffix.buffer_len as usize)),
+        _ => None,
+    };
+    (result, hint)
+}
+
+/// Decode the part of `input` that can be decoded now, and say how many bytes to carry.
+///
+/// The count is [`trailing_incomplete_len(input)`](trailing_incomplete_len): 1 to 3 if
+/// `input` ends with an incomplete code point, which should be copied to the front of
+/// the next buffer before appending more input, and 0 otherwise. The result is the text
+/// before those bytes, or the first invalid sequence found in it. In that case the
+/// invalid bytes are a subslice of `input`; use [`decode()`] for the full error.
+///
+/// ```
+/// use utf8_zero::decode_carry;
+///
+/// assert_eq!(decode_carry(b"abc\xE2\x82"), (Ok("abc"), 2));
+/// assert_eq!(decode_carry(b"abc"), (Ok("abc"), 0));
+/// assert_eq!(decode_carry(b"a\xFFc"), (Err(&b"\xFF"[..]), 0));
+/// ```
+pub fn decode_carry(input: &[u8]) -> (Result<&str, &[u8]>, usize) {
+    let carry = trailing_incomplete_len(input);
+    // Before the carry, an incomplete sequence is as much an error as an invalid one.
+    let result = match utf8_chunks(&input[..input.len() - carry]).next() {
+        None => Ok(""),
+        Some(Utf8Chunk { valid, invalid: [] }) => Ok(valid),
+        Some(chunk) => Err(chunk.invalid),
+    };
+    (result, carry)
+}
+
+/// Validate `input` and iterate over its lines.
+///
+/// Lines are split on `\n`, with a trailing `\r` stripped, exactly like
+/// [`str::lines()`]. The whole input is validated before the iterator is returned, so an
+/// error is reported before any line is yielded. Does not allocate.
+///
+/// ```
+/// let lines: Vec<_> = utf8_zero::lines(b"one\r\ntwo\nthree").unwrap().collect();
+/// assert_eq!(lines, ["one", "two", "three"]);
+/// ```
+pub fn lines(input: &[u8]) -> Result<impl Iterator<Item = &str>, DecodeError<'_>> {
+    Ok(decode(input)?.lines())
+}
+
+/// Count the U+FFFD replacement characters that lossy decoding of `input` would insert.
+///
+/// This is one per maximal invalid subpart (not one per invalid byte) plus one for a
+/// trailing incomplete sequence, exactly as many as `String::from_utf8_lossy` inserts.
+/// Does not allocate.
+///
+/// ```
+/// use utf8_zero::count_replacements;
+///
+/// assert_eq!(count_replacements(b"ok"), 0);
+/// assert_eq!(count_replacements(b"\xF0\x9F\xFF!\xE2\x82"), 3);
+/// ```
+pub fn count_replacements(mut input: &[u8]) -> usize {
+    let mut count = 0;
+    loop {
+        match decode(input) {
+            Ok(_) => return count,
+            Err(DecodeError::Incomplete { .. }) => return count + 1,
+            Err(DecodeError::Invalid {
+                remaining_input, ..
+            }) => {
+                count += 1;
+                input = remaining_input;
+            }
+        }
+    }
+}
+
+/// Count the code points in `input`, or return the first error.
+///
+/// The code points are counted while validating, in a single pass over `input`. Does
+/// not allocate.
+///
+/// ```
+/// use utf8_zero::count_code_points;
+///
+/// assert_eq!(count_code_points("aé€🌍".as_bytes()).unwrap(), 4);
+/// assert!(count_code_points(b"a\xFFb").unwrap_err().is_invalid());
+/// ```
+pub fn count_code_points(input: &[u8]) -> Result<usize, DecodeError<'_>> {
+    match dfa::validate_counting(input) {
+        (valid_up_to, None, count) if valid_up_to == input.len() => Ok(count),
+        (valid_up_to, error_len, _) => Err(decode_error(input, valid_up_to, error_len)),
+    }
+}
+
+/// Count the `char`s that lossy decoding of `input` would produce.
+///
+/// This is the code points of the valid parts, plus one for each U+FFFD replacement
+/// character, as counted by [`count_replacements()`]. Like [`count_code_points()`], this
+/// counts while validating, in a single pass. Does not allocate.
+///
+/// ```
+/// use utf8_zero::count_code_points_lossy;
+///
+/// assert_eq!(count_code_points_lossy(b"\xC3\xA9\xF0\x9F\xFF!\xE2\x82"), 5);
+/// ```
+pub fn count_code_points_lossy(mut input: &[u8]) -> usize {
+    let mut count = 0;
+    loop {
+        match dfa::validate_counting(input) {
+            (valid_up_to, None, valid_count) if valid_up_to == input.len() => {
+                return count + valid_count
+            }
+            // An incomplete sequence at the end is one replacement.
+            (_, None, valid_count) => return count + valid_count + 1,
+            (valid_up_to, Some(error_len), valid_count) => {
+                count += valid_count + 1;
+                input = &input[valid_up_to + error_len..];
+            }
+        }
+    }
+}
+
+/// Lossily decode as much of `input` as fits into `out`.
+///
+/// Valid UTF-8 is copied and each invalid sequence, and a trailing incomplete one, is
+/// written as U+FFFD, like `String::from_utf8_lossy`. A code point or replacement
+/// character is never split: decoding stops before the first one that does not fit.
+/// Returns how many bytes of `input` were consumed and how many bytes of `out` were
+/// written, which are always valid UTF-8. Allocates nothing and takes no closure.
+///
+/// Call it again with the rest of `input` until that is empty. An `out` of at least 4
+/// bytes always makes progress, and only about `out.len()` bytes of `input` are looked
+/// at per call, so decoding all of `input` this way takes linear time. To decode a
+/// stream in pieces, hold back the last [`trailing_incomplete_len()`] bytes of each
+/// piece, which would otherwise be replaced.
+///
+/// ```
+/// use utf8_zero::decode_lossy_into;
+///
+/// let input = b"ab\xFF\xE2\x82\xAC";
+/// // U+FFFD takes 3 bytes, so it does not fit after "ab".
+/// let mut out = [0; 4];
+/// assert_eq!(decode_lossy_into(input, &mut out), (2, 2));
+/// assert_eq!(&out[..2], b"ab");
+///
+/// let mut out = [0; 16];
+/// let (consumed, written) = decode_lossy_into(input, &mut out);
+/// assert_eq!(consumed, input.len());
+/// assert_eq!(&out[..written], "ab\u{FFFD}€".as_bytes());
+/// ```
+pub fn decode_lossy_into(input: &[u8], out: &mut [u8]) -> (usize, usize) {
+    let mut consumed = 0;
+    let mut written = 0;
+    while consumed < input.len() {
+        let rest = &input[consumed..];
+        let space = out.len() - written;
+        // Only what can fit needs validating: `space` bytes of text, or the at most 3
+        // bytes of an invalid sequence after it.
+        let window = &rest[..cmp::min(rest.len(), space + 3)];
+        let (valid, error_len) = match decode(window) {
+            Ok(valid) => (valid, 0),
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                ..
+            }) => (valid_prefix, invalid_sequence.len()),
+            Err(DecodeError::Incomplete { valid_prefix, .. }) if window.len() == rest.len() => {
+                (valid_prefix, rest.len() - valid_prefix.len())
+            }
+            // Cut short by the window, so it starts past `space` and can't fit.
+            Err(DecodeError::Incomplete { valid_prefix, .. }) => (valid_prefix, 0),
+        };
+        let mut fits = cmp::min(valid.len(), space);
+        while !valid.is_char_boundary(fits) {
+            fits -= 1;
+        }
+        out[written..written + fits].copy_from_slice(&valid.as_bytes()[..fits]);
+        consumed += fits;
+        written += fits;
+        if fits < valid.len() || error_len == 0 {
+            break;
+        }
+        let replacement = REPLACEMENT_CHARACTER.as_bytes();
+        if out.len() - written < replacement.len() {
+            break;
+        }
+        out[written..written + replacement.len()].copy_from_slice(replacement);
+        consumed += error_len;
+        written += replacement.len();
+    }
+    (consumed, written)
+}
+
+/// Count the overlong (non-shortest form) encodings in `input`.
+///
+/// These are sequences starting with a `C0` or `C1` lead byte, a 3-byte sequence
+/// starting `E0 80..=9F`, or a 4-byte sequence starting `F0 80..=8F`. Each counts once,
+/// however many of its continuation bytes follow. Other invalid sequences are not
+/// counted.
+///
+/// ```
+/// use utf8_zero::count_overlong;
+///
+/// assert_eq!(count_overlong(b"\xC0\x80 \xE0\x80\x80 \xF0\x80\x80\x80 \xFF"), 3);
+/// ```
+pub fn count_overlong(mut input: &[u8]) -> usize {
+    let mut count = 0;
+    while let Err(DecodeError::Invalid {
+        valid_prefix,
+        remaining_input,
+        ..
+    }) = decode(input)
+    {
+        let error_start = &input[valid_prefix.len()..];
+        input = match overlong_len(error_start) {
+            Some(len) => {
+                count += 1;
+                let continuation = error_start[1..]
+                    .iter()
+                    .take(len - 1)
+                    .take_while(|&&b| b & 0xC0 == 0x80)
+                    .count();
+                &error_start[1 + continuation..]
+            }
+            None => remaining_input,
+        };
+    }
+    count
+}
+
+/// If `bytes` starts with an overlong encoding, the length of the sequence it starts.
+fn overlong_len(bytes: &[u8]) -> Option<usize> {
+    match (bytes.first()?, bytes.get(1)) {
+        (0xC0 | 0xC1, _) => Some(2),
+        (0xE0, Some(0x80..=0x9F)) => Some(3),
+        (0xF0, Some(0x80..=0x8F)) => Some(4),
+        _ => None,
+    }
+}
+
+/// Check whether `input` is valid [WTF-8](https://simonsapin.github.io/wtf-8/).
+///
+/// WTF-8 extends UTF-8 so that it can represent potentially ill-formed UTF-16, such as
+/// Windows file names: a lone surrogate code point is encoded as a 3-byte sequence,
+/// `ED A0..=BF 80..=BF`, which UTF-8 forbids. A high surrogate followed by a low one
+/// must still be encoded as the single 4-byte sequence of their supplementary code
+/// point. Valid WTF-8 is therefore not necessarily valid UTF-8.
+///
+/// ```
+/// use utf8_zero::is_valid_wtf8;
+///
+/// assert!(is_valid_wtf8("caf\u{E9}".as_bytes()));
+/// assert!(is_valid_wtf8(b"lone \xED\xA0\xBD"));
+/// assert!(!is_valid_wtf8(b"paired \xED\xA0\xBC\xED\xBC\x8D"));
+/// assert!(!is_valid_wtf8(b"\xFF"));
+/// ```
+pub fn is_valid_wtf8(input: &[u8]) -> bool {
+    decode_wtf8_with(input, |_| {}).is_ok()
+}
+
+/// Split WTF-8 `input` into runs of UTF-8 and lone surrogates, passed to `f` in order.
+/// On invalid input, returns the offset of the first byte that isn't WTF-8.
+fn decode_wtf8_with<F: FnMut(Result<&str, u16>)>(mut input: &[u8], mut f: F) -> Result<(), usize> {
+    let mut offset = 0;
+    loop {
+        let (valid_prefix, rest) = match decode(input) {
+            Ok(s) => {
+                f(Ok(s));
+                return Ok(());
+            }
+            Err(DecodeError::Incomplete { valid_prefix, .. })
+            | Err(DecodeError::Invalid { valid_prefix, .. }) => {
+                (valid_prefix, &input[valid_prefix.len()..])
+            }
+        };
+        offset += valid_prefix.len();
+        let surrogate = match wtf8_surrogate(rest) {
+            // A surrogate pair must have been encoded as one supplementary code point.
+            Some(0xD800..=0xDBFF)
+                if matches!(wtf8_surrogate(&rest[3..]), Some(0xDC00..=0xDFFF)) =>
+            {
+                return Err(offset + 3)
+            }
+            Some(surrogate) => surrogate,
+            None => return Err(offset),
+        };
+        if !valid_prefix.is_empty() {
+            f(Ok(valid_prefix));
+        }
+        f(Err(surrogate));
+        offset += 3;
+        input = &rest[3..];
+    }
+}
+
+/// The surrogate code point encoded at the start of `bytes` as in WTF-8, if any.
+fn wtf8_surrogate(bytes: &[u8]) -> Option<u16> {
+    match *bytes {
+        [0xED, b1 @ 0xA0..=0xBF, b2 @ 0x80..=0xBF, ..] => {
+            Some(0xD000 | u16::from(b1 & 0x3F) << 6 | u16::from(b2 & 0x3F))
+        }
+        _ => None,
+    }
+}
+
+/// The length of the UTF-8 sequence started by `lead`, or `None` if it can't start one.
+fn sequence_len(lead: u8) -> Option<usize> {
+    match lead {
+        0x00..=0x7F => Some(1),
+        0xC2..=0xDF => Some(2),
+        0xE0..=0xEF => Some(3),
+        0xF0..=0xF4 => Some(4),
+        _ => None,
+    }
+}
+
 impl Incomplete {
     /// Create an empty `Incomplete` with no buffered bytes.
     pub fn empty() -> Self {
@@ -168,7 +1491,19 @@ impl Incomplete {
         self.buffer_len == 0
     }
 
+    /// Discard the buffered bytes, leaving `self` empty.
+    ///
+    /// Only `buffer_len` is reset; the contents of `buffer` are left as they are.
+    pub fn clear(&mut self) {
+        self.buffer_len = 0;
+    }
+
     /// Create an `Incomplete` pre-filled with the given bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is longer than 4 bytes. See [`try_new()`](Incomplete::try_new)
+    /// for a version that doesn't.
     pub fn new(bytes: &[u8]) -> Self {
         let mut buffer = [0, 0, 0, 0];
         let len = bytes.len();
@@ -179,6 +1514,92 @@ impl Incomplete {
         }
     }
 
+    /// Create an `Incomplete` pre-filled with the given bytes, or `None` if `bytes` is
+    /// longer than 4 bytes.
+    ///
+    /// ```
+    /// use utf8_zero::Incomplete;
+    ///
+    /// assert!(Incomplete::try_new(b"\xE2\x82").is_some());
+    /// assert!(Incomplete::try_new(&[0; 5]).is_none());
+    /// ```
+    pub fn try_new(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > 4 {
+            return None;
+        }
+        Some(Self::new(bytes))
+    }
+
+    /// Append one byte to the buffer, or return `Err(())` if it already holds 4 bytes.
+    ///
+    /// This is for feeding input a byte at a time. The byte is not validated; call
+    /// [`try_complete()`](Incomplete::try_complete) with an empty slice after each push to
+    /// check whether the buffer now holds a complete (or an invalid) sequence. A pushed
+    /// byte may end an invalid sequence without being part of it, in which case it stays
+    /// buffered, so keep calling `try_complete(&[])` while it returns `Some` and the buffer
+    /// is not empty.
+    ///
+    /// ```
+    /// use utf8_zero::Incomplete;
+    ///
+    /// let mut incomplete = Incomplete::empty();
+    /// for &byte in "\u{E9}".as_bytes() {
+    ///     incomplete.push(byte).unwrap();
+    /// }
+    /// assert!(matches!(incomplete.try_complete(&[]), Some((Ok("\u{E9}"), _))));
+    ///
+    /// incomplete.push(0xC3).unwrap();
+    /// incomplete.push(b'a').unwrap();
+    /// assert!(matches!(incomplete.try_complete(&[]), Some((Err(b"\xC3"), _))));
+    /// assert!(matches!(incomplete.try_complete(&[]), Some((Ok("a"), _))));
+    /// assert!(incomplete.is_empty());
+    /// ```
+    #[allow(clippy::result_unit_err)]
+    pub fn push(&mut self, byte: u8) -> Result<(), ()> {
+        let len = self.buffer_len as usize;
+        if len >= self.buffer.len() {
+            return Err(());
+        }
+        self.buffer[len] = byte;
+        self.buffer_len += 1;
+        Ok(())
+    }
+
+    /// The minimum number of bytes still needed to complete the buffered code point.
+    ///
+    /// This is the sequence length given by the lead byte `buffer[0]`, minus
+    /// `buffer_len`. It is 0 if nothing is buffered or if `buffer[0]` can't start a
+    /// multi-byte sequence, since no amount of input would complete it then.
+    ///
+    /// ```
+    /// use utf8_zero::Incomplete;
+    ///
+    /// assert_eq!(Incomplete::new(b"\xE2").needed(), 2);
+    /// assert_eq!(Incomplete::new(b"\xF0\x9F").needed(), 2);
+    /// ```
+    pub fn needed(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        self.expected_len()
+            .map_or(0, |len| len.saturating_sub(self.buffer_len as usize))
+    }
+
+    /// The total length of the buffered code point according to its lead byte: 2, 3 or
+    /// 4, or `None` if `buffer[0]` is not a lead byte of a multi-byte sequence.
+    ///
+    /// Only `buffer[0]` is read, whatever `buffer_len` is.
+    ///
+    /// ```
+    /// use utf8_zero::Incomplete;
+    ///
+    /// assert_eq!(Incomplete::new(b"\xE2\x82").expected_len(), Some(3));
+    /// assert_eq!(Incomplete::new(b"\x80").expected_len(), None);
+    /// ```
+    pub fn expected_len(&self) -> Option<usize> {
+        sequence_len(self.buffer[0]).filter(|&len| len > 1)
+    }
+
     /// * `None`: still incomplete, call `try_complete` again with more input.
     ///   If no more input is available, this is invalid byte sequence.
     /// * `Some((result, remaining_input))`: We’re done with this `Incomplete`.
@@ -188,19 +1609,66 @@ impl Incomplete {
         &mut self,
         input: &'input [u8],
     ) -> Option<(Result<&str, &[u8]>, &'input [u8])> {
+        let (result, consumed) = self.try_complete_with_consumed(input)?;
+        Some((result, &input[consumed..]))
+    }
+
+    /// Same as [`try_complete()`](Incomplete::try_complete), but returns how many bytes
+    /// of `input` were consumed instead of the remaining input.
+    ///
+    /// When `None` is returned, all of `input` was consumed into the buffer.
+    ///
+    /// ```
+    /// use utf8_zero::Incomplete;
+    ///
+    /// let mut incomplete = Incomplete::new(b"\xE2");
+    /// assert!(incomplete.try_complete_with_consumed(b"\x82").is_none());
+    /// assert!(matches!(
+    ///     incomplete.try_complete_with_consumed(b"\xAC\xFF"),
+    ///     Some((Ok("\u{20AC}"), 1))
+    /// ));
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn try_complete_with_consumed(
+        &mut self,
+        input: &[u8],
+    ) -> Option<(Result<&str, &[u8]>, usize)> {
+        let initial_buffer_len = cmp::min(self.buffer_len as usize, self.buffer.len());
         let (consumed, opt_result) = self.try_complete_offsets(input);
         let result = opt_result?;
-        let remaining_input = &input[consumed..];
-        let result_bytes = self.take_buffer();
+        let result_len = self.buffer_len as usize;
+        let result_bytes = if result_len < initial_buffer_len {
+            // Bytes were buffered (by `push()` or through the public fields) after the end
+            // of the result. Keep them at the front for the next call, and hand out the
+            // result from behind them.
+            let leftover = initial_buffer_len - result_len;
+            self.buffer[..initial_buffer_len].rotate_left(result_len);
+            self.buffer_len = leftover as u8;
+            &self.buffer[leftover..initial_buffer_len]
+        } else {
+            self.take_buffer()
+        };
         let result = match result {
             Ok(()) => Ok(unsafe { str::from_utf8_unchecked(result_bytes) }),
             Err(()) => Err(result_bytes),
         };
-        Some((result, remaining_input))
+        Some((result, consumed))
+    }
+
+    /// The buffered bytes, `buffer[..buffer_len]`.
+    ///
+    /// ```
+    /// use utf8_zero::Incomplete;
+    ///
+    /// assert_eq!(Incomplete::new(b"\xE2\x82").as_bytes(), b"\xE2\x82");
+    /// assert_eq!(Incomplete::empty().as_bytes(), b"");
+    /// ```
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..cmp::min(self.buffer_len as usize, self.buffer.len())]
     }
 
     fn take_buffer(&mut self) -> &[u8] {
-        let len = self.buffer_len as usize;
+        let len = cmp::min(self.buffer_len as usize, self.buffer.len());
         self.buffer_len = 0;
         &self.buffer[..len]
     }
@@ -208,8 +1676,13 @@ impl Incomplete {
     /// (consumed_from_input, None): not enough input
     /// (consumed_from_input, Some(Err(()))): error bytes in buffer
     /// (consumed_from_input, Some(Ok(()))): UTF-8 string in buffer
+    ///
+    /// With `push()` or the public fields, `self` may hold bytes that are not an incomplete
+    /// code point, so the result can end before the bytes buffered so far do. This must
+    /// not panic in that case: the subtractions below saturate to nothing consumed, and
+    /// `try_complete_with_consumed()` keeps the buffered bytes after the result.
     fn try_complete_offsets(&mut self, input: &[u8]) -> (usize, Option<Result<(), ()>>) {
-        let initial_buffer_len = self.buffer_len as usize;
+        let initial_buffer_len = cmp::min(self.buffer_len as usize, self.buffer.len());
         let copied_from_input;
         {
             let unwritten = &mut self.buffer[initial_buffer_len..];
@@ -225,15 +1698,14 @@ impl Incomplete {
             Err(error) => {
                 let valid_up_to = error.valid_up_to();
                 if valid_up_to > 0 {
-                    let consumed = valid_up_to.checked_sub(initial_buffer_len).unwrap();
+                    let consumed = valid_up_to.saturating_sub(initial_buffer_len);
                     self.buffer_len = valid_up_to as u8;
                     (consumed, Some(Ok(())))
                 } else {
                     match error.error_len() {
                         Some(invalid_sequence_length) => {
-                            let consumed = invalid_sequence_length
-                                .checked_sub(initial_buffer_len)
-                                .unwrap();
+                            let consumed =
+                                invalid_sequence_length.saturating_sub(initial_buffer_len);
                             self.buffer_len = invalid_sequence_length as u8;
                             (consumed, Some(Err(())))
                         }
@@ -1,9 +1,13 @@
 use super::*;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::string::String;
 
 /// A push-based, lossy decoder for UTF-8.
 /// Errors are replaced with the U+FFFD replacement character.
 ///
-/// Users "push" bytes into the decoder, which in turn "pushes" `&str` slices into a callback.
+/// Users "push" bytes into the decoder, which in turn "pushes" `&str` slices into a callback
+/// or any other [`Utf8Sink`].
 ///
 /// **Note:** Dropping the decoder signals the end of the input:
 /// If the last input chunk ended with an incomplete byte sequence for a code point,
@@ -39,24 +43,372 @@ use super::*;
 /// }
 /// assert_eq!(output, "Hello \u{00E9}!");
 /// ```
-pub struct LossyDecoder<F: FnMut(&str)> {
-    push_str: F,
+///
+/// # Replacement modes
+///
+/// How many U+FFFD are emitted for a run of bad bytes depends on the
+/// [`ReplacementPolicy`]. For `b"\xF0\x9F\xFF"`, a truncated `F0 9F` followed by `FF`:
+///
+/// * [`Lossy`](ReplacementPolicy::Lossy), as with [`new()`](LossyDecoder::new) -- one
+///   replacement per maximal invalid subpart, as recommended by Unicode since 6.0 and
+///   specified by the WHATWG Encoding Standard. This matches `String::from_utf8_lossy`:
+///   two U+FFFD, one for `F0 9F` and one for `FF`.
+/// * [`LossyPerByte`](ReplacementPolicy::LossyPerByte), as with
+///   [`with_per_byte_replacement()`](LossyDecoder::with_per_byte_replacement) -- one
+///   replacement for every invalid byte, as older software did before that
+///   recommendation: three U+FFFD.
+/// * [`LossyCoalesced`](ReplacementPolicy::LossyCoalesced) -- one replacement for each
+///   run of invalid sequences with no valid text between them, even across chunks, so
+///   that garbage can't swell the output: one U+FFFD.
+pub struct LossyDecoder<F: Utf8Sink, B: FnMut(usize, &[u8]) = fn(usize, &[u8])> {
+    output: Output<F>,
+    on_bad: Option<B>,
     incomplete: Incomplete,
+    policy: ReplacementPolicy,
+    error: Option<StrictDecoderError>,
+    position: usize,
+    /// Where the last invalid sequence ended, to coalesce adjacent ones.
+    last_invalid_end: Option<usize>,
 }
 
 impl<F: FnMut(&str)> LossyDecoder<F> {
     /// Create a new decoder from a callback.
     #[inline]
     pub fn new(push_str: F) -> Self {
-        LossyDecoder {
+        Self::new_with_sink(push_str)
+    }
+
+    /// Create a new decoder that emits one U+FFFD for every invalid byte,
+    /// rather than one per maximal invalid subpart.
+    ///
+    /// This is [`with_policy(ReplacementPolicy::LossyPerByte(REPLACEMENT_CHARACTER),
+    /// push_str)`](LossyDecoder::with_policy).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// LossyDecoder::with_per_byte_replacement(|s| output.push_str(s)).feed(b"a\xF0\x9F\xFFb");
+    /// assert_eq!(output, "a\u{FFFD}\u{FFFD}\u{FFFD}b");
+    /// ```
+    #[inline]
+    pub fn with_per_byte_replacement(push_str: F) -> Self {
+        Self::with_policy(
+            ReplacementPolicy::LossyPerByte(REPLACEMENT_CHARACTER),
             push_str,
-            incomplete: Incomplete {
-                buffer: [0, 0, 0, 0],
-                buffer_len: 0,
-            },
+        )
+    }
+
+    /// Create a new decoder that pushes `replacement` instead of U+FFFD for each error.
+    ///
+    /// The replacement goes through `push_str` like any other text. An empty
+    /// `replacement` drops invalid sequences from the output.
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// LossyDecoder::with_replacement("?", |s| output.push_str(s)).feed(b"a\xFFb\xC3");
+    /// assert_eq!(output, "a?b?");
+    /// ```
+    #[inline]
+    pub fn with_replacement(replacement: &'static str, push_str: F) -> Self {
+        Self::with_policy(ReplacementPolicy::Lossy(replacement), push_str)
+    }
+
+    /// Create a new decoder that drops invalid sequences, including a trailing incomplete
+    /// one, instead of replacing them.
+    ///
+    /// The output is the longest valid subsequence of the input, pushed in the same pieces
+    /// as with [`new()`](LossyDecoder::new) minus the replacements. This is
+    /// [`with_policy(ReplacementPolicy::Skip, push_str)`](LossyDecoder::with_policy).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// LossyDecoder::skipping(|s| output.push_str(s)).feed(b"a\xFFb\xF0\x9F");
+    /// assert_eq!(output, "ab");
+    /// ```
+    #[inline]
+    pub fn skipping(push_str: F) -> Self {
+        Self::with_policy(ReplacementPolicy::Skip, push_str)
+    }
+
+    /// Create a new decoder that handles errors according to `policy`.
+    ///
+    /// With [`ReplacementPolicy::Strict`] the decoder stops at the first invalid sequence
+    /// in a chunk, as a [`StrictDecoder`] does: the rest of the chunk is not decoded, and
+    /// [`try_feed()`](LossyDecoder::try_feed) and [`error()`](LossyDecoder::error) report
+    /// where it stopped. The next chunk carries on after the invalid sequence.
+    ///
+    /// ```
+    /// use utf8_zero::{LossyDecoder, ReplacementPolicy};
+    ///
+    /// let mut output = String::new();
+    /// {
+    ///     let mut decoder = LossyDecoder::with_policy(ReplacementPolicy::Strict, |s| output.push_str(s));
+    ///     assert!(decoder.try_feed(b"ok").is_ok());
+    ///     assert_eq!(decoder.try_feed(b"\xFFnot decoded").unwrap_err().byte_offset, 2);
+    ///     assert_eq!(decoder.byte_position(), 3);
+    ///     assert!(decoder.try_feed(b"!").is_ok());
+    /// }
+    /// assert_eq!(output, "ok!");
+    /// ```
+    #[inline]
+    pub fn with_policy(policy: ReplacementPolicy, push_str: F) -> Self {
+        Self::new(push_str).policy(policy)
+    }
+
+    /// Create a new decoder that marks input cut off in the middle of a code point with
+    /// `marker` instead of U+FFFD.
+    ///
+    /// Input that ends with an incomplete sequence, which more bytes could have completed,
+    /// was truncated rather than corrupted: when the decoder is dropped, `marker` (for
+    /// example `"\u{2026}"`) is pushed once for that sequence. Invalid bytes anywhere
+    /// else, including a sequence that a later chunk shows to be invalid, are still
+    /// replaced with U+FFFD. This is [`with_policy(ReplacementPolicy::TruncationMarker {
+    /// replacement: REPLACEMENT_CHARACTER, marker }, push_str)`](LossyDecoder::with_policy).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// {
+    ///     let mut decoder = LossyDecoder::with_truncation_marker("\u{2026}", |s| output.push_str(s));
+    ///     decoder.feed(b"a\xFFb");
+    ///     decoder.feed(b"\xE2\x82");
+    /// }
+    /// assert_eq!(output, "a\u{FFFD}b\u{2026}");
+    /// ```
+    #[inline]
+    pub fn with_truncation_marker(marker: &'static str, push_str: F) -> Self {
+        let policy = ReplacementPolicy::TruncationMarker {
+            replacement: REPLACEMENT_CHARACTER,
+            marker,
+        };
+        Self::with_policy(policy, push_str)
+    }
+
+    /// Create a new decoder whose callbacks end on line boundaries where possible.
+    ///
+    /// Text after the last `\n` seen so far is held back until the next newline arrives,
+    /// so every callback ends with a `\n` and contains whole lines, except for the final
+    /// callback when the decoder is dropped, which flushes whatever followed the last
+    /// newline.
+    ///
+    /// At most 8 KiB of a partial line is held back. A line longer than that is still
+    /// split: what has arrived of it is pushed without a trailing `\n`, and the rest
+    /// follows in later callbacks. This is
+    /// [`new(push_str).line_aligned(8 * 1024)`](LossyDecoder::line_aligned).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut callbacks = Vec::new();
+    /// {
+    ///     let mut decoder = LossyDecoder::with_line_aligned_output(|s| callbacks.push(s.to_owned()));
+    ///     decoder.feed(b"one\ntw");
+    ///     decoder.feed(b"o\nthree\nfo");
+    ///     decoder.feed(b"ur");
+    /// }
+    /// assert_eq!(callbacks, ["one\n", "two\nthree\n", "four"]);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_line_aligned_output(push_str: F) -> Self {
+        Self::new(push_str).line_aligned(8 * 1024)
+    }
+}
+
+impl<S: Utf8Sink> LossyDecoder<S> {
+    /// Create a new decoder that pushes into a [`Utf8Sink`] rather than a closure.
+    ///
+    /// ```
+    /// use utf8_zero::{LossyDecoder, Utf8Sink};
+    ///
+    /// #[derive(Default)]
+    /// struct Pieces(Vec<String>);
+    ///
+    /// impl Utf8Sink for Pieces {
+    ///     fn push_str(&mut self, s: &str) {
+    ///         if !s.is_empty() {
+    ///             self.0.push(s.to_owned());
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let mut decoder = LossyDecoder::new_with_sink(Pieces::default());
+    /// decoder.feed(b"caf\xC3");
+    /// decoder.feed(b"\xA9");
+    /// assert_eq!(decoder.finish().0, ["caf", "\u{E9}"]);
+    /// ```
+    #[inline]
+    pub fn new_with_sink(sink: S) -> Self {
+        LossyDecoder {
+            output: Output::new(sink),
+            on_bad: None,
+            incomplete: Incomplete::empty(),
+            policy: ReplacementPolicy::default(),
+            error: None,
+            position: 0,
+            last_invalid_end: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl LossyDecoder<String> {
+    /// Create a new decoder that accumulates its output into a `String`, returned by
+    /// [`finish()`](LossyDecoder::finish).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut decoder = LossyDecoder::to_string();
+    /// decoder.feed(b"caf\xC3");
+    /// decoder.feed(b"\xA9 \xFF");
+    /// assert_eq!(decoder.finish(), "caf\u{E9} \u{FFFD}");
+    /// ```
+    #[inline]
+    pub fn to_string() -> Self {
+        Self::new_with_sink(String::new())
+    }
+}
+
+impl<W: fmt::Write> LossyDecoder<FmtWriteSink<W>> {
+    /// Create a new decoder that writes into a [`core::fmt::Write`], such as a `String`.
+    ///
+    /// The first error returned by the writer is stored and nothing more is written
+    /// after it. Check it with [`FmtWriteSink::result()`] on the sink returned by
+    /// [`finish()`](LossyDecoder::finish).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut decoder = LossyDecoder::from_fmt_write(String::new());
+    /// decoder.feed(b"Hello\xC0World");
+    /// let sink = decoder.finish();
+    /// assert!(sink.result().is_ok());
+    /// assert_eq!(sink.into_inner(), "Hello\u{FFFD}World");
+    /// ```
+    #[inline]
+    pub fn from_fmt_write(writer: W) -> Self {
+        Self::new_with_sink(FmtWriteSink {
+            writer,
+            result: Ok(()),
+        })
+    }
+}
+
+impl<F: FnMut(&str), B: FnMut(usize, &[u8])> LossyDecoder<F, B> {
+    /// Create a new decoder that also reports every invalid sequence to `on_bad`.
+    ///
+    /// `on_bad` is called with the absolute offset of the sequence in the stream (counting
+    /// all bytes passed to [`feed()`](LossyDecoder::feed) so far) and its raw bytes, once
+    /// per maximal invalid subpart and just before its replacement is pushed. A trailing
+    /// incomplete sequence is reported when the decoder is dropped. The text pushed to
+    /// `push_str` is the same as with [`new()`](LossyDecoder::new).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// let mut bad = Vec::new();
+    /// {
+    ///     let mut decoder = LossyDecoder::with_forensics(
+    ///         |s| output.push_str(s),
+    ///         |offset, bytes| bad.push((offset, bytes.to_vec())),
+    ///     );
+    ///     decoder.feed(b"ok\xFF");
+    ///     decoder.feed(b"then \xF0\x9F");
+    /// }
+    /// assert_eq!(output, "ok\u{FFFD}then \u{FFFD}");
+    /// assert_eq!(bad, [(2, vec![0xFF]), (8, vec![0xF0, 0x9F])]);
+    /// ```
+    #[inline]
+    pub fn with_forensics(push_str: F, on_bad: B) -> Self {
+        LossyDecoder::new(push_str).forensics(on_bad)
+    }
+}
+
+impl<F: Utf8Sink, B: FnMut(usize, &[u8])> LossyDecoder<F, B> {
+    /// Handle errors according to `policy`, as described for
+    /// [`with_policy()`](LossyDecoder::with_policy).
+    ///
+    /// This and the other builder methods below work for any sink, where the `with_*`
+    /// constructors take a closure.
+    ///
+    /// ```
+    /// use utf8_zero::{LossyDecoder, ReplacementPolicy};
+    ///
+    /// let mut bad = Vec::new();
+    /// let mut decoder = LossyDecoder::to_string()
+    ///     .policy(ReplacementPolicy::LossyPerByte("?"))
+    ///     .forensics(|offset, bytes: &[u8]| bad.push((offset, bytes.len())));
+    /// decoder.feed(b"a\xF0\x9F!");
+    /// assert_eq!(decoder.finish(), "a??!");
+    /// assert_eq!(bad, [(1, 2)]);
+    /// ```
+    #[inline]
+    pub fn policy(mut self, policy: ReplacementPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Also report every invalid sequence to `on_bad`, as described for
+    /// [`with_forensics()`](LossyDecoder::with_forensics). This replaces any callback set
+    /// before.
+    pub fn forensics<B2: FnMut(usize, &[u8])>(mut self, on_bad: B2) -> LossyDecoder<F, B2> {
+        let incomplete = self.incomplete;
+        // Leave nothing for `self` to push when it is dropped.
+        self.incomplete.clear();
+        LossyDecoder {
+            output: self.output.take(),
+            on_bad: Some(on_bad),
+            incomplete,
+            policy: self.policy,
+            error: self.error,
+            position: self.position,
+            last_invalid_end: self.last_invalid_end,
         }
     }
 
+    /// Make callbacks end on line boundaries where possible, as described for
+    /// [`with_line_aligned_output()`](LossyDecoder::with_line_aligned_output), holding
+    /// back at most `max_line_len` bytes of a partial line.
+    ///
+    /// This shapes the output rather than handling errors, so it combines with any
+    /// [`ReplacementPolicy`].
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut callbacks = Vec::new();
+    /// {
+    ///     let mut decoder = LossyDecoder::new(|s: &str| callbacks.push(s.to_owned())).line_aligned(4);
+    ///     decoder.feed(b"ab\nc");
+    ///     decoder.feed(b"defg");
+    ///     decoder.feed(b"h\n");
+    /// }
+    /// assert_eq!(callbacks, ["ab\n", "c", "defg", "h\n"]);
+    /// ```
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn line_aligned(mut self, max_line_len: usize) -> Self {
+        self.output.line_buffer.get_or_insert_with(String::new);
+        self.output.max_line_len = max_line_len;
+        self
+    }
+
+    /// Count the `char`s pushed, for [`char_position()`](LossyDecoder::char_position).
+    ///
+    /// Counting costs a pass over all the output, so it is off unless asked for.
+    #[inline]
+    pub fn track_char_position(mut self) -> Self {
+        self.output.chars.get_or_insert(0);
+        self
+    }
+
     /// Feed one chunk of input into the decoder.
     ///
     /// The input is decoded lossily
@@ -64,53 +416,461 @@ impl<F: FnMut(&str)> LossyDecoder<F> {
     ///
     /// If the UTF-8 byte sequence for one code point was split into this bytes chunk
     /// and previous bytes chunks, it will be correctly pieced back together.
-    pub fn feed(&mut self, mut input: &[u8]) {
+    ///
+    /// With the `tracing` feature, each call runs in a `trace`-level span recording the
+    /// input length and the number of replacements, and emits a `debug`-level event
+    /// when any invalid bytes were replaced.
+    pub fn feed(&mut self, input: &[u8]) {
+        self.feed_counted(input);
+    }
+
+    /// Same as [`feed()`](LossyDecoder::feed), but returns how many replacements were
+    /// pushed for invalid sequences during this call (or how many were dropped, with
+    /// [`ReplacementPolicy::Skip`]).
+    ///
+    /// The count includes a sequence left incomplete by an earlier chunk that this one
+    /// shows to be invalid. It excludes a trailing incomplete sequence, which is only
+    /// replaced at the end of the input and counted by
+    /// [`finish_counted()`](LossyDecoder::finish_counted).
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// let mut decoder = LossyDecoder::new(|s| output.push_str(s));
+    /// assert_eq!(decoder.feed_counted(b"a\xFFb\xC0\x80"), 3);
+    /// assert_eq!(decoder.feed_counted(b"ok\xE2\x82"), 0);
+    /// ```
+    pub fn feed_counted(&mut self, input: &[u8]) -> usize {
+        #[cfg(not(feature = "tracing"))]
+        return self.feed_inner(input);
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::trace_span!(
+                "utf8_zero::feed",
+                input_len = input.len(),
+                replacements = tracing::field::Empty
+            )
+            .entered();
+            let replacements = self.feed_inner(input);
+            span.record("replacements", replacements);
+            if replacements > 0 {
+                tracing::debug!(
+                    input_len = input.len(),
+                    replacements,
+                    "replaced invalid UTF-8"
+                );
+            }
+            replacements
+        }
+    }
+
+    /// Same as [`feed()`](LossyDecoder::feed), but returns the error at which a decoder with
+    /// [`ReplacementPolicy::Strict`] stopped in this call.
+    ///
+    /// An incomplete sequence at the end of the input is only an error once the input
+    /// has ended, so it is not reported here.
+    #[inline]
+    pub fn try_feed(&mut self, input: &[u8]) -> Result<(), StrictDecoderError> {
+        self.feed(input);
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// The offset in the stream up to which input has been decoded.
+    ///
+    /// This counts all bytes passed to [`feed()`](LossyDecoder::feed), minus a trailing
+    /// incomplete sequence that is still buffered. Text for all bytes before it has been
+    /// pushed, except for what line-aligned mode holds back.
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut decoder = LossyDecoder::new(|_| {}).track_char_position();
+    /// decoder.feed(b"caf\xC3");
+    /// assert_eq!((decoder.byte_position(), decoder.char_position()), (3, Some(3)));
+    /// decoder.feed(b"\xA9\xFF");
+    /// assert_eq!((decoder.byte_position(), decoder.char_position()), (6, Some(5)));
+    /// ```
+    #[inline]
+    pub fn byte_position(&self) -> usize {
+        self.position - self.incomplete.buffer_len as usize
+    }
+
+    /// The number of `char`s decoded up to [`byte_position()`](LossyDecoder::byte_position),
+    /// counting each replacement string as its own `char`s, or `None` unless enabled with
+    /// [`track_char_position()`](LossyDecoder::track_char_position).
+    #[inline]
+    pub fn char_position(&self) -> Option<usize> {
+        self.output.chars
+    }
+
+    /// The invalid sequence at which a decoder with [`ReplacementPolicy::Strict`] stopped in
+    /// the last call to [`feed()`](LossyDecoder::feed), or at the end of the input.
+    #[inline]
+    pub fn error(&self) -> Option<StrictDecoderError> {
+        self.error
+    }
+
+    /// The bytes of a trailing incomplete sequence buffered from the last chunk, if any.
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut decoder = LossyDecoder::new(|_| {});
+    /// decoder.feed(b"a\xE2");
+    /// assert_eq!(decoder.pending(), [0xE2]);
+    /// decoder.feed(b"\x82\xAC");
+    /// assert!(decoder.pending().is_empty());
+    /// ```
+    #[inline]
+    pub fn pending(&self) -> &[u8] {
+        self.incomplete.as_bytes()
+    }
+
+    /// Signal the end of the input and return the sink.
+    ///
+    /// This does what dropping the decoder does: if the input ended with an incomplete
+    /// byte sequence, a replacement is pushed for it (along with any held-back output), but
+    /// at a point of the caller's choosing. Nothing more is pushed when the decoder is then
+    /// dropped.
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// let mut decoder = LossyDecoder::new(|s| output.push_str(s));
+    /// decoder.feed(b"caf\xC3");
+    /// let _push_str = decoder.finish();
+    /// assert_eq!(output, "caf\u{FFFD}");
+    /// ```
+    pub fn finish(self) -> F {
+        self.finish_counted().0
+    }
+
+    /// Same as [`finish()`](LossyDecoder::finish), but also returns how many replacements
+    /// were pushed for a trailing incomplete sequence, counted like
+    /// [`feed_counted()`](LossyDecoder::feed_counted) counts them.
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut decoder = LossyDecoder::to_string();
+    /// assert_eq!(decoder.feed_counted(b"a\xFFb\xE2\x82"), 1);
+    /// assert_eq!(decoder.finish_counted(), ("a\u{FFFD}b\u{FFFD}".to_owned(), 1));
+    /// ```
+    pub fn finish_counted(mut self) -> (F, usize) {
+        let replacements = self.end();
+        // With the sink gone, the `end()` that `Drop` runs pushes nothing.
+        let sink = self
+            .output
+            .sink
+            .take()
+            .expect("the sink is only taken by methods that consume the decoder");
+        (sink, replacements)
+    }
+
+    /// Discard all buffered state, so the decoder can be reused for a new stream.
+    ///
+    /// Unlike [`finish()`](LossyDecoder::finish) and dropping, which treat a trailing
+    /// incomplete sequence as an error and push a replacement for it, `reset` throws the
+    /// buffered bytes away without pushing anything, so that they can't be combined with
+    /// the start of the next stream. Text held back in line-aligned mode is discarded as
+    /// well. Stream offsets reported to the forensics callback and the positions start
+    /// again from 0.
+    ///
+    /// ```
+    /// use utf8_zero::LossyDecoder;
+    ///
+    /// let mut output = String::new();
+    /// {
+    ///     let mut decoder = LossyDecoder::new(|s| output.push_str(s));
+    ///     decoder.feed(b"first\xC3");
+    ///     decoder.reset();
+    ///     decoder.feed(b"\xA9second");
+    /// }
+    /// assert_eq!(output, "first\u{FFFD}second");
+    /// ```
+    pub fn reset(&mut self) {
+        self.incomplete.clear();
+        self.position = 0;
+        if let Some(chars) = &mut self.output.chars {
+            *chars = 0;
+        }
+        self.error = None;
+        self.last_invalid_end = None;
+        #[cfg(feature = "std")]
+        if let Some(pending) = &mut self.output.line_buffer {
+            pending.clear();
+        }
+    }
+
+    /// Borrow the sink, or `None` once it has been taken.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn sink_mut(&mut self) -> Option<&mut F> {
+        self.output.sink.as_mut()
+    }
+
+    /// Push the replacement for a trailing incomplete sequence and any held-back output.
+    /// Returns the number of replacement strings pushed.
+    pub(crate) fn end(&mut self) -> usize {
+        let mut replacements = 0;
+        if self.incomplete.buffer_len > 0 {
+            let invalid_len = self.incomplete.buffer_len as usize;
+            let buffer = self.incomplete.buffer;
+            let offset = self.position - invalid_len;
+            self.incomplete.buffer_len = 0;
+            replacements =
+                self.push_invalid(offset, &buffer[..invalid_len], DecodeErrorKind::Incomplete);
+        }
+        self.output.flush();
+        replacements
+    }
+
+    /// Returns the number of replacement strings pushed.
+    fn feed_inner(&mut self, mut input: &[u8]) -> usize {
+        self.error = None;
+        let start = self.position;
+        self.position += input.len();
+        let input_len = input.len();
+        let mut replacements = 0;
         if self.incomplete.buffer_len > 0 {
+            let offset = start - self.incomplete.buffer_len as usize;
             match self.incomplete.try_complete(input) {
                 Some((Ok(s), remaining)) => {
-                    (self.push_str)(s);
+                    self.output.push(s);
                     input = remaining
                 }
-                Some((Err(_), remaining)) => {
-                    (self.push_str)(REPLACEMENT_CHARACTER);
+                Some((Err(invalid), remaining)) => {
+                    let invalid_len = invalid.len();
+                    let buffer = self.incomplete.buffer;
+                    replacements +=
+                        self.push_invalid(offset, &buffer[..invalid_len], DecodeErrorKind::Invalid);
+                    if self.error.is_some() {
+                        return replacements;
+                    }
                     input = remaining
                 }
-                None => return,
+                None => return replacements,
             }
         }
         loop {
             match decode(input) {
                 Ok(s) => {
-                    (self.push_str)(s);
-                    return;
+                    self.output.push(s);
+                    return replacements;
                 }
                 Err(DecodeError::Incomplete {
                     valid_prefix,
                     incomplete_suffix,
                 }) => {
-                    (self.push_str)(valid_prefix);
+                    self.output.push(valid_prefix);
                     self.incomplete = incomplete_suffix;
-                    return;
+                    return replacements;
                 }
                 Err(DecodeError::Invalid {
                     valid_prefix,
+                    invalid_sequence,
                     remaining_input,
-                    ..
                 }) => {
-                    (self.push_str)(valid_prefix);
-                    (self.push_str)(REPLACEMENT_CHARACTER);
+                    self.output.push(valid_prefix);
+                    let offset = start + (input_len - input.len()) + valid_prefix.len();
+                    replacements +=
+                        self.push_invalid(offset, invalid_sequence, DecodeErrorKind::Invalid);
+                    if self.error.is_some() {
+                        return replacements;
+                    }
                     input = remaining_input
                 }
             }
         }
     }
+
+    /// Report a bad sequence and push its replacement, or stop decoding for the strict
+    /// policy. `kind` is `Incomplete` for a sequence cut off by the end of the input.
+    /// Returns the number of replacement strings pushed.
+    fn push_invalid(&mut self, offset: usize, invalid: &[u8], kind: DecodeErrorKind) -> usize {
+        if let Some(on_bad) = &mut self.on_bad {
+            on_bad(offset, invalid);
+        }
+        let replacement =
+            self.policy
+                .replacement(&mut self.last_invalid_end, offset, invalid.len(), kind);
+        let Some((replacement, count)) = replacement else {
+            // Consumed up to the end of the sequence, as for `StrictDecoder`.
+            self.error = Some(StrictDecoderError {
+                byte_offset: offset,
+                invalid_len: invalid.len(),
+                kind,
+            });
+            self.position = offset + invalid.len();
+            self.incomplete.clear();
+            return 0;
+        };
+        for _ in 0..count {
+            self.output.push(replacement)
+        }
+        count
+    }
 }
 
-impl<F: FnMut(&str)> Drop for LossyDecoder<F> {
+impl<F: Utf8Sink, B: FnMut(usize, &[u8])> Drop for LossyDecoder<F, B> {
     #[inline]
     fn drop(&mut self) {
-        if self.incomplete.buffer_len > 0 {
-            (self.push_str)(REPLACEMENT_CHARACTER)
+        self.end();
+    }
+}
+
+/// Where a [`LossyDecoder`] pushes its decoded text.
+///
+/// Any `FnMut(&str)` closure is a sink, and so is `String` with the `std` feature.
+/// Implement this trait directly for a type that carries more state than a closure
+/// conveniently can, and pass it to [`LossyDecoder::new_with_sink()`].
+pub trait Utf8Sink {
+    /// Receive the next piece of decoded text.
+    fn push_str(&mut self, s: &str);
+}
+
+impl<F: FnMut(&str)> Utf8Sink for F {
+    #[inline]
+    fn push_str(&mut self, s: &str) {
+        self(s)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Utf8Sink for String {
+    #[inline]
+    fn push_str(&mut self, s: &str) {
+        String::push_str(self, s)
+    }
+}
+
+/// A [`Utf8Sink`] writing into a [`core::fmt::Write`], created by
+/// [`LossyDecoder::from_fmt_write()`].
+#[derive(Debug)]
+pub struct FmtWriteSink<W> {
+    writer: W,
+    result: fmt::Result,
+}
+
+impl<W> FmtWriteSink<W> {
+    /// The first error returned by the writer, if any.
+    #[inline]
+    pub fn result(&self) -> fmt::Result {
+        self.result
+    }
+
+    /// Borrow the writer.
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Return the writer, discarding any stored error.
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: fmt::Write> Utf8Sink for FmtWriteSink<W> {
+    #[inline]
+    fn push_str(&mut self, s: &str) {
+        if self.result.is_ok() {
+            self.result = self.writer.write_str(s);
+        }
+    }
+}
+
+/// The sink of a [`LossyDecoder`], plus the partial line held back in line-aligned mode.
+struct Output<F> {
+    /// Taken by [`LossyDecoder::finish()`], after which nothing is pushed.
+    sink: Option<F>,
+    /// The number of `char`s passed to `push()`, if counted.
+    chars: Option<usize>,
+    #[cfg(feature = "std")]
+    line_buffer: Option<String>,
+    /// How long `line_buffer` may get before it is pushed anyway.
+    #[cfg(feature = "std")]
+    max_line_len: usize,
+}
+
+impl<F: Utf8Sink> Output<F> {
+    /// Move the output out, leaving one without a sink.
+    fn take(&mut self) -> Self {
+        Output {
+            sink: self.sink.take(),
+            chars: self.chars,
+            #[cfg(feature = "std")]
+            line_buffer: self.line_buffer.take(),
+            #[cfg(feature = "std")]
+            max_line_len: self.max_line_len,
+        }
+    }
+
+    #[inline]
+    fn new(sink: F) -> Self {
+        Output {
+            sink: Some(sink),
+            chars: None,
+            #[cfg(feature = "std")]
+            line_buffer: None,
+            #[cfg(feature = "std")]
+            max_line_len: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, s: &str) {
+        let Some(sink) = &mut self.sink else {
+            return;
+        };
+        if let Some(chars) = &mut self.chars {
+            *chars += s.chars().count();
+        }
+        #[cfg(feature = "std")]
+        if let Some(pending) = &mut self.line_buffer {
+            let rest = match s.rfind('\n') {
+                Some(newline) => {
+                    let (lines, rest) = s.split_at(newline + 1);
+                    if pending.is_empty() {
+                        sink.push_str(lines);
+                    } else {
+                        pending.push_str(lines);
+                        sink.push_str(pending);
+                        pending.clear();
+                    }
+                    rest
+                }
+                None => s,
+            };
+            if pending.len() + rest.len() > self.max_line_len {
+                // Too long to hold back, so the line is split here.
+                if !pending.is_empty() {
+                    sink.push_str(pending);
+                    pending.clear();
+                }
+                sink.push_str(rest);
+            } else {
+                pending.push_str(rest);
+            }
+            return;
+        }
+        sink.push_str(s)
+    }
+
+    fn flush(&mut self) {
+        #[cfg(feature = "std")]
+        if let (Some(sink), Some(pending)) = (&mut self.sink, &mut self.line_buffer) {
+            if !pending.is_empty() {
+                sink.push_str(pending);
+                pending.clear();
+            }
         }
     }
 }
@@ -0,0 +1,154 @@
+use super::*;
+use std::str::Utf8Error;
+use std::string::String;
+use std::vec::Vec;
+
+/// An owned version of [`DecodeError`], which doesn't borrow the input.
+///
+/// Created by [`DecodeError::into_owned()`], for returning an error after the input
+/// buffer is gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnedDecodeError {
+    /// See `DecodeError::Invalid`.
+    Invalid {
+        /// The leading valid UTF-8 portion of the input.
+        valid_prefix: String,
+        /// The bytes that form the invalid sequence.
+        invalid_sequence: Vec<u8>,
+        /// The bytes after the invalid sequence, not yet decoded.
+        remaining_input: Vec<u8>,
+    },
+
+    /// See `DecodeError::Incomplete`.
+    Incomplete {
+        /// The leading valid UTF-8 portion of the input.
+        valid_prefix: String,
+        /// The trailing bytes that start a multi-byte code point but are not complete.
+        incomplete_suffix: Incomplete,
+    },
+}
+
+impl OwnedDecodeError {
+    /// Borrow this as a [`DecodeError`].
+    pub fn as_decode_error(&self) -> DecodeError<'_> {
+        match self {
+            OwnedDecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            } => DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            },
+            OwnedDecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            } => DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix: *incomplete_suffix,
+            },
+        }
+    }
+}
+
+impl<'a> DecodeError<'a> {
+    /// Copy the borrowed parts of this error into an [`OwnedDecodeError`].
+    ///
+    /// ```
+    /// use utf8_zero::{decode, OwnedDecodeError};
+    ///
+    /// fn parse(buffer: Vec<u8>) -> Result<usize, OwnedDecodeError> {
+    ///     let s = decode(&buffer).map_err(|e| e.into_owned())?;
+    ///     Ok(s.len())
+    /// }
+    ///
+    /// let error = parse(b"ab\xFFcd".to_vec()).unwrap_err();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "found invalid byte sequence [ff] after 2 valid bytes, followed by 2 more unprocessed bytes"
+    /// );
+    /// ```
+    pub fn into_owned(self) -> OwnedDecodeError {
+        match self {
+            DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            } => OwnedDecodeError::Invalid {
+                valid_prefix: valid_prefix.into(),
+                invalid_sequence: invalid_sequence.into(),
+                remaining_input: remaining_input.into(),
+            },
+            DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            } => OwnedDecodeError::Incomplete {
+                valid_prefix: valid_prefix.into(),
+                incomplete_suffix,
+            },
+        }
+    }
+}
+
+impl<'a> DecodeError<'a> {
+    /// Convert to the equivalent [`Utf8Error`], as `str::from_utf8` would return it.
+    ///
+    /// `Utf8Error` can't be built directly, so this copies `valid_prefix`, the error
+    /// bytes, and the byte after them if the sequence was cut short, into a temporary
+    /// buffer and validates that. For an error returned by [`decode()`] the result has
+    /// `valid_up_to()` equal to `valid_prefix.len()`, and `error_len()` equal to
+    /// `Some(invalid_sequence.len())` for `Invalid` or `None` for `Incomplete`.
+    ///
+    /// The fields are public, so an error can also be built by hand. One whose bytes are
+    /// valid UTF-8 has no equivalent `Utf8Error`, and this returns `None`.
+    ///
+    /// ```
+    /// use utf8_zero::decode;
+    ///
+    /// let error = decode(b"ab\xF0\x9F!").unwrap_err().to_utf8_error().unwrap();
+    /// assert_eq!(error.valid_up_to(), 2);
+    /// assert_eq!(error.error_len(), Some(2));
+    /// ```
+    pub fn to_utf8_error(&self) -> Option<Utf8Error> {
+        let mut bytes = Vec::from(self.valid_prefix().as_bytes());
+        match *self {
+            DecodeError::Invalid {
+                invalid_sequence,
+                remaining_input,
+                ..
+            } => {
+                bytes.extend_from_slice(invalid_sequence);
+                bytes.extend(remaining_input.first());
+            }
+            DecodeError::Incomplete {
+                incomplete_suffix, ..
+            } => bytes.extend_from_slice(incomplete_suffix.as_bytes()),
+        }
+        str::from_utf8(&bytes).err()
+    }
+}
+
+/// Fails, giving the error back, if it describes valid UTF-8. See
+/// [`DecodeError::to_utf8_error()`].
+impl<'a> TryFrom<DecodeError<'a>> for Utf8Error {
+    type Error = DecodeError<'a>;
+
+    fn try_from(error: DecodeError<'a>) -> Result<Self, DecodeError<'a>> {
+        error.to_utf8_error().ok_or(error)
+    }
+}
+
+impl<'a> From<DecodeError<'a>> for OwnedDecodeError {
+    fn from(error: DecodeError<'a>) -> Self {
+        error.into_owned()
+    }
+}
+
+impl fmt::Display for OwnedDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.as_decode_error().fmt(f)
+    }
+}
+
+impl std::error::Error for OwnedDecodeError {}
@@ -0,0 +1,161 @@
+use super::*;
+use core::ops::RangeInclusive;
+
+/// Why a [`PolicyRejection`] was raised.
+///
+/// Policies only see valid UTF-8. Malformed input, such as an overlong encoding, is
+/// reported as [`PolicyError::Decode`] instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PolicyReason {
+    /// A U+0000 NUL code point.
+    Nul,
+    /// A C1 control code point, U+0080 to U+009F.
+    C1Control,
+    /// A bidirectional formatting code point, such as U+202E RIGHT-TO-LEFT OVERRIDE.
+    BidiControl,
+    /// A code point outside the allowed ranges.
+    OutOfRange,
+    /// A noncharacter code point, such as U+FFFE.
+    Noncharacter,
+}
+
+/// Input that is valid UTF-8 but violates a decoding policy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PolicyRejection {
+    /// Byte offset of the offending code point in the input.
+    pub byte_offset: usize,
+    /// The policy that was violated.
+    pub reason: PolicyReason,
+}
+
+/// Error from the policy decoders such as [`decode_without_nul()`] and [`decode_in_ranges()`].
+///
+/// Keeps UTF-8 errors apart from input that is well-formed but rejected by the policy.
+#[derive(Debug, Copy, Clone)]
+pub enum PolicyError<'a> {
+    /// The input is not valid UTF-8. Policies are only checked up to this error.
+    Decode(DecodeError<'a>),
+
+    /// The input is valid UTF-8 up to and including the offending code point,
+    /// which violates the policy.
+    Rejected {
+        /// The valid, accepted text before the offending code point.
+        valid_prefix: &'a str,
+        /// Where and why the input was rejected.
+        rejection: PolicyRejection,
+    },
+}
+
+impl<'a> From<DecodeError<'a>> for PolicyError<'a> {
+    fn from(error: DecodeError<'a>) -> Self {
+        PolicyError::Decode(error)
+    }
+}
+
+impl<'a> fmt::Display for PolicyError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicyError::Decode(ref error) => error.fmt(f),
+            PolicyError::Rejected { rejection, .. } => write!(
+                f,
+                "rejected code point at byte {}: {:?}",
+                rejection.byte_offset, rejection.reason
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> std::error::Error for PolicyError<'a> {}
+
+/// Decode `input`, rejecting the first code point for which `reject` returns a reason.
+fn decode_with_policy<F: FnMut(char) -> Option<PolicyReason>>(
+    input: &[u8],
+    mut reject: F,
+) -> Result<&str, PolicyError<'_>> {
+    let (s, error) = match decode(input) {
+        Ok(s) => (s, None),
+        Err(error) => match error {
+            DecodeError::Invalid { valid_prefix, .. }
+            | DecodeError::Incomplete { valid_prefix, .. } => (valid_prefix, Some(error)),
+        },
+    };
+    for (byte_offset, c) in s.char_indices() {
+        if let Some(reason) = reject(c) {
+            return Err(PolicyError::Rejected {
+                valid_prefix: &s[..byte_offset],
+                rejection: PolicyRejection {
+                    byte_offset,
+                    reason,
+                },
+            });
+        }
+    }
+    match error {
+        Some(error) => Err(PolicyError::Decode(error)),
+        None => Ok(s),
+    }
+}
+
+/// Decode `input`, rejecting any U+0000 NUL with [`PolicyReason::Nul`].
+///
+/// Useful before handing text to C APIs that treat NUL as a terminator.
+///
+/// ```
+/// use utf8_zero::{decode_without_nul, PolicyError, PolicyReason};
+///
+/// assert_eq!(decode_without_nul(b"hello").unwrap(), "hello");
+/// match decode_without_nul(b"hel\0lo") {
+///     Err(PolicyError::Rejected { valid_prefix, rejection }) => {
+///         assert_eq!(valid_prefix, "hel");
+///         assert_eq!(rejection.byte_offset, 3);
+///         assert_eq!(rejection.reason, PolicyReason::Nul);
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn decode_without_nul(input: &[u8]) -> Result<&str, PolicyError<'_>> {
+    decode_with_policy(input, |c| (c == '\0').then_some(PolicyReason::Nul))
+}
+
+/// Decode `input` and check that every code point falls within one of `ranges`.
+///
+/// `ranges` must be sorted and non-overlapping (checked in debug builds). A code point
+/// outside all of them is rejected with [`PolicyReason::OutOfRange`] at its byte offset.
+///
+/// ```
+/// use utf8_zero::{decode_in_ranges, PolicyError, PolicyReason};
+///
+/// let lowercase = ['a'..='z'];
+/// assert_eq!(decode_in_ranges(b"hello", &lowercase).unwrap(), "hello");
+/// match decode_in_ranges(b"hell0", &lowercase) {
+///     Err(PolicyError::Rejected { valid_prefix, rejection }) => {
+///         assert_eq!(valid_prefix, "hell");
+///         assert_eq!(rejection.reason, PolicyReason::OutOfRange);
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn decode_in_ranges<'a>(
+    input: &'a [u8],
+    ranges: &[RangeInclusive<char>],
+) -> Result<&'a str, PolicyError<'a>> {
+    debug_assert!(
+        ranges.windows(2).all(|w| w[0].end() < w[1].start()),
+        "ranges must be sorted and non-overlapping"
+    );
+    decode_with_policy(input, |c| {
+        ranges
+            .binary_search_by(|range| {
+                if *range.end() < c {
+                    cmp::Ordering::Less
+                } else if *range.start() > c {
+                    cmp::Ordering::Greater
+                } else {
+                    cmp::Ordering::Equal
+                }
+            })
+            .is_err()
+            .then_some(PolicyReason::OutOfRange)
+    })
+}
@@ -0,0 +1,192 @@
+use super::*;
+use std::borrow::Cow;
+use std::string::String;
+
+/// Switches that relax or tighten UTF-8 validation in [`decode_profiled()`].
+///
+/// The [`Default`] profile is standard UTF-8: overlong encodings and surrogates are
+/// rejected and 4-byte sequences are accepted. A rejected overlong or surrogate encoding
+/// is invalid UTF-8, reported as `DecodeError::Invalid` exactly as [`decode()`] would. A
+/// rejected 4-byte sequence is valid UTF-8, so it gets its own
+/// [`ProfileError::FourByte`] instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConformanceProfile {
+    /// Accept overlong (non-shortest form) encodings such as `C0 80`, decoding them to
+    /// the code point they spell out. Overlong forms of surrogates stay invalid.
+    pub allow_overlong: bool,
+    /// Accept surrogate pairs encoded as two 3-byte sequences (as in CESU-8), decoding
+    /// them to the supplementary code point. Lone surrogates stay invalid since they
+    /// can't be represented in a `str`.
+    pub allow_surrogates: bool,
+    /// Accept 4-byte sequences, i.e. code points above U+FFFF (and, together with
+    /// `allow_overlong`, 4-byte overlong forms).
+    pub allow_four_byte: bool,
+}
+
+impl Default for ConformanceProfile {
+    fn default() -> Self {
+        ConformanceProfile {
+            allow_overlong: false,
+            allow_surrogates: false,
+            allow_four_byte: true,
+        }
+    }
+}
+
+/// Error from [`decode_profiled()`].
+///
+/// Keeps UTF-8 errors apart from well-formed input that the profile rejects. A plain
+/// [`DecodeError`] can't carry the latter: `DecodeError::Invalid` says its
+/// `invalid_sequence` is not UTF-8, and code that relies on that, such as
+/// [`DecodeError::to_utf8_error()`], has nothing to report for a valid 4-byte sequence.
+/// Errors in the UTF-8 itself are still the `DecodeError` that [`decode()`] returns,
+/// wrapped in [`Decode`](ProfileError::Decode).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ProfileError<'a> {
+    /// The input is not valid UTF-8, and no relaxed switch of the profile accepts it.
+    Decode(DecodeError<'a>),
+
+    /// A 4-byte sequence, which is valid UTF-8, but `allow_four_byte` is off.
+    FourByte {
+        /// The valid, accepted text before the sequence. Like the `valid_prefix` of a
+        /// decode error, it only covers the run after the last relaxed sequence.
+        valid_prefix: &'a str,
+        /// Offset of the sequence in the input.
+        byte_offset: usize,
+        /// The code point it encodes, above U+FFFF.
+        code_point: char,
+    },
+}
+
+impl<'a> From<DecodeError<'a>> for ProfileError<'a> {
+    fn from(error: DecodeError<'a>) -> Self {
+        ProfileError::Decode(error)
+    }
+}
+
+impl<'a> fmt::Display for ProfileError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProfileError::Decode(ref error) => error.fmt(f),
+            ProfileError::FourByte {
+                byte_offset,
+                code_point,
+                ..
+            } => write!(
+                f,
+                "rejected 4-byte sequence for U+{:X} at byte {}",
+                code_point as u32, byte_offset
+            ),
+        }
+    }
+}
+
+impl<'a> std::error::Error for ProfileError<'a> {}
+
+/// Decode `input` as UTF-8 with the validation rules adjusted by `profile`.
+///
+/// Returns `Cow::Borrowed` when the input is standard UTF-8 that the profile accepts,
+/// and `Cow::Owned` when sequences accepted only by a relaxed switch had to be
+/// re-encoded.
+///
+/// If sequences accepted by a relaxed switch occur before the error, the error's
+/// `valid_prefix` only covers the standard UTF-8 run after the last of them.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use utf8_zero::{decode_profiled, ConformanceProfile};
+///
+/// let lenient = ConformanceProfile {
+///     allow_overlong: true,
+///     ..ConformanceProfile::default()
+/// };
+/// assert_eq!(decode_profiled(b"a\xC0\x80b", lenient).unwrap(), "a\0b");
+/// assert!(decode_profiled(b"a\xC0\x80b", ConformanceProfile::default()).is_err());
+/// assert!(matches!(decode_profiled(b"ab", lenient), Ok(Cow::Borrowed("ab"))));
+/// ```
+pub fn decode_profiled(
+    input: &[u8],
+    profile: ConformanceProfile,
+) -> Result<Cow<'_, str>, ProfileError<'_>> {
+    let mut owned: Option<String> = None;
+    let mut rest = input;
+    loop {
+        let (valid, error) = match decode(rest) {
+            Ok(valid) => (valid, None),
+            Err(error @ DecodeError::Invalid { valid_prefix, .. })
+            | Err(error @ DecodeError::Incomplete { valid_prefix, .. }) => {
+                (valid_prefix, Some(error))
+            }
+        };
+        if !profile.allow_four_byte {
+            if let Some((i, c)) = valid.char_indices().find(|(_, c)| c.len_utf8() == 4) {
+                return Err(ProfileError::FourByte {
+                    valid_prefix: &valid[..i],
+                    byte_offset: input.len() - rest.len() + i,
+                    code_point: c,
+                });
+            }
+        }
+        let error = match error {
+            Some(error) => error,
+            None => {
+                return Ok(match owned {
+                    None => Cow::Borrowed(valid),
+                    Some(mut string) => {
+                        string.push_str(valid);
+                        Cow::Owned(string)
+                    }
+                })
+            }
+        };
+        let after_valid = &rest[valid.len()..];
+        let (c, len) = decode_relaxed(after_valid, profile).ok_or(ProfileError::Decode(error))?;
+        let string = owned.get_or_insert_with(|| String::with_capacity(input.len()));
+        string.push_str(valid);
+        string.push(c);
+        rest = &after_valid[len..];
+    }
+}
+
+/// Try to decode a sequence that standard UTF-8 rejects but `profile` accepts.
+fn decode_relaxed(bytes: &[u8], profile: ConformanceProfile) -> Option<(char, usize)> {
+    fn cont(bytes: &[u8], i: usize) -> Option<u32> {
+        match bytes.get(i) {
+            Some(&b) if b & 0xC0 == 0x80 => Some((b & 0x3F) as u32),
+            _ => None,
+        }
+    }
+    let lead = *bytes.first()?;
+    if profile.allow_overlong {
+        let overlong = match lead {
+            0xC0 | 0xC1 => cont(bytes, 1).map(|b1| (((lead & 0x1F) as u32) << 6 | b1, 2)),
+            0xE0 if matches!(bytes.get(1), Some(0x80..=0x9F)) => {
+                let b1 = cont(bytes, 1)?;
+                let b2 = cont(bytes, 2)?;
+                Some((b1 << 6 | b2, 3))
+            }
+            0xF0 if profile.allow_four_byte && matches!(bytes.get(1), Some(0x80..=0x8F)) => {
+                let b1 = cont(bytes, 1)?;
+                let b2 = cont(bytes, 2)?;
+                let b3 = cont(bytes, 3)?;
+                Some((b1 << 12 | b2 << 6 | b3, 4))
+            }
+            _ => None,
+        };
+        if let Some((value, len)) = overlong {
+            return char::from_u32(value).map(|c| (c, len));
+        }
+    }
+    if profile.allow_surrogates
+        && lead == 0xED
+        && matches!(bytes.get(1), Some(0xA0..=0xAF))
+        && bytes.get(3) == Some(&0xED)
+        && matches!(bytes.get(4), Some(0xB0..=0xBF))
+    {
+        let high = 0xD000 | cont(bytes, 1)? << 6 | cont(bytes, 2)?;
+        let low = 0xD000 | cont(bytes, 4)? << 6 | cont(bytes, 5)?;
+        let value = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        return char::from_u32(value).map(|c| (c, 6));
+    }
+    None
+}
@@ -1,9 +1,13 @@
 use super::*;
 use std::error::Error;
 use std::fmt;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read};
+use std::iter;
+use std::mem;
 use std::str;
 use std::string::String;
+use std::sync::mpsc::Sender;
+use std::vec::Vec;
 
 /// Wraps a `std::io::BufRead` buffered byte stream and decode it as UTF-8.
 ///
@@ -45,6 +49,9 @@ pub struct BufReadDecoder<B: BufRead> {
     buf_read: B,
     bytes_consumed: usize,
     incomplete: Incomplete,
+    position: usize,
+    /// Whether to drop a UTF-8 BOM at the start of the first chunk.
+    skip_bom: bool,
 }
 
 /// Error returned by [`BufReadDecoder::next_strict()`].
@@ -68,6 +75,20 @@ impl<'a> BufReadDecoderError<'a> {
             BufReadDecoderError::InvalidByteSequence(_) => Ok(REPLACEMENT_CHARACTER),
         }
     }
+
+    /// The I/O error from the underlying byte stream, if this is one.
+    pub fn as_io_error(&self) -> Option<&io::Error> {
+        match self {
+            BufReadDecoderError::Io(error) => Some(error),
+            BufReadDecoderError::InvalidByteSequence(_) => None,
+        }
+    }
+}
+
+impl<'a> From<io::Error> for BufReadDecoderError<'a> {
+    fn from(error: io::Error) -> Self {
+        BufReadDecoderError::Io(error)
+    }
 }
 
 impl<'a> fmt::Display for BufReadDecoderError<'a> {
@@ -90,23 +111,346 @@ impl<'a> Error for BufReadDecoderError<'a> {
     }
 }
 
+/// Error returned by [`BufReadDecoder::read_to_string_strict()`].
+///
+/// Like [`BufReadDecoderError`], but the invalid bytes are copied out of the stream,
+/// which is gone by the time the error is returned, along with their offset in it.
+#[derive(Debug)]
+pub enum ReadToStringError {
+    /// The first invalid byte sequence in the stream, or a truncated one at its end.
+    InvalidByteSequence {
+        /// The bytes of the sequence.
+        bytes: Vec<u8>,
+        /// The offset of the first of them in the stream.
+        position: usize,
+    },
+
+    /// An I/O error from the underlying byte stream
+    Io(io::Error),
+}
+
+impl From<io::Error> for ReadToStringError {
+    fn from(error: io::Error) -> Self {
+        ReadToStringError::Io(error)
+    }
+}
+
+impl fmt::Display for ReadToStringError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadToStringError::InvalidByteSequence {
+                ref bytes,
+                position,
+            } => write!(
+                f,
+                "invalid byte sequence {:02x?} at byte {}",
+                bytes, position
+            ),
+            ReadToStringError::Io(ref err) => write!(f, "underlying bytestream error: {}", err),
+        }
+    }
+}
+
+impl Error for ReadToStringError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ReadToStringError::InvalidByteSequence { .. } => None,
+            ReadToStringError::Io(ref err) => Some(err),
+        }
+    }
+}
+
 impl<B: BufRead> BufReadDecoder<B> {
     /// This is to `Read::read_to_string` what `String::from_utf8_lossy` is to `String::from_utf8`.
     pub fn read_to_string_lossy(buf_read: B) -> io::Result<String> {
-        let mut decoder = Self::new(buf_read);
         let mut string = String::new();
+        Self::read_to_string_lossy_into(buf_read, &mut string)?;
+        Ok(string)
+    }
+
+    /// Same as [`read_to_string_lossy()`](Self::read_to_string_lossy), but append to `out`
+    /// so that its allocation can be reused. Returns the number of bytes appended.
+    ///
+    /// On an I/O error, what was decoded before it has been appended.
+    ///
+    /// ```
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let mut out = String::from("> ");
+    /// assert_eq!(BufReadDecoder::read_to_string_lossy_into(&b"a\xFF"[..], &mut out).unwrap(), 4);
+    /// assert_eq!(out, "> a\u{FFFD}");
+    /// ```
+    pub fn read_to_string_lossy_into(buf_read: B, out: &mut String) -> io::Result<usize> {
+        let start = out.len();
+        let mut decoder = Self::new(buf_read);
         while let Some(result) = decoder.next_lossy() {
-            string.push_str(result?)
+            out.push_str(result?)
+        }
+        Ok(out.len() - start)
+    }
+
+    /// Same as [`read_to_string_lossy()`](Self::read_to_string_lossy), but insert
+    /// `replacement` instead of U+FFFD for each error. An empty `replacement` drops
+    /// invalid sequences.
+    ///
+    /// ```
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let input = &b"caf\xC3 \xFF"[..];
+    /// assert_eq!(BufReadDecoder::read_to_string_lossy_with(input, "?").unwrap(), "caf? ?");
+    /// ```
+    pub fn read_to_string_lossy_with(buf_read: B, replacement: &str) -> io::Result<String> {
+        let mut decoder = Self::new(buf_read);
+        let mut string = String::new();
+        while let Some(result) = decoder.next_strict() {
+            match result {
+                Ok(s) => string.push_str(s),
+                Err(BufReadDecoderError::InvalidByteSequence(_)) => string.push_str(replacement),
+                Err(BufReadDecoderError::Io(error)) => return Err(error),
+            }
         }
         Ok(string)
     }
 
+    /// Decode the stream into a `String`, failing on the first invalid or truncated byte
+    /// sequence.
+    ///
+    /// This is the strict counterpart of [`read_to_string_lossy()`](Self::read_to_string_lossy).
+    /// A stream that ends in the middle of a code point is reported as invalid, with the
+    /// bytes of the truncated sequence. What was decoded before an error is discarded.
+    ///
+    /// ```
+    /// use utf8_zero::{BufReadDecoder, ReadToStringError};
+    ///
+    /// let output = BufReadDecoder::read_to_string_strict(&b"caf\xC3\xA9"[..]).unwrap();
+    /// assert_eq!(output, "caf\u{E9}");
+    ///
+    /// match BufReadDecoder::read_to_string_strict(&b"caf\xC3"[..]) {
+    ///     Err(ReadToStringError::InvalidByteSequence { bytes, position }) => {
+    ///         assert_eq!((&bytes[..], position), (&b"\xC3"[..], 3));
+    ///     }
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn read_to_string_strict(buf_read: B) -> Result<String, ReadToStringError> {
+        let mut decoder = Self::new(buf_read);
+        let mut string = String::new();
+        while let Some(result) = decoder.next_strict() {
+            match result {
+                Ok(s) => string.push_str(s),
+                Err(BufReadDecoderError::InvalidByteSequence(bytes)) => {
+                    let bytes = bytes.to_vec();
+                    let position = decoder.position() - bytes.len();
+                    return Err(ReadToStringError::InvalidByteSequence { bytes, position });
+                }
+                Err(BufReadDecoderError::Io(error)) => return Err(ReadToStringError::Io(error)),
+            }
+        }
+        Ok(string)
+    }
+
+    /// Lossily decode the stream into owned chunks.
+    ///
+    /// Each chunk is what [`next_lossy()`](Self::next_lossy) would have returned, copied
+    /// into a `String`, so it can outlive the next one at the cost of an allocation.
+    /// Errors come only from the underlying byte stream.
+    ///
+    /// ```
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let chunks: Vec<String> = BufReadDecoder::new(&b"ok\xFFok"[..])
+    ///     .into_lossy_chunks()
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(chunks.concat(), "ok\u{FFFD}ok");
+    /// ```
+    pub fn into_lossy_chunks(mut self) -> impl Iterator<Item = io::Result<String>> {
+        iter::from_fn(move || Some(self.next_lossy()?.map(String::from)))
+    }
+
+    /// Lossily decode the stream one `char` at a time.
+    ///
+    /// Each invalid sequence yields one U+FFFD. Errors come only from the underlying
+    /// byte stream.
+    ///
+    /// ```
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let chars: String = BufReadDecoder::new(&b"a\xFF\xC3\xA9"[..])
+    ///     .chars_lossy()
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(chars, "a\u{FFFD}\u{E9}");
+    /// ```
+    pub fn chars_lossy(mut self) -> impl Iterator<Item = io::Result<char>> {
+        // The current chunk, copied out of the decoder, and how much of it was yielded.
+        let mut chunk = String::new();
+        let mut position = 0;
+        iter::from_fn(move || loop {
+            if let Some(c) = chunk[position..].chars().next() {
+                position += c.len_utf8();
+                return Some(Ok(c));
+            }
+            chunk.clear();
+            position = 0;
+            match self.next_lossy()? {
+                Ok(s) => chunk.push_str(s),
+                Err(error) => return Some(Err(error)),
+            }
+        })
+    }
+
+    /// Lossily decode the stream line by line.
+    ///
+    /// Lines are split on `\n`, which is removed along with a `\r` before it. A final
+    /// line without a newline is yielded too. Invalid bytes are replaced with U+FFFD,
+    /// and since a newline is never part of an invalid sequence, a replacement never
+    /// straddles two lines.
+    ///
+    /// ```
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let input = &b"one\r\ntw\xF0\nthree"[..];
+    /// let lines: Vec<String> = BufReadDecoder::new(input)
+    ///     .lines_lossy()
+    ///     .collect::<Result<_, _>>()
+    ///     .unwrap();
+    /// assert_eq!(lines, ["one", "tw\u{FFFD}", "three"]);
+    /// ```
+    pub fn lines_lossy(mut self) -> impl Iterator<Item = io::Result<String>> {
+        let mut line = String::new();
+        // How much of `line` is known not to contain a newline.
+        let mut searched = 0;
+        let mut eof = false;
+        iter::from_fn(move || loop {
+            if let Some(newline) = line[searched..].find('\n') {
+                let rest = line.split_off(searched + newline + 1);
+                let mut complete = mem::replace(&mut line, rest);
+                searched = 0;
+                complete.pop();
+                if complete.ends_with('\r') {
+                    complete.pop();
+                }
+                return Some(Ok(complete));
+            }
+            searched = line.len();
+            if eof {
+                return None;
+            }
+            match self.next_lossy() {
+                Some(Ok(s)) => line.push_str(s),
+                Some(Err(error)) => return Some(Err(error)),
+                None => {
+                    eof = true;
+                    if !line.is_empty() {
+                        searched = 0;
+                        return Some(Ok(mem::take(&mut line)));
+                    }
+                }
+            }
+        })
+    }
+
     /// Wrap a buffered byte stream for UTF-8 decoding.
     pub fn new(buf_read: B) -> Self {
         Self {
             buf_read,
             bytes_consumed: 0,
             incomplete: Incomplete::empty(),
+            position: 0,
+            skip_bom: false,
+        }
+    }
+
+    /// Same as [`new()`](Self::new), but drop a UTF-8 byte order mark at the start of
+    /// the stream, as [`skip_bom()`](crate::skip_bom) does for a slice.
+    ///
+    /// The BOM is skipped even if it is split across fills of the stream's buffer. One
+    /// later in the stream is U+FEFF content and is kept. [`position()`](Self::position)
+    /// still counts its bytes.
+    ///
+    /// ```
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let mut decoder = BufReadDecoder::new_skip_bom(&b"\xEF\xBB\xBFtext\xEF\xBB\xBF"[..]);
+    /// assert_eq!(decoder.next_strict().unwrap().unwrap(), "text\u{FEFF}");
+    /// ```
+    pub fn new_skip_bom(buf_read: B) -> Self {
+        Self {
+            skip_bom: true,
+            ..Self::new(buf_read)
+        }
+    }
+
+    /// The offset in the stream of the end of the last chunk returned by
+    /// [`next_strict()`](Self::next_strict) or [`next_lossy()`](Self::next_lossy).
+    ///
+    /// This counts the bytes read from the underlying stream, minus those of an
+    /// incomplete sequence held by the decoder. After an
+    /// [`InvalidByteSequence(bytes)`](BufReadDecoderError::InvalidByteSequence), the
+    /// sequence starts at `position() - bytes.len()`.
+    ///
+    /// ```
+    /// use utf8_zero::{BufReadDecoder, BufReadDecoderError};
+    ///
+    /// let mut decoder = BufReadDecoder::new(&b"ok\xC3\xA9\xFF!"[..]);
+    /// assert_eq!(decoder.next_strict().unwrap().unwrap(), "ok\u{E9}");
+    /// assert_eq!(decoder.position(), 4);
+    /// let len = match decoder.next_strict() {
+    ///     Some(Err(BufReadDecoderError::InvalidByteSequence(bytes))) => bytes.len(),
+    ///     _ => unreachable!(),
+    /// };
+    /// assert_eq!(decoder.position() - len, 4);
+    /// ```
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Borrow the underlying byte stream.
+    ///
+    /// Its buffer may still start with the bytes of the chunk last returned by
+    /// [`next_strict()`](Self::next_strict), which are only consumed on the next call.
+    pub fn get_ref(&self) -> &B {
+        &self.buf_read
+    }
+
+    /// Mutably borrow the underlying byte stream.
+    ///
+    /// The bytes of chunks already returned are consumed first, so reading from it picks
+    /// up where decoding left off, after any incomplete sequence held by the decoder.
+    pub fn get_mut(&mut self) -> &mut B {
+        self.consume_pending();
+        &mut self.buf_read
+    }
+
+    /// Unwrap the underlying byte stream, positioned after everything decoded so far.
+    ///
+    /// A code point split across two fills of the stream's buffer is read out of it before
+    /// being decoded. If an I/O error interrupted that, its first bytes are held by the
+    /// decoder. They are returned as the [`Incomplete`], rather than being lost. Otherwise
+    /// it is empty.
+    ///
+    /// ```
+    /// use std::io::Read;
+    /// use utf8_zero::BufReadDecoder;
+    ///
+    /// let mut decoder = BufReadDecoder::new(&b"head\xC3\xA9\xFFraw"[..]);
+    /// assert_eq!(decoder.next_strict().unwrap().unwrap(), "head\u{E9}");
+    /// let (mut rest, incomplete) = decoder.into_inner();
+    /// assert!(incomplete.is_empty());
+    /// let mut raw = Vec::new();
+    /// rest.read_to_end(&mut raw).unwrap();
+    /// assert_eq!(raw, b"\xFFraw");
+    /// ```
+    pub fn into_inner(mut self) -> (B, Incomplete) {
+        self.consume_pending();
+        (self.buf_read, self.incomplete)
+    }
+
+    fn consume_pending(&mut self) {
+        if self.bytes_consumed > 0 {
+            self.buf_read.consume(self.bytes_consumed);
+            self.bytes_consumed = 0;
         }
     }
 
@@ -123,78 +467,275 @@ impl<B: BufRead> BufReadDecoder<B> {
     /// This is similar to `Iterator::next`,
     /// except that decoded chunks borrow the decoder (~iterator)
     /// so they need to be handled or copied before the next chunk can start decoding.
+    ///
+    /// Nothing is copied: a chunk, or the bytes of an
+    /// [`InvalidByteSequence`](BufReadDecoderError::InvalidByteSequence), borrows the
+    /// buffer of the underlying `BufRead`, whose bytes are only consumed at the start of
+    /// the next call. The exception is a code point or invalid sequence split across
+    /// fills of that buffer, which is pieced together in a small buffer of the decoder and
+    /// borrowed from there. Either way, the `&mut self` borrow is what keeps the bytes from
+    /// being overwritten while the result is alive.
+    ///
+    /// An input that ends in the middle of a code point yields a final
+    /// `InvalidByteSequence` with the bytes of the truncated sequence.
     pub fn next_strict(&mut self) -> Option<Result<&str, BufReadDecoderError<'_>>> {
-        enum BytesSource {
-            BufRead(usize),
-            Incomplete,
-        }
-        macro_rules! try_io {
-            ($io_result: expr) => {
-                match $io_result {
-                    Ok(value) => value,
-                    Err(error) => return Some(Err(BufReadDecoderError::Io(error))),
-                }
-            };
+        let (source, valid) = match self.next_source()? {
+            Ok(next) => next,
+            Err(error) => return Some(Err(BufReadDecoderError::Io(error))),
+        };
+        let bytes = match self.source_bytes(source) {
+            Ok(bytes) => bytes,
+            Err(error) => return Some(Err(BufReadDecoderError::Io(error))),
+        };
+        if valid {
+            Some(Ok(unsafe { str::from_utf8_unchecked(bytes) }))
+        } else {
+            Some(Err(BufReadDecoderError::InvalidByteSequence(bytes)))
         }
-        let (source, result) = loop {
-            if self.bytes_consumed > 0 {
-                self.buf_read.consume(self.bytes_consumed);
-                self.bytes_consumed = 0;
-            }
-            let buf = try_io!(self.buf_read.fill_buf());
+    }
 
-            // Force loop iteration to go through an explicit `continue`
-            enum Unreachable {}
-            let _: Unreachable = if self.incomplete.is_empty() {
-                if buf.is_empty() {
-                    return None; // EOF
-                }
-                match str::from_utf8(buf) {
-                    Ok(_) => break (BytesSource::BufRead(buf.len()), Ok(())),
-                    Err(error) => {
-                        let valid_up_to = error.valid_up_to();
-                        if valid_up_to > 0 {
-                            break (BytesSource::BufRead(valid_up_to), Ok(()));
-                        }
-                        match error.error_len() {
-                            Some(invalid_sequence_length) => {
-                                break (BytesSource::BufRead(invalid_sequence_length), Err(()))
-                            }
-                            None => {
-                                self.bytes_consumed = buf.len();
-                                self.incomplete = Incomplete::new(buf);
-                                // need more input bytes
-                                continue;
-                            }
+    /// Find the next chunk, without borrowing its bytes yet. `Ok((source, valid))`.
+    fn next_source(&mut self) -> Option<io::Result<(BytesSource, bool)>> {
+        let result = loop {
+            self.consume_pending();
+            let buf = match self.buf_read.fill_buf() {
+                Ok(buf) => buf,
+                Err(error) => return Some(Err(error)),
+            };
+            match next_chunk(buf, &mut self.incomplete, &mut self.bytes_consumed) {
+                NextChunk::Found(source, valid) => break (source, valid),
+                NextChunk::NeedMore => continue,
+                NextChunk::Eof => return None,
+            }
+        };
+        if mem::take(&mut self.skip_bom) && result.1 {
+            match result.0 {
+                BytesSource::BufRead(byte_count) => {
+                    let buf = match self.buf_read.fill_buf() {
+                        Ok(buf) => buf,
+                        Err(error) => return Some(Err(error)),
+                    };
+                    if buf[..byte_count].starts_with(BOM) {
+                        self.buf_read.consume(BOM.len());
+                        self.position += BOM.len();
+                        if byte_count == BOM.len() {
+                            return self.next_source();
                         }
+                        return Some(Ok((BytesSource::BufRead(byte_count - BOM.len()), true)));
                     }
                 }
-            } else {
-                if buf.is_empty() {
-                    break (BytesSource::Incomplete, Err(())); // EOF with incomplete code point
-                }
-                let (consumed, opt_result) = self.incomplete.try_complete_offsets(buf);
-                self.bytes_consumed = consumed;
-                match opt_result {
-                    None => {
-                        // need more input bytes
-                        continue;
+                BytesSource::Incomplete => {
+                    let len = self.incomplete.as_bytes().len();
+                    if self.incomplete.as_bytes().starts_with(BOM) {
+                        self.incomplete.buffer.copy_within(BOM.len()..len, 0);
+                        self.incomplete.buffer_len -= BOM.len() as u8;
+                        self.position += BOM.len();
+                        if len == BOM.len() {
+                            return self.next_source();
+                        }
                     }
-                    Some(result) => break (BytesSource::Incomplete, result),
                 }
-            };
-        };
+            }
+        }
+        Some(Ok(result))
+    }
+
+    /// Borrow the bytes of a chunk found by `next_source()`.
+    fn source_bytes(&mut self, source: BytesSource) -> io::Result<&[u8]> {
         let bytes = match source {
             BytesSource::BufRead(byte_count) => {
                 self.bytes_consumed = byte_count;
-                let buf = try_io!(self.buf_read.fill_buf());
-                &buf[..byte_count]
+                &self.buf_read.fill_buf()?[..byte_count]
             }
             BytesSource::Incomplete => self.incomplete.take_buffer(),
         };
-        match result {
-            Ok(()) => Some(Ok(unsafe { str::from_utf8_unchecked(bytes) })),
-            Err(()) => Some(Err(BufReadDecoderError::InvalidByteSequence(bytes))),
+        self.position += bytes.len();
+        Ok(bytes)
+    }
+}
+
+/// What a fill of the buffer holds next, from [`next_chunk()`].
+pub(crate) enum NextChunk {
+    /// A chunk, and whether it is valid UTF-8 rather than an invalid sequence.
+    Found(BytesSource, bool),
+    /// The fill only started a code point, and was moved into the `Incomplete`.
+    NeedMore,
+    /// The stream ended with nothing left to decode.
+    Eof,
+}
+
+/// Find the next chunk in `buf`, the current fill of the buffer of a buffered reader,
+/// after any bytes held in `incomplete`.
+///
+/// This is the decoding core shared by [`BufReadDecoder`] and, with the `futures`
+/// feature, `AsyncBufReadDecoder`, so that they decode the same way. `*bytes_consumed`
+/// is set to how many bytes of `buf` were moved into `incomplete`, which the caller
+/// consumes before the next fill. An empty `buf` means end of stream.
+pub(crate) fn next_chunk(
+    buf: &[u8],
+    incomplete: &mut Incomplete,
+    bytes_consumed: &mut usize,
+) -> NextChunk {
+    if incomplete.is_empty() {
+        if buf.is_empty() {
+            return NextChunk::Eof;
         }
+        match str::from_utf8(buf) {
+            Ok(_) => NextChunk::Found(BytesSource::BufRead(buf.len()), true),
+            Err(error) => {
+                let valid_up_to = error.valid_up_to();
+                if valid_up_to > 0 {
+                    return NextChunk::Found(BytesSource::BufRead(valid_up_to), true);
+                }
+                match error.error_len() {
+                    Some(invalid_sequence_length) => {
+                        NextChunk::Found(BytesSource::BufRead(invalid_sequence_length), false)
+                    }
+                    None => {
+                        *bytes_consumed = buf.len();
+                        *incomplete = Incomplete::new(buf);
+                        NextChunk::NeedMore
+                    }
+                }
+            }
+        }
+    } else {
+        if buf.is_empty() {
+            // EOF with incomplete code point
+            return NextChunk::Found(BytesSource::Incomplete, false);
+        }
+        let (consumed, opt_result) = incomplete.try_complete_offsets(buf);
+        *bytes_consumed = consumed;
+        match opt_result {
+            None => NextChunk::NeedMore,
+            Some(result) => NextChunk::Found(BytesSource::Incomplete, result.is_ok()),
+        }
+    }
+}
+
+/// Where the bytes of the next chunk of a [`BufReadDecoder`] are.
+pub(crate) enum BytesSource {
+    /// The first bytes of the `BufRead` buffer.
+    BufRead(usize),
+    /// The buffer of the decoder's `Incomplete`.
+    Incomplete,
+}
+
+/// Wraps a `std::io::Read` and lossily decodes its bytes as UTF-8, producing bytes.
+///
+/// Reading from it gives the same bytes as [`BufReadDecoder::read_to_string_lossy`]
+/// would produce as a string: valid runs of the input with U+FFFD for each invalid
+/// sequence. So anything read from it is valid UTF-8, and a `read` never ends in the
+/// middle of a code point unless the buffer is too small for the next one; the rest of
+/// it is then returned by the next `read`.
+///
+/// ```
+/// use std::io::Read;
+/// use utf8_zero::Utf8LossyReader;
+///
+/// let mut output = String::new();
+/// Utf8LossyReader::new(&b"Hello\xC0World"[..])
+///     .read_to_string(&mut output)
+///     .unwrap();
+/// assert_eq!(output, "Hello\u{FFFD}World");
+/// ```
+pub struct Utf8LossyReader<R: Read> {
+    decoder: BufReadDecoder<io::BufReader<R>>,
+    /// The part of the last decoded chunk that didn't fit in the caller's buffer.
+    pending: Vec<u8>,
+    pending_start: usize,
+}
+
+impl<R: Read> Utf8LossyReader<R> {
+    /// Wrap a byte stream for lossy UTF-8 decoding.
+    pub fn new(read: R) -> Self {
+        Utf8LossyReader {
+            decoder: BufReadDecoder::new(io::BufReader::new(read)),
+            pending: Vec::new(),
+            pending_start: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Utf8LossyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pending_start < self.pending.len() {
+            let pending = &self.pending[self.pending_start..];
+            let len = fitting_len(pending, buf.len());
+            buf[..len].copy_from_slice(&pending[..len]);
+            self.pending_start += len;
+            return Ok(len);
+        }
+        let chunk = match self.decoder.next_lossy() {
+            Some(chunk) => chunk?.as_bytes(),
+            None => return Ok(0),
+        };
+        let len = fitting_len(chunk, buf.len());
+        buf[..len].copy_from_slice(&chunk[..len]);
+        self.pending.clear();
+        self.pending.extend_from_slice(&chunk[len..]);
+        self.pending_start = 0;
+        Ok(len)
+    }
+}
+
+/// How much of the UTF-8 `bytes` to copy into a buffer of `max` bytes: as much as fits
+/// while ending on a code point boundary, or `max` if not even one code point fits.
+fn fitting_len(bytes: &[u8], max: usize) -> usize {
+    if bytes.len() <= max {
+        return bytes.len();
+    }
+    match (1..=max).rev().find(|&i| (bytes[i] as i8) >= -0x40) {
+        Some(len) => len,
+        None => max,
+    }
+}
+
+/// Lossily decode everything from `reader`, sending it down `tx` in owned chunks.
+///
+/// Each chunk is roughly `chunk` bytes of decoded text and always ends on a code point
+/// boundary; code points split across reads are pieced back together. Concatenating
+/// the received chunks gives the same result as [`BufReadDecoder::read_to_string_lossy`].
+/// Returns once `reader` reaches EOF, after sending the last chunk.
+///
+/// Meant to be run on a producer thread, with the consumer holding the receiver.
+/// If the receiver hangs up, this stops early with an `io::ErrorKind::BrokenPipe` error.
+///
+/// ```
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// let (tx, rx) = mpsc::channel();
+/// let producer = thread::spawn(move || {
+///     utf8_zero::decode_to_channel(&b"Hello \xF0\x9F\x8C\x8D\xC0world"[..], tx, 4)
+/// });
+/// let output: String = rx.iter().collect();
+/// producer.join().unwrap().unwrap();
+/// assert_eq!(output, "Hello \u{1F30D}\u{FFFD}world");
+/// ```
+pub fn decode_to_channel<R: Read + Send>(
+    reader: R,
+    tx: Sender<String>,
+    chunk: usize,
+) -> io::Result<()> {
+    let chunk = cmp::max(chunk, 1);
+    let mut decoder = BufReadDecoder::new(io::BufReader::with_capacity(chunk, reader));
+    let mut string = String::new();
+    let send = |string: String| {
+        tx.send(string)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "receiver hung up"))
+    };
+    while let Some(result) = decoder.next_lossy() {
+        string.push_str(result?);
+        if string.len() >= chunk {
+            send(std::mem::take(&mut string))?;
+        }
+    }
+    if !string.is_empty() {
+        send(string)?;
     }
+    Ok(())
 }
@@ -0,0 +1,42 @@
+use super::Incomplete;
+use core::fmt;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Serialized as its buffered bytes, `buffer[..buffer_len]`.
+impl Serialize for Incomplete {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_bytes())
+    }
+}
+
+/// Deserialized from at most 4 bytes, as by [`Incomplete::try_new()`].
+impl<'de> Deserialize<'de> for Incomplete {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(IncompleteVisitor)
+    }
+}
+
+struct IncompleteVisitor;
+
+impl<'de> Visitor<'de> for IncompleteVisitor {
+    type Value = Incomplete;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("at most 4 bytes")
+    }
+
+    fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<Incomplete, E> {
+        Incomplete::try_new(bytes).ok_or_else(|| E::invalid_length(bytes.len(), &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Incomplete, A::Error> {
+        let mut incomplete = Incomplete::empty();
+        while let Some(byte) = seq.next_element()? {
+            if incomplete.push(byte).is_err() {
+                return Err(A::Error::invalid_length(5, &self));
+            }
+        }
+        Ok(incomplete)
+    }
+}
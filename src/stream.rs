@@ -0,0 +1,105 @@
+use super::*;
+
+/// A lossy streaming decoder that hands out borrowed `&str` and never allocates.
+///
+/// Each call to [`feed()`](StreamDecoder::feed) returns an iterator of string slices for
+/// the chunk, in order: valid runs borrowed from the chunk, `"\u{FFFD}"` for each
+/// invalid sequence, and any code point split across the previous chunk and this one.
+/// Such a code point cannot borrow from either chunk, so it is spliced together in a
+/// small buffer owned by the decoder and yielded as a `&str` borrowing that buffer.
+///
+/// This is why the iterator borrows the decoder mutably for as long as it borrows the
+/// chunk: the slices must be used (or copied) before the next `feed()`, which may
+/// overwrite the buffer. The compiler enforces this.
+///
+/// Unlike [`LossyDecoder`], the end of input is not signalled on drop: call
+/// [`finish()`](StreamDecoder::finish) to get the replacement for a trailing incomplete
+/// sequence.
+///
+/// ```
+/// use utf8_zero::StreamDecoder;
+///
+/// let mut decoder = StreamDecoder::new();
+/// let mut output = String::new();
+/// for chunk in [&b"caf\xC3"[..], b"\xA9 \xFF!", b"\xE2\x82"] {
+///     output.extend(decoder.feed(chunk));
+/// }
+/// output.extend(decoder.finish());
+/// assert_eq!(output, "caf\u{E9} \u{FFFD}!\u{FFFD}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamDecoder {
+    incomplete: Incomplete,
+    splice: [u8; 4],
+}
+
+impl Default for StreamDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamDecoder {
+    /// Create a new decoder with nothing buffered.
+    pub fn new() -> Self {
+        StreamDecoder {
+            incomplete: Incomplete::empty(),
+            splice: [0; 4],
+        }
+    }
+
+    /// Feed one chunk of input, returning the decoded text as borrowed slices.
+    ///
+    /// A trailing incomplete sequence in `input` is held back until the next chunk.
+    pub fn feed<'s>(&'s mut self, input: &'s [u8]) -> impl Iterator<Item = &'s str> + 's {
+        let mut first = None;
+        let mut input = input;
+        if !self.incomplete.is_empty() {
+            match self.incomplete.try_complete(input) {
+                Some((Ok(s), remaining)) => {
+                    let len = s.len();
+                    self.splice[..len].copy_from_slice(s.as_bytes());
+                    first = Some(len);
+                    input = remaining;
+                }
+                Some((Err(_), remaining)) => {
+                    first = Some(0);
+                    input = remaining;
+                }
+                None => input = &[],
+            }
+        }
+        let body_len = input.len() - trailing_incomplete_len(input);
+        if body_len < input.len() {
+            self.incomplete = Incomplete::new(&input[body_len..]);
+        }
+        let body = &input[..body_len];
+
+        let this: &'s Self = self;
+        let first = first.map(|len| match len {
+            0 => REPLACEMENT_CHARACTER,
+            // Safety: `splice[..len]` was copied from a `&str`.
+            len => unsafe { str::from_utf8_unchecked(&this.splice[..len]) },
+        });
+        // With the trailing incomplete sequence split off above, every invalid part of a
+        // chunk is a real error.
+        let rest = utf8_chunks(body).flat_map(|chunk| {
+            let valid = Some(chunk.valid).filter(|valid| !valid.is_empty());
+            let replacement = (!chunk.invalid.is_empty()).then_some(REPLACEMENT_CHARACTER);
+            valid.into_iter().chain(replacement)
+        });
+        first.into_iter().chain(rest)
+    }
+
+    /// Signal the end of input.
+    ///
+    /// Returns `"\u{FFFD}"` if an incomplete sequence was held back from the last chunk,
+    /// and resets the decoder for a new stream.
+    pub fn finish(&mut self) -> Option<&'static str> {
+        if self.incomplete.is_empty() {
+            return None;
+        }
+        self.incomplete = Incomplete::empty();
+        Some(REPLACEMENT_CHARACTER)
+    }
+}
@@ -0,0 +1,215 @@
+use super::*;
+
+/// A push-based, strict decoder for UTF-8.
+///
+/// Like [`LossyDecoder`], bytes are pushed in chunks and decoded text is pushed as `&str`
+/// slices into a callback, with code points split across chunks pieced back together.
+/// Unlike it, nothing is replaced: [`feed()`](StrictDecoder::feed) stops and returns an
+/// error at the first invalid sequence, and [`finish()`](StrictDecoder::finish) returns
+/// one if the input ended in the middle of a code point.
+///
+/// After an error the decoder has consumed the input up to the end of the invalid
+/// sequence, and nothing is buffered. To skip the bad bytes and carry on, feed the rest
+/// of the chunk, which starts at `error.byte_offset + error.invalid_len` in the stream.
+///
+/// ```
+/// use utf8_zero::{DecodeErrorKind, StrictDecoder, StrictDecoderError};
+///
+/// let mut output = String::new();
+/// let mut decoder = StrictDecoder::new(|s| output.push_str(s));
+/// assert!(decoder.feed(b"caf\xC3").is_ok());
+/// let error = decoder.feed(b"\xA9 \xFF!").unwrap_err();
+/// assert_eq!(
+///     error,
+///     StrictDecoderError { byte_offset: 6, invalid_len: 1, kind: DecodeErrorKind::Invalid }
+/// );
+/// assert_eq!(decoder.position(), 7);
+/// assert!(decoder.feed(b"!").is_ok());
+/// assert!(decoder.finish().is_ok());
+/// assert_eq!(output, "caf\u{E9} !");
+/// ```
+pub struct StrictDecoder<F: FnMut(&str)> {
+    push_str: F,
+    policy: ReplacementPolicy,
+    incomplete: Incomplete,
+    position: usize,
+    /// Where the last invalid sequence ended, to coalesce adjacent ones.
+    last_invalid_end: Option<usize>,
+}
+
+/// Error from a [`StrictDecoder`], locating the failure in the stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StrictDecoderError {
+    /// Offset of the first byte of the bad sequence, counting all bytes passed to
+    /// [`feed()`](StrictDecoder::feed).
+    pub byte_offset: usize,
+    /// Length of the bad sequence. It may have started in an earlier chunk.
+    pub invalid_len: usize,
+    /// Whether the input was invalid or ended with an incomplete code point.
+    pub kind: DecodeErrorKind,
+}
+
+impl fmt::Display for StrictDecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            DecodeErrorKind::Invalid => "invalid",
+            DecodeErrorKind::Incomplete => "incomplete",
+        };
+        write!(
+            f,
+            "found {} byte sequence at offset {}",
+            kind, self.byte_offset
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StrictDecoderError {}
+
+impl<F: FnMut(&str)> StrictDecoder<F> {
+    /// Create a new decoder from a callback.
+    #[inline]
+    pub fn new(push_str: F) -> Self {
+        StrictDecoder {
+            push_str,
+            policy: ReplacementPolicy::Strict,
+            incomplete: Incomplete::empty(),
+            position: 0,
+            last_invalid_end: None,
+        }
+    }
+
+    /// Create a new decoder that handles errors according to `policy`.
+    ///
+    /// With any policy other than [`ReplacementPolicy::Strict`], invalid sequences are
+    /// replaced or dropped instead of reported, so neither [`feed()`](StrictDecoder::feed)
+    /// nor [`finish()`](StrictDecoder::finish) returns an error.
+    ///
+    /// ```
+    /// use utf8_zero::{ReplacementPolicy, StrictDecoder};
+    ///
+    /// let mut output = String::new();
+    /// let mut decoder = StrictDecoder::with_policy(ReplacementPolicy::Skip, |s| output.push_str(s));
+    /// assert!(decoder.feed(b"a\xFFb\xC3").is_ok());
+    /// assert!(decoder.finish().is_ok());
+    /// assert_eq!(output, "ab");
+    /// ```
+    #[inline]
+    pub fn with_policy(policy: ReplacementPolicy, push_str: F) -> Self {
+        StrictDecoder {
+            policy,
+            ..Self::new(push_str)
+        }
+    }
+
+    /// The number of bytes consumed so far, including a buffered incomplete sequence.
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Feed one chunk of input into the decoder.
+    ///
+    /// Valid text is pushed to the callback up to the first invalid sequence, if any,
+    /// for which an error is returned. The rest of the chunk is not decoded. Other
+    /// policies than the default [`ReplacementPolicy::Strict`] decode the whole chunk.
+    pub fn feed(&mut self, mut input: &[u8]) -> Result<(), StrictDecoderError> {
+        let start = self.position;
+        let end = start + input.len();
+        self.position = end;
+        if self.incomplete.buffer_len > 0 {
+            let offset = start - self.incomplete.buffer_len as usize;
+            match self.incomplete.try_complete(input) {
+                Some((Ok(s), remaining)) => {
+                    (self.push_str)(s);
+                    input = remaining
+                }
+                Some((Err(invalid), remaining)) => {
+                    let invalid_len = invalid.len();
+                    self.invalid(offset, invalid_len, end - remaining.len())?;
+                    input = remaining
+                }
+                None => return Ok(()),
+            }
+        }
+        loop {
+            match decode(input) {
+                Ok(s) => {
+                    (self.push_str)(s);
+                    return Ok(());
+                }
+                Err(DecodeError::Incomplete {
+                    valid_prefix,
+                    incomplete_suffix,
+                }) => {
+                    (self.push_str)(valid_prefix);
+                    self.incomplete = incomplete_suffix;
+                    return Ok(());
+                }
+                Err(DecodeError::Invalid {
+                    valid_prefix,
+                    invalid_sequence,
+                    remaining_input,
+                }) => {
+                    (self.push_str)(valid_prefix);
+                    let consumed = end - remaining_input.len();
+                    let invalid_len = invalid_sequence.len();
+                    self.invalid(consumed - invalid_len, invalid_len, consumed)?;
+                    input = remaining_input
+                }
+            }
+        }
+    }
+
+    /// Handle an invalid sequence according to the policy. For the strict policy, stop
+    /// after it, with `consumed` bytes of the stream consumed.
+    fn invalid(
+        &mut self,
+        byte_offset: usize,
+        invalid_len: usize,
+        consumed: usize,
+    ) -> Result<(), StrictDecoderError> {
+        let result = self.bad_sequence(byte_offset, invalid_len, DecodeErrorKind::Invalid);
+        if result.is_err() {
+            self.position = consumed;
+        }
+        result
+    }
+
+    /// Push the replacement for a bad sequence, or return the error for it for the
+    /// strict policy.
+    fn bad_sequence(
+        &mut self,
+        byte_offset: usize,
+        invalid_len: usize,
+        kind: DecodeErrorKind,
+    ) -> Result<(), StrictDecoderError> {
+        let replacement =
+            self.policy
+                .replacement(&mut self.last_invalid_end, byte_offset, invalid_len, kind);
+        let Some((replacement, count)) = replacement else {
+            return Err(StrictDecoderError {
+                byte_offset,
+                invalid_len,
+                kind,
+            });
+        };
+        for _ in 0..count {
+            (self.push_str)(replacement)
+        }
+        Ok(())
+    }
+
+    /// Signal the end of the input.
+    ///
+    /// Returns an error if the input ended with an incomplete byte sequence, or handles
+    /// it like an invalid one for other policies.
+    pub fn finish(mut self) -> Result<(), StrictDecoderError> {
+        let invalid_len = self.incomplete.buffer_len as usize;
+        if invalid_len == 0 {
+            return Ok(());
+        }
+        let byte_offset = self.position - invalid_len;
+        self.bad_sequence(byte_offset, invalid_len, DecodeErrorKind::Incomplete)
+    }
+}
@@ -0,0 +1,515 @@
+use super::*;
+use std::borrow::Cow;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// Lossily decode `input`, recording for every input byte whether it was valid.
+///
+/// `out` is cleared and filled with `input.len()` booleans: `true` for bytes that were
+/// part of valid UTF-8, `false` for bytes that were replaced with U+FFFD (including a
+/// trailing incomplete sequence).
+///
+/// ```
+/// use utf8_zero::decode_validity_mask;
+///
+/// let mut mask = Vec::new();
+/// let s = decode_validity_mask(b"a\xFFb", &mut mask);
+/// assert_eq!(s, "a\u{FFFD}b");
+/// assert_eq!(mask, [true, false, true]);
+/// ```
+pub fn decode_validity_mask(input: &[u8], out: &mut Vec<bool>) -> String {
+    out.clear();
+    out.reserve(input.len());
+    let mut string = String::with_capacity(input.len());
+    let mut input = input;
+    loop {
+        match decode(input) {
+            Ok(s) => {
+                string.push_str(s);
+                out.resize(out.len() + s.len(), true);
+                return string;
+            }
+            Err(DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            }) => {
+                string.push_str(valid_prefix);
+                string.push_str(REPLACEMENT_CHARACTER);
+                out.resize(out.len() + valid_prefix.len(), true);
+                out.resize(out.len() + incomplete_suffix.buffer_len as usize, false);
+                return string;
+            }
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            }) => {
+                string.push_str(valid_prefix);
+                string.push_str(REPLACEMENT_CHARACTER);
+                out.resize(out.len() + valid_prefix.len(), true);
+                out.resize(out.len() + invalid_sequence.len(), false);
+                input = remaining_input;
+            }
+        }
+    }
+}
+
+/// Append the offset and length of every invalid sequence in `input` to `out`.
+///
+/// One `(offset, len)` pair is appended per maximal invalid subpart, where lossy
+/// decoding would insert U+FFFD, plus one for a trailing incomplete sequence. `len` is
+/// 1 to 3. Offsets are `u32` to keep the index compact, so this does not support inputs
+/// longer than 4 GiB.
+///
+/// # Panics
+///
+/// Panics if an error is found at an offset that does not fit in a `u32`.
+///
+/// ```
+/// use utf8_zero::error_ranges;
+///
+/// let mut errors = Vec::new();
+/// error_ranges(b"a\xFFb\xF0\x9F!\xE2\x82", &mut errors);
+/// assert_eq!(errors, [(1, 1), (3, 2), (6, 2)]);
+/// ```
+pub fn error_ranges(input: &[u8], out: &mut Vec<(u32, u8)>) {
+    let mut remaining = input;
+    loop {
+        let (valid_len, invalid_len, rest) = match decode(remaining) {
+            Ok(_) => return,
+            Err(DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            }) => (
+                valid_prefix.len(),
+                incomplete_suffix.buffer_len as usize,
+                None,
+            ),
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            }) => (
+                valid_prefix.len(),
+                invalid_sequence.len(),
+                Some(remaining_input),
+            ),
+        };
+        let offset = input.len() - remaining.len() + valid_len;
+        let offset = u32::try_from(offset).expect("error offset does not fit in a u32");
+        out.push((offset, invalid_len as u8));
+        match rest {
+            Some(rest) => remaining = rest,
+            None => return,
+        }
+    }
+}
+
+/// Decode `input`, or describe why it is not valid UTF-8.
+///
+/// The message is the [`Display`](core::fmt::Display) output of the [`DecodeError`].
+///
+/// ```
+/// use utf8_zero::decode_or_message;
+///
+/// assert_eq!(decode_or_message(b"ok"), Ok("ok"));
+/// assert_eq!(
+///     decode_or_message(b"a\xFFb").unwrap_err(),
+///     "found invalid byte sequence [ff] after 1 valid bytes, followed by 1 more unprocessed bytes"
+/// );
+/// ```
+pub fn decode_or_message(input: &[u8]) -> Result<&str, String> {
+    decode(input).map_err(|error| error.to_string())
+}
+
+/// Lossily decode `input` and append it to `out` as the contents of a JSON string.
+///
+/// Escaping follows RFC 8259 section 7, without the surrounding quotes: `"` and `\` are
+/// backslash-escaped, and the control characters U+0000 to U+001F are written as `\b`,
+/// `\f`, `\n`, `\r` or `\t` where those exist and as `\u00XX` otherwise. Everything else,
+/// including non-ASCII text, is written as is. Each invalid sequence, and a trailing
+/// incomplete one, is written as the escape `\ufffd`, which a JSON parser decodes to
+/// U+FFFD like [`LossyDecoder`] would produce.
+///
+/// ```
+/// use utf8_zero::decode_to_json_string;
+///
+/// let mut json = String::from("\"");
+/// decode_to_json_string(b"say \"hi\"\n\xFF", &mut json);
+/// json.push('"');
+/// assert_eq!(json, r#""say \"hi\"\n\ufffd""#);
+/// ```
+pub fn decode_to_json_string(mut input: &[u8], out: &mut String) {
+    out.reserve(input.len());
+    loop {
+        let (valid, remaining) = match decode(input) {
+            Ok(s) => (s, None),
+            Err(DecodeError::Incomplete { valid_prefix, .. }) => (valid_prefix, Some(&[][..])),
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                remaining_input,
+                ..
+            }) => (valid_prefix, Some(remaining_input)),
+        };
+        push_json_escaped(valid, out);
+        match remaining {
+            Some(remaining) => {
+                out.push_str("\\ufffd");
+                input = remaining;
+            }
+            None => return,
+        }
+    }
+}
+
+fn push_json_escaped(s: &str, out: &mut String) {
+    let mut unescaped = 0;
+    for (i, byte) in s.bytes().enumerate() {
+        let escape = match byte {
+            b'"' => "\\\"",
+            b'\\' => "\\\\",
+            b'\x08' => "\\b",
+            b'\x0C' => "\\f",
+            b'\n' => "\\n",
+            b'\r' => "\\r",
+            b'\t' => "\\t",
+            0x00..=0x1F => "",
+            _ => continue,
+        };
+        out.push_str(&s[unescaped..i]);
+        if escape.is_empty() {
+            const HEX: &[u8; 16] = b"0123456789abcdef";
+            out.push_str("\\u00");
+            out.push(HEX[(byte >> 4) as usize] as char);
+            out.push(HEX[(byte & 0xF) as usize] as char);
+        } else {
+            out.push_str(escape);
+        }
+        unescaped = i + 1;
+    }
+    out.push_str(&s[unescaped..]);
+}
+
+/// Lossily decode a complete buffer, like `String::from_utf8_lossy`.
+///
+/// Each invalid sequence, and a trailing incomplete one, is replaced with U+FFFD. The
+/// result is `Cow::Borrowed` when `input` is valid UTF-8, so a `String` is only
+/// allocated when there are errors.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use utf8_zero::decode_lossy;
+///
+/// assert!(matches!(decode_lossy(b"valid"), Cow::Borrowed("valid")));
+/// assert_eq!(decode_lossy(b"a\xFFb\xE2\x82"), "a\u{FFFD}b\u{FFFD}");
+/// ```
+pub fn decode_lossy(input: &[u8]) -> Cow<'_, str> {
+    lossy_cow(input, REPLACEMENT_CHARACTER, REPLACEMENT_CHARACTER).0
+}
+
+/// Lossily decode `input`, dropping an incomplete code point at the end.
+///
+/// Invalid sequences are replaced with U+FFFD, but unlike `String::from_utf8_lossy` a
+/// trailing incomplete sequence is discarded instead of replaced. This keeps the result
+/// borrowed for streaming tails that will be completed or thrown away anyway:
+/// `Cow::Borrowed` is returned when the input is valid UTF-8 apart from such a tail,
+/// and a `String` is only allocated when there are errors before it.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use utf8_zero::decode_lossy_drop_incomplete;
+///
+/// assert!(matches!(decode_lossy_drop_incomplete(b"abc\xE2\x82"), Cow::Borrowed("abc")));
+/// assert_eq!(decode_lossy_drop_incomplete(b"a\xFFb\xE2\x82"), "a\u{FFFD}b");
+/// ```
+pub fn decode_lossy_drop_incomplete(input: &[u8]) -> Cow<'_, str> {
+    lossy_cow(input, REPLACEMENT_CHARACTER, "").0
+}
+
+/// Decode `input`, dropping invalid sequences, and report whether anything was dropped.
+///
+/// Invalid sequences and a trailing incomplete sequence are removed without leaving a
+/// replacement, which leaves the longest valid subsequence of the input. The flag tells an
+/// empty input apart from one that consisted entirely of invalid bytes. The result is
+/// borrowed when nothing before a trailing incomplete sequence had to be removed.
+///
+/// ```
+/// use utf8_zero::decode_skipping_reported;
+///
+/// assert_eq!(decode_skipping_reported(b"a\xFFb"), ("ab".into(), true));
+/// assert_eq!(decode_skipping_reported(b"\xFF\xFE"), ("".into(), true));
+/// assert_eq!(decode_skipping_reported(b""), ("".into(), false));
+/// ```
+pub fn decode_skipping_reported(input: &[u8]) -> (Cow<'_, str>, bool) {
+    lossy_cow(input, "", "")
+}
+
+/// Decode `input`, interpreting the bytes of invalid sequences as Latin-1.
+///
+/// Valid UTF-8 is decoded as usual, and every byte of an invalid or trailing
+/// incomplete sequence becomes the code point with the same value, as in ISO-8859-1:
+/// `E9` becomes U+00E9. This recovers text that mixes UTF-8 with Latin-1, as logs
+/// often do. Like [`decode_lossy()`], the result is borrowed when `input` is valid UTF-8.
+///
+/// ```
+/// use utf8_zero::decode_latin1_fallback;
+///
+/// assert_eq!(decode_latin1_fallback(b"caf\xE9 cr\xC3\xA8me"), "caf\u{E9} cr\u{E8}me");
+/// ```
+pub fn decode_latin1_fallback(input: &[u8]) -> Cow<'_, str> {
+    let mut remaining = input;
+    let mut string = String::new();
+    loop {
+        let bad = match decode(remaining) {
+            Ok(s) if string.is_empty() => return Cow::Borrowed(s),
+            Ok(s) => {
+                string.push_str(s);
+                return Cow::Owned(string);
+            }
+            Err(DecodeError::Incomplete {
+                valid_prefix,
+                incomplete_suffix,
+            }) => {
+                string.push_str(valid_prefix);
+                string.extend(incomplete_suffix.as_bytes().iter().map(|&b| char::from(b)));
+                return Cow::Owned(string);
+            }
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                remaining_input,
+            }) => {
+                if string.is_empty() {
+                    string.reserve(input.len() + input.len() / 2);
+                }
+                string.push_str(valid_prefix);
+                remaining = remaining_input;
+                invalid_sequence
+            }
+        };
+        string.extend(bad.iter().map(|&b| char::from(b)));
+    }
+}
+
+/// Return the maximal runs of valid UTF-8 in `input`, borrowing from it.
+///
+/// Invalid sequences and a trailing incomplete sequence separate runs and are omitted
+/// entirely, as are empty runs: an all-valid, non-empty input gives a single run and an
+/// empty input gives none.
+///
+/// ```
+/// use utf8_zero::valid_runs;
+///
+/// assert_eq!(valid_runs(b"one\xFF\xFEtwo\xC3"), ["one", "two"]);
+/// assert_eq!(valid_runs(b"all valid"), ["all valid"]);
+/// ```
+pub fn valid_runs(mut input: &[u8]) -> Vec<&str> {
+    let mut runs = Vec::new();
+    loop {
+        let (valid, remaining) = match decode(input) {
+            Ok(s) => (s, None),
+            Err(DecodeError::Incomplete { valid_prefix, .. }) => (valid_prefix, None),
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                remaining_input,
+                ..
+            }) => (valid_prefix, Some(remaining_input)),
+        };
+        if !valid.is_empty() {
+            runs.push(valid);
+        }
+        match remaining {
+            Some(remaining) => input = remaining,
+            None => return runs,
+        }
+    }
+}
+
+/// Lossily decode `input`, pushing `replacement` for each invalid sequence and
+/// `tail_replacement` for a trailing incomplete one. The result is borrowed if nothing
+/// had to be pushed. Also returns whether there were any errors.
+fn lossy_cow<'a>(
+    input: &'a [u8],
+    replacement: &str,
+    tail_replacement: &str,
+) -> (Cow<'a, str>, bool) {
+    let mut string: Option<String> = None;
+    let mut remaining = input;
+    loop {
+        match decode(remaining) {
+            Ok(s) => {
+                return match string {
+                    None => (Cow::Borrowed(s), false),
+                    Some(mut string) => {
+                        string.push_str(s);
+                        (Cow::Owned(string), true)
+                    }
+                }
+            }
+            Err(DecodeError::Incomplete { valid_prefix, .. }) => {
+                return match string {
+                    None if tail_replacement.is_empty() => (Cow::Borrowed(valid_prefix), true),
+                    string => {
+                        let mut string = string.unwrap_or_default();
+                        string.push_str(valid_prefix);
+                        string.push_str(tail_replacement);
+                        (Cow::Owned(string), true)
+                    }
+                }
+            }
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                remaining_input,
+                ..
+            }) => {
+                let string = string.get_or_insert_with(|| {
+                    String::with_capacity(input.len() + REPLACEMENT_CHARACTER.len())
+                });
+                string.push_str(valid_prefix);
+                string.push_str(replacement);
+                remaining = remaining_input;
+            }
+        }
+    }
+}
+
+/// Remove invalid UTF-8 from `buf` in place, so that what remains is valid UTF-8.
+///
+/// Invalid sequences and a trailing incomplete sequence are dropped, and the valid code
+/// points that remain are moved to the front of `buf` in their original order. Only
+/// whole code points are ever moved. Does not allocate.
+///
+/// ```
+/// use utf8_zero::retain_valid_in_place;
+///
+/// let mut buf = b"a\xFFb\xC3\xA9\xC0\x80c\xE2\x82".to_vec();
+/// retain_valid_in_place(&mut buf);
+/// assert_eq!(buf, "ab\u{E9}c".as_bytes());
+/// ```
+pub fn retain_valid_in_place(buf: &mut Vec<u8>) {
+    let mut read = 0;
+    let mut write = 0;
+    loop {
+        let (valid_len, invalid_len) = match decode(&buf[read..]) {
+            Ok(s) => (s.len(), None),
+            Err(DecodeError::Incomplete { valid_prefix, .. }) => (valid_prefix.len(), None),
+            Err(DecodeError::Invalid {
+                valid_prefix,
+                invalid_sequence,
+                ..
+            }) => (valid_prefix.len(), Some(invalid_sequence.len())),
+        };
+        buf.copy_within(read..read + valid_len, write);
+        write += valid_len;
+        match invalid_len {
+            Some(invalid_len) => read += valid_len + invalid_len,
+            None => break,
+        }
+    }
+    buf.truncate(write);
+}
+
+/// Undo one level of double encoding, where UTF-8 was misread as Latin-1 and encoded
+/// as UTF-8 again (turning "é" into "Ã©").
+///
+/// The input is decoded (lossily), then, if every code point is at most U+00FF and at
+/// least one is above U+007F, the code points are taken as bytes and decoded again. If
+/// that gives valid UTF-8 it is returned, otherwise the first decoding is.
+///
+/// This is a best-effort heuristic, not a lossless transformation: genuine Latin-1 text
+/// that happens to form valid UTF-8 when reinterpreted (such as "Ã©" itself) is
+/// "repaired" as well, and double-encoded text mixed with other characters is left
+/// alone.
+///
+/// ```
+/// use utf8_zero::repair_double_encoded;
+///
+/// assert_eq!(repair_double_encoded("cafÃ©".as_bytes()), "café");
+/// assert_eq!(repair_double_encoded(b"plain"), "plain");
+/// assert_eq!(repair_double_encoded("café".as_bytes()), "café");
+/// ```
+pub fn repair_double_encoded(input: &[u8]) -> Cow<'_, str> {
+    let decoded = String::from_utf8_lossy(input);
+    if decoded.is_ascii() || decoded.chars().any(|c| c > '\u{FF}') {
+        return decoded;
+    }
+    let bytes: Vec<u8> = decoded.chars().map(|c| c as u8).collect();
+    match String::from_utf8(bytes) {
+        Ok(repaired) => Cow::Owned(repaired),
+        Err(_) => decoded,
+    }
+}
+
+/// Decode Java's "modified UTF-8", as used by `DataInput`, JNI and `.class` files.
+///
+/// It differs from UTF-8 in two ways, both of which are undone here: U+0000 is encoded
+/// as the overlong `C0 80`, and a supplementary code point as the two 3-byte encodings
+/// of its UTF-16 surrogate pair. Standard UTF-8 is accepted as well, so only the
+/// input's differences from it cause allocation.
+///
+/// Anything else that isn't UTF-8, including a lone surrogate, is an error as reported
+/// by [`decode()`] for the input after the last `C0 80` or surrogate pair.
+///
+/// ```
+/// use utf8_zero::decode_modified_utf8;
+///
+/// assert_eq!(decode_modified_utf8(b"a\xC0\x80b").unwrap(), "a\0b");
+/// assert_eq!(decode_modified_utf8(b"\xED\xA0\xBC\xED\xBC\x8D").unwrap(), "\u{1F30D}");
+/// assert!(decode_modified_utf8(b"\xED\xA0\xBC!").is_err());
+/// ```
+pub fn decode_modified_utf8(mut input: &[u8]) -> Result<Cow<'_, str>, DecodeError<'_>> {
+    let mut string = String::new();
+    loop {
+        match decode(input) {
+            Ok(s) if string.is_empty() => return Ok(Cow::Borrowed(s)),
+            Ok(s) => {
+                string.push_str(s);
+                return Ok(Cow::Owned(string));
+            }
+            Err(error @ DecodeError::Incomplete { .. }) => return Err(error),
+            Err(error @ DecodeError::Invalid { valid_prefix, .. }) => {
+                let rest = &input[valid_prefix.len()..];
+                let (c, len) = match rest {
+                    [0xC0, 0x80, ..] => ('\0', 2),
+                    [0xED, h1 @ 0xA0..=0xAF, h2 @ 0x80..=0xBF, 0xED, l1 @ 0xB0..=0xBF, l2 @ 0x80..=0xBF, ..] =>
+                    {
+                        let high = u32::from(h1 & 0x0F) << 6 | u32::from(h2 & 0x3F);
+                        let low = u32::from(l1 & 0x0F) << 6 | u32::from(l2 & 0x3F);
+                        let c = char::from_u32(0x10000 + (high << 10 | low)).unwrap();
+                        (c, 6)
+                    }
+                    _ => return Err(error),
+                };
+                string.push_str(valid_prefix);
+                string.push(c);
+                input = &rest[len..];
+            }
+        }
+    }
+}
+
+/// Decode [WTF-8](https://simonsapin.github.io/wtf-8/) into UTF-16 code units.
+///
+/// Lone surrogates, which WTF-8 can encode and UTF-8 can't, become the same lone
+/// surrogates in the result, so it is the potentially ill-formed UTF-16 that Windows
+/// uses for file names, and not necessarily valid UTF-16. Neither the input nor the
+/// result is necessarily valid UTF-8 or convertible to a `String` without loss.
+///
+/// On invalid input, returns the offset of the first byte that isn't WTF-8, as
+/// [`is_valid_wtf8()`] would reject it.
+///
+/// ```
+/// use utf8_zero::decode_wtf8;
+///
+/// assert_eq!(decode_wtf8(b"a\xED\xA0\xBDb"), Ok(vec![0x61, 0xD83D, 0x62]));
+/// assert_eq!(decode_wtf8("\u{1F30D}".as_bytes()), Ok(vec![0xD83C, 0xDF0D]));
+/// assert_eq!(decode_wtf8(b"a\xFF"), Err(1));
+/// ```
+pub fn decode_wtf8(input: &[u8]) -> Result<Vec<u16>, usize> {
+    let mut units = Vec::with_capacity(input.len());
+    decode_wtf8_with(input, |piece| match piece {
+        Ok(s) => units.extend(s.encode_utf16()),
+        Err(surrogate) => units.push(surrogate),
+    })?;
+    Ok(units)
+}
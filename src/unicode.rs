@@ -0,0 +1,104 @@
+#[cfg(any(feature = "unicode-bidi", feature = "unicode-segmentation"))]
+use super::{decode, DecodeError};
+#[cfg(feature = "unicode-bidi")]
+use unicode_bidi::{bidi_class, BidiClass};
+#[cfg(feature = "unicode-normalization")]
+use unicode_normalization::char::is_combining_mark;
+#[cfg(feature = "unicode-segmentation")]
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Returns `true` if the last code point of `s` is a combining mark.
+///
+/// A combining mark attaches to the preceding base character, so when decoded chunks are
+/// appended one after another, a chunk ending in one may render differently once more
+/// text follows. Callers can hold such a trailing mark back until the next chunk.
+///
+/// The property used is `General_Category=Mark` (`Mn`, `Mc` or `Me`), as provided by
+/// the [`unicode-normalization`](https://crates.io/crates/unicode-normalization) crate
+/// for its Unicode version.
+///
+/// ```
+/// use utf8_zero::ends_with_combining;
+///
+/// assert!(ends_with_combining("e\u{301}"));
+/// assert!(!ends_with_combining("\u{E9}"));
+/// ```
+#[cfg(feature = "unicode-normalization")]
+pub fn ends_with_combining(s: &str) -> bool {
+    s.chars().next_back().is_some_and(is_combining_mark)
+}
+
+/// Decode `input` and count its grapheme clusters (user-perceived characters).
+///
+/// Returns the count together with the decoded string. This can be much smaller than
+/// the number of code points: an emoji ZWJ sequence or a letter with combining accents
+/// is a single grapheme. Extended grapheme clusters are used, with the segmentation rules
+/// of the Unicode version supported by the
+/// [`unicode-segmentation`](https://crates.io/crates/unicode-segmentation) crate
+/// (`unicode_segmentation::UNICODE_VERSION`), so counts for newly added characters can
+/// change when that crate is upgraded.
+///
+/// ```
+/// use utf8_zero::decode_grapheme_count;
+///
+/// let (count, s) = decode_grapheme_count("e\u{301}!".as_bytes()).unwrap();
+/// assert_eq!(count, 2);
+/// assert_eq!(s.chars().count(), 3);
+/// ```
+#[cfg(feature = "unicode-segmentation")]
+pub fn decode_grapheme_count(input: &[u8]) -> Result<(usize, &str), DecodeError<'_>> {
+    let s = decode(input)?;
+    Ok((s.graphemes(true).count(), s))
+}
+
+/// The dominant direction of a text, as found by [`decode_text_direction()`].
+#[cfg(feature = "unicode-bidi")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextDirection {
+    /// Left-to-right, such as Latin or Han text.
+    Ltr,
+    /// Right-to-left, such as Arabic or Hebrew text.
+    Rtl,
+    /// No strongly directional character was found, for example only digits or punctuation.
+    Neutral,
+}
+
+/// Decode `input` and guess its direction from the first strong directional character.
+///
+/// This is the heuristic of rules P2 and P3 in the Unicode Bidirectional Algorithm
+/// (UAX #9) for the paragraph level: the first character of bidirectional type `L` makes
+/// the text `Ltr`, and the first of type `R` or `AL` makes it `Rtl`. Characters between an
+/// isolate initiator (`LRI`, `RLI`, `FSI`) and its matching `PDI` are skipped. Unlike
+/// UAX #9 the whole input is treated as one paragraph, so it is a coarse hint for
+/// multi-paragraph text. Types come from the
+/// [`unicode-bidi`](https://crates.io/crates/unicode-bidi) crate.
+///
+/// ```
+/// use utf8_zero::{decode_text_direction, TextDirection};
+///
+/// let (direction, s) = decode_text_direction("123 \u{5E9}\u{5DC}\u{5D5}\u{5DD}".as_bytes()).unwrap();
+/// assert_eq!(direction, TextDirection::Rtl);
+/// assert_eq!(s.chars().count(), 8);
+/// ```
+#[cfg(feature = "unicode-bidi")]
+pub fn decode_text_direction(input: &[u8]) -> Result<(TextDirection, &str), DecodeError<'_>> {
+    let s = decode(input)?;
+    let mut isolates = 0usize;
+    let mut direction = TextDirection::Neutral;
+    for c in s.chars() {
+        match bidi_class(c) {
+            BidiClass::LRI | BidiClass::RLI | BidiClass::FSI => isolates += 1,
+            BidiClass::PDI => isolates = isolates.saturating_sub(1),
+            BidiClass::L if isolates == 0 => {
+                direction = TextDirection::Ltr;
+                break;
+            }
+            BidiClass::R | BidiClass::AL if isolates == 0 => {
+                direction = TextDirection::Rtl;
+                break;
+            }
+            _ => {}
+        }
+    }
+    Ok((direction, s))
+}
@@ -0,0 +1,86 @@
+use super::*;
+use std::io::{self, Write};
+
+/// Wraps a `std::io::Write` and lossily decodes the bytes written to it as UTF-8.
+///
+/// Valid text is passed through to the inner writer, and each maximal invalid subpart is
+/// replaced with U+FFFD, as with [`LossyDecoder`]. A code point split across `write`
+/// calls is buffered until the rest of it arrives, so the inner writer only ever sees
+/// well-formed UTF-8.
+///
+/// [`flush()`](Write::flush) and dropping treat a buffered incomplete sequence as the end
+/// of the input and write a replacement for it. Don't flush in the middle of a code point.
+///
+/// ```
+/// use std::io;
+/// use utf8_zero::Utf8LossyWriter;
+///
+/// let mut output = Vec::new();
+/// io::copy(&mut &b"Hello\xC0World"[..], &mut Utf8LossyWriter::new(&mut output)).unwrap();
+/// assert_eq!(output, "Hello\u{FFFD}World".as_bytes());
+/// ```
+pub struct Utf8LossyWriter<W: Write> {
+    decoder: LossyDecoder<IoWriteSink<W>>,
+}
+
+impl<W: Write> Utf8LossyWriter<W> {
+    /// Create a new writer wrapping `inner`.
+    #[inline]
+    pub fn new(inner: W) -> Self {
+        Utf8LossyWriter {
+            decoder: LossyDecoder::new_with_sink(IoWriteSink {
+                writer: inner,
+                error: None,
+            }),
+        }
+    }
+
+    /// Take the first error the inner writer returned since the last call, if any.
+    fn take_error(&mut self) -> io::Result<()> {
+        match self.decoder.sink_mut().and_then(|sink| sink.error.take()) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for Utf8LossyWriter<W> {
+    /// Decode all of `buf` and write the result to the inner writer.
+    ///
+    /// This always consumes the whole buffer. If the inner writer fails, part of the
+    /// decoded text may already have been written to it, and the rest of `buf` is dropped.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.decoder.feed(buf);
+        self.take_error()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.decoder.end();
+        self.take_error()?;
+        match self.decoder.sink_mut() {
+            Some(sink) => sink.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes decoded text to an `io::Write`, keeping the first error rather than panicking.
+///
+/// Dropping the decoder writes the replacement for a trailing incomplete sequence, and
+/// any error from that is lost; call `flush()` first to see it.
+struct IoWriteSink<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Utf8Sink for IoWriteSink<W> {
+    #[inline]
+    fn push_str(&mut self, s: &str) {
+        if self.error.is_none() {
+            if let Err(error) = self.writer.write_all(s.as_bytes()) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
@@ -1,3 +1,12 @@
+// The helpers below predate these lints and are kept as they were upstream.
+#![allow(
+    clippy::absurd_extreme_comparisons,
+    clippy::borrow_deref_ref,
+    clippy::needless_lifetimes,
+    clippy::needless_return,
+    clippy::redundant_static_lifetimes
+)]
+
 extern crate utf8_zero;
 
 use std::borrow::Cow;
@@ -210,3 +219,3444 @@ impl<'a> io::BufRead for Chunks<'a> {
         }
     }
 }
+
+#[test]
+fn test_decode_from() {
+    for &(input, _) in DECODED_LOSSY {
+        let full = decode(input);
+        let checkpoint = match full {
+            Ok(s) => s.len(),
+            Err(DecodeError::Invalid { valid_prefix, .. })
+            | Err(DecodeError::Incomplete { valid_prefix, .. }) => valid_prefix.len(),
+        };
+        for resume in 0..=checkpoint {
+            if !std::str::from_utf8(&input[..checkpoint])
+                .unwrap()
+                .is_char_boundary(resume)
+            {
+                continue;
+            }
+            let resumed = decode_from(input, resume);
+            match (full, resumed) {
+                (Ok(a), Ok(b)) => assert_eq!(&a[resume..], b),
+                (
+                    Err(DecodeError::Invalid {
+                        valid_prefix: a,
+                        invalid_sequence: ia,
+                        ..
+                    }),
+                    Err(DecodeError::Invalid {
+                        valid_prefix: b,
+                        invalid_sequence: ib,
+                        ..
+                    }),
+                ) => {
+                    assert_eq!(&a[resume..], b);
+                    assert_eq!(ia, ib);
+                }
+                (
+                    Err(DecodeError::Incomplete {
+                        valid_prefix: a, ..
+                    }),
+                    Err(DecodeError::Incomplete {
+                        valid_prefix: b, ..
+                    }),
+                ) => assert_eq!(&a[resume..], b),
+                other => panic!("mismatch resuming at {}: {:?}", resume, other),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_count_matching() {
+    let input = "line\none\r\n\x00é€\u{10000}\x1B[0m".as_bytes();
+    let (count, s) = decode_count_matching(input, char::is_control).unwrap();
+    assert_eq!(count, 5);
+    assert_eq!(s.as_bytes(), input);
+
+    let (count, _) = decode_count_matching(input, |c| c.len_utf8() > 1).unwrap();
+    assert_eq!(count, 3);
+
+    assert!(decode_count_matching(b"\x07\xFF", char::is_control).is_err());
+}
+
+#[test]
+fn test_decode_stateful() {
+    let input = "aé€\u{1F30D}".as_bytes();
+    all_partitions(input, |chunks| {
+        let mut state = Incomplete::empty();
+        let mut string = String::new();
+        for &chunk in chunks {
+            decode_stateful(&mut state, chunk, |s| string.push_str(s)).unwrap();
+        }
+        assert!(state.is_empty());
+        assert_eq!(string.as_bytes(), input);
+    });
+
+    let mut state = Incomplete::empty();
+    let mut string = String::new();
+    decode_stateful(&mut state, b"a\xE2\x82", |s| string.push_str(s)).unwrap();
+    match decode_stateful(&mut state, b"b", |s| string.push_str(s)) {
+        Err(DecodeError::Invalid {
+            valid_prefix: "",
+            invalid_sequence: b"",
+            remaining_input: b"b",
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(state.is_empty());
+    assert_eq!(string, "a");
+}
+
+#[test]
+fn test_decode_validity_mask() {
+    let mut mask = vec![false; 100];
+    let input = b"\xC3\xA9\xFF\xFE\xFDz\xE2\x82";
+    let s = decode_validity_mask(input, &mut mask);
+    assert_eq!(s, "\u{E9}\u{FFFD}\u{FFFD}\u{FFFD}z\u{FFFD}");
+    assert_eq!(mask, [true, true, false, false, false, true, false, false]);
+
+    for &(input, expected) in DECODED_LOSSY {
+        let s = decode_validity_mask(input, &mut mask);
+        assert_eq!(s, expected);
+        assert_eq!(mask.len(), input.len());
+    }
+}
+
+#[test]
+fn test_per_byte_replacement() {
+    fn per_byte(chunks: &[&[u8]]) -> String {
+        let mut string = String::new();
+        {
+            let mut decoder = LossyDecoder::with_per_byte_replacement(|s| string.push_str(s));
+            for &chunk in chunks {
+                decoder.feed(chunk);
+            }
+        }
+        string
+    }
+
+    assert_eq!(per_byte(&[b"a\xFF\xFE\xFDb"]), "a\u{FFFD}\u{FFFD}\u{FFFD}b");
+    assert_eq!(per_byte(&[b"a\xE2\x82", b"b"]), "a\u{FFFD}\u{FFFD}b");
+    assert_eq!(
+        per_byte(&[b"\xF0\x9F", b"\x8C"]),
+        "\u{FFFD}\u{FFFD}\u{FFFD}"
+    );
+    assert_eq!(
+        per_byte(&[b"\xC0\x80", b"\xE2\x82\xAC"]),
+        "\u{FFFD}\u{FFFD}\u{20AC}"
+    );
+
+    // Every input byte is either kept or replaced by exactly one U+FFFD.
+    for &(input, _) in DECODED_LOSSY {
+        all_partitions(input, |chunks| {
+            let output = per_byte(chunks);
+            let valid_bytes = output
+                .chars()
+                .filter(|&c| c != '\u{FFFD}')
+                .map(char::len_utf8)
+                .sum::<usize>();
+            let replacements = output.chars().filter(|&c| c == '\u{FFFD}').count();
+            assert_eq!(valid_bytes + replacements, input.len());
+        });
+    }
+}
+
+#[test]
+fn test_decode_first_line() {
+    let (line, rest) = decode_first_line(b"caf\xC3\xA9\n\xFF\x00\xC3\n").unwrap();
+    assert_eq!(line, "caf\u{E9}");
+    assert_eq!(rest, b"\xFF\x00\xC3\n");
+
+    assert_eq!(
+        decode_first_line(b"no newline").unwrap(),
+        ("no newline", &b""[..])
+    );
+    assert_eq!(decode_first_line(b"\n").unwrap(), ("", &b""[..]));
+    assert_eq!(
+        decode_first_line(b"crlf\r\nx").unwrap(),
+        ("crlf\r", &b"x"[..])
+    );
+
+    match decode_first_line(b"a\xFFb\nc") {
+        Err(DecodeError::Invalid {
+            valid_prefix: "a",
+            invalid_sequence: b"\xFF",
+            remaining_input: b"b\nc",
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+    match decode_first_line(b"a\xE2\x82\nc") {
+        Err(DecodeError::Invalid {
+            valid_prefix: "a",
+            invalid_sequence: b"\xE2\x82",
+            remaining_input: b"\nc",
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        decode_first_line(b"a\xE2\x82"),
+        Err(DecodeError::Incomplete {
+            valid_prefix: "a",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_code_point_offsets() {
+    let s = "ศไทย中华Việt Nam \u{1F30D}";
+    let offsets: Vec<_> = code_point_offsets(s.as_bytes()).collect();
+    let expected: Vec<_> = s.char_indices().map(|(i, _)| Ok(i)).collect();
+    assert_eq!(offsets, expected);
+
+    assert_eq!(code_point_offsets(b"").count(), 0);
+    let offsets: Vec<_> = code_point_offsets(b"\xC0\x80a\xE2\x82").collect();
+    assert_eq!(offsets, [Err(0), Err(1), Ok(2), Err(3)]);
+
+    for &(input, expected) in DECODED_LOSSY {
+        assert_eq!(code_point_offsets(input).count(), expected.chars().count());
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn test_lossy_decoder_tracing() {
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    struct Replacements(Option<u64>);
+
+    impl Visit for Replacements {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "replacements" {
+                self.0 = Some(value);
+            }
+        }
+
+        fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+    }
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u64>>>);
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut visitor = Replacements(None);
+            event.record(&mut visitor);
+            self.0.lock().unwrap().extend(visitor.0);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let capture = Capture::default();
+    tracing::subscriber::with_default(capture.clone(), || {
+        let mut decoder = LossyDecoder::new(|_| {});
+        decoder.feed(b"clean");
+        decoder.feed(b"a\xFFb\xC0\x80c");
+        decoder.feed(b"\xE2\x82");
+        decoder.feed(b"x");
+    });
+    assert_eq!(*capture.0.lock().unwrap(), [3, 1]);
+}
+
+#[test]
+fn test_decode_profiled() {
+    let standard = ConformanceProfile::default();
+    let overlong = ConformanceProfile {
+        allow_overlong: true,
+        ..standard
+    };
+    let surrogates = ConformanceProfile {
+        allow_surrogates: true,
+        ..standard
+    };
+    let bmp_only = ConformanceProfile {
+        allow_four_byte: false,
+        ..standard
+    };
+
+    for &(input, _) in DECODED_LOSSY {
+        let expected = str_from_utf8(input).map_err(|_| ());
+        let actual = decode_profiled(input, standard);
+        assert_eq!(actual.as_deref().map_err(|_| ()), expected);
+        assert!(matches!(actual, Ok(Cow::Borrowed(_)) | Err(_)));
+    }
+
+    // Overlong encodings.
+    let input = b"\xC0\x80|\xC1\xBF|\xE0\x81\x81|\xF0\x80\x81\x81";
+    assert!(decode_profiled(input, standard).is_err());
+    assert!(decode_profiled(input, surrogates).is_err());
+    assert_eq!(decode_profiled(input, overlong).unwrap(), "\0|\x7F|A|A");
+    match decode_profiled(
+        b"\xC0\x80ok\xF0\x80\x81\x81",
+        ConformanceProfile {
+            allow_four_byte: false,
+            ..overlong
+        },
+    ) {
+        Err(ProfileError::Decode(DecodeError::Invalid {
+            valid_prefix: "ok",
+            invalid_sequence: b"\xF0",
+            remaining_input: b"\x80\x81\x81",
+        })) => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(decode_profiled(b"\xF0\x8D\xA0\x80", overlong).is_err());
+
+    // Surrogate pairs.
+    let pair = b"<\xED\xA0\xBD\xED\xB8\x80>";
+    assert!(decode_profiled(pair, standard).is_err());
+    assert!(decode_profiled(pair, overlong).is_err());
+    assert_eq!(decode_profiled(pair, surrogates).unwrap(), "<\u{1F600}>");
+    assert!(decode_profiled(b"\xED\xA0\xBD", surrogates).is_err());
+    assert!(decode_profiled(b"\xED\xB8\x80\xED\xA0\xBD", surrogates).is_err());
+
+    // 4-byte sequences.
+    assert_eq!(
+        decode_profiled("\u{1F600}".as_bytes(), standard).unwrap(),
+        "\u{1F600}"
+    );
+    // Rejected as a profile violation, not as invalid UTF-8.
+    assert_eq!(
+        decode_profiled("a\u{1F600}b".as_bytes(), bmp_only),
+        Err(ProfileError::FourByte {
+            valid_prefix: "a",
+            byte_offset: 1,
+            code_point: '\u{1F600}',
+        })
+    );
+    assert_eq!(
+        decode_profiled(
+            b"\xC0\x80\xF0\x9F\x98\x80",
+            ConformanceProfile {
+                allow_overlong: true,
+                ..bmp_only
+            }
+        ),
+        Err(ProfileError::FourByte {
+            valid_prefix: "",
+            byte_offset: 2,
+            code_point: '\u{1F600}',
+        })
+    );
+    assert_eq!(
+        decode_profiled(
+            pair,
+            ConformanceProfile {
+                allow_four_byte: false,
+                ..surrogates
+            }
+        )
+        .unwrap(),
+        "<\u{1F600}>"
+    );
+}
+
+#[test]
+fn test_decode_or_message() {
+    assert_eq!(decode_or_message("é".as_bytes()), Ok("é"));
+    for input in [&b"\xC0\x80"[..], b"abc\xE2\x82", b"\xFF"] {
+        assert_eq!(
+            decode_or_message(input).unwrap_err(),
+            decode(input).unwrap_err().to_string()
+        );
+    }
+}
+
+#[test]
+fn test_trailing_incomplete_len() {
+    assert_eq!(trailing_incomplete_len(b""), 0);
+    assert_eq!(trailing_incomplete_len(b"a"), 0);
+    assert_eq!(trailing_incomplete_len("é€\u{1F30D}".as_bytes()), 0);
+    assert_eq!(trailing_incomplete_len(b"\xC3"), 1);
+    assert_eq!(trailing_incomplete_len(b"a\xE2"), 1);
+    assert_eq!(trailing_incomplete_len(b"a\xE2\x82"), 2);
+    assert_eq!(trailing_incomplete_len(b"\xF0"), 1);
+    assert_eq!(trailing_incomplete_len(b"\xF0\x9F"), 2);
+    assert_eq!(trailing_incomplete_len(b"ab\xF0\x9F\x8C"), 3);
+    // Invalid interior bytes don't matter, only the tail.
+    assert_eq!(trailing_incomplete_len(b"\xFF\xF0\x9F\x8C"), 3);
+
+    // Not completable.
+    assert_eq!(trailing_incomplete_len(b"a\xFF"), 0);
+    assert_eq!(trailing_incomplete_len(b"a\x80"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xC3\xA9\x80"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xF0\x9F\x8C\x8D\x80"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xC0"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xE0\x80"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xED\xA0"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xF4\x90"), 0);
+    assert_eq!(trailing_incomplete_len(b"\xF5"), 0);
+
+    for &(input, _) in DECODED_LOSSY {
+        for end in 0..=input.len() {
+            let prefix = &input[..end];
+            let expected = match decode(prefix) {
+                Err(DecodeError::Incomplete {
+                    incomplete_suffix, ..
+                }) => incomplete_suffix.buffer_len as usize,
+                _ => trailing_incomplete_len(prefix),
+            };
+            assert_eq!(trailing_incomplete_len(prefix), expected);
+        }
+    }
+}
+
+#[test]
+fn test_decode_nopanic() {
+    for &(input, _) in DECODED_LOSSY {
+        for end in 0..=input.len() {
+            let input = &input[..end];
+            assert_eq!(
+                format!("{:?}", decode_nopanic(input)),
+                format!("{:?}", decode(input))
+            );
+        }
+    }
+
+    // Garbage in the public fields of `Incomplete` must not cause a panic.
+    for buffer in [
+        [0x80, 0x80, 0, 0],
+        [b'A', 0xC3, 0, 0],
+        [0xC3, 0xA9, b'x', 0xFF],
+    ] {
+        for buffer_len in [0, 1, 2, 3, 4, 5, 255] {
+            for input in [&b""[..], b"\xA9", b"\xFF", b"abcd"] {
+                let mut incomplete = Incomplete { buffer, buffer_len };
+                let _ = incomplete.try_complete(input);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_decode_lossy_drop_incomplete() {
+    assert!(matches!(
+        decode_lossy_drop_incomplete(b""),
+        Cow::Borrowed("")
+    ));
+    assert!(matches!(
+        decode_lossy_drop_incomplete(b"ok"),
+        Cow::Borrowed("ok")
+    ));
+    assert!(matches!(
+        decode_lossy_drop_incomplete(b"ok\xF0\x9F\x8C"),
+        Cow::Borrowed("ok")
+    ));
+    assert!(matches!(
+        decode_lossy_drop_incomplete(b"\xC3"),
+        Cow::Borrowed("")
+    ));
+
+    let result = decode_lossy_drop_incomplete(b"\xFFok\xC0\x80!\xF0\x9F\x8C");
+    assert!(matches!(result, Cow::Owned(_)));
+    assert_eq!(result, "\u{FFFD}ok\u{FFFD}\u{FFFD}!");
+
+    for &(input, expected) in DECODED_LOSSY {
+        let mut expected = expected;
+        if trailing_incomplete_len(input) > 0 {
+            expected = expected.strip_suffix('\u{FFFD}').unwrap();
+        }
+        assert_eq!(decode_lossy_drop_incomplete(input), expected);
+    }
+}
+
+#[test]
+fn test_decode_with_hint() {
+    assert_eq!(decode_with_hint(b"abc").1, None);
+    assert_eq!(decode_with_hint(b"a\xFFb").1, None);
+    assert_eq!(decode_with_hint(b"a\xE2\x82\xFF").1, None);
+
+    let s = "é€\u{1F30D}";
+    for (start, c) in s.char_indices() {
+        let expected_len = c.len_utf8();
+        for buffer_len in 1..expected_len {
+            let input = &s.as_bytes()[start..start + buffer_len];
+            let (result, hint) = decode_with_hint(input);
+            assert!(matches!(result, Err(DecodeError::Incomplete { .. })));
+            assert_eq!(hint, Some(expected_len - buffer_len));
+        }
+    }
+}
+
+#[test]
+fn test_count_overlong() {
+    assert_eq!(count_overlong(b""), 0);
+    assert_eq!(count_overlong("valid é€\u{1F30D}".as_bytes()), 0);
+    assert_eq!(count_overlong(b"\xC0\x80"), 1);
+    assert_eq!(count_overlong(b"\xE0\x80\x80"), 1);
+    assert_eq!(count_overlong(b"\xF0\x80\x80\x80"), 1);
+    assert_eq!(
+        count_overlong(b"a\xC0\x80b\xE0\x80\x80c\xF0\x80\x80\x80d"),
+        3
+    );
+    assert_eq!(count_overlong(b"\xC1\xBF\xE0\x9F\xBF\xF0\x8F\xBF\xBF"), 3);
+    // Truncated overlong forms still count.
+    assert_eq!(count_overlong(b"\xC0\xE0\x80\xF0\x80\x80"), 3);
+    // Other invalid sequences don't.
+    assert_eq!(
+        count_overlong(b"\xFF\x80\xED\xA0\x80\xF4\x90\x80\x80\xE2\x82"),
+        0
+    );
+}
+
+#[test]
+fn test_lines() {
+    fn collect(input: &[u8]) -> Result<Vec<&str>, DecodeError<'_>> {
+        lines(input).map(|lines| lines.collect())
+    }
+    assert_eq!(collect(b"").unwrap(), Vec::<&str>::new());
+    assert_eq!(collect(b"\n").unwrap(), [""]);
+    assert_eq!(collect(b"a\r\nb\r\n").unwrap(), ["a", "b"]);
+    assert_eq!(collect(b"a\n\nb").unwrap(), ["a", "", "b"]);
+    assert_eq!(
+        collect("caf\u{E9}\r\n\u{1F30D}".as_bytes()).unwrap(),
+        ["caf\u{E9}", "\u{1F30D}"]
+    );
+    // A lone `\r` is not a line ending.
+    assert_eq!(collect(b"a\rb").unwrap(), ["a\rb"]);
+
+    assert!(matches!(
+        collect(b"fine\nbad \xFF\n"),
+        Err(DecodeError::Invalid {
+            valid_prefix: "fine\nbad ",
+            ..
+        })
+    ));
+    assert!(matches!(
+        collect(b"fine\n\xE2\x82"),
+        Err(DecodeError::Incomplete { .. })
+    ));
+}
+
+#[test]
+fn test_decode_head() {
+    let s = "ศไทย中华Việt Nam \u{1F30D}!";
+    for n in 0..s.chars().count() + 2 {
+        let expected: String = s.chars().take(n).collect();
+        assert_eq!(decode_head(s.as_bytes(), n).unwrap(), expected);
+    }
+
+    // Only the head is validated.
+    let mut large = "é".repeat(10).into_bytes();
+    large.extend(std::iter::repeat_n(0xFF, 1_000_000));
+    assert_eq!(decode_head(&large, 10).unwrap(), "é".repeat(10));
+    assert!(decode_head(&large, 11).is_err());
+
+    match decode_head(b"ab\xE2\x82cd", 4) {
+        Err(DecodeError::Invalid {
+            valid_prefix: "ab",
+            invalid_sequence: b"\xE2\x82",
+            remaining_input: b"cd",
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(matches!(
+        decode_head(b"ab\xE2\x82", 4),
+        Err(DecodeError::Incomplete {
+            valid_prefix: "ab",
+            ..
+        })
+    ));
+    assert_eq!(decode_head(b"ab\xE2\x82", 2).unwrap(), "ab");
+    assert!(decode_head(b"a\x80\x80\x80\x80b", 2).is_err());
+}
+
+#[cfg(feature = "unicode-normalization")]
+#[test]
+fn test_ends_with_combining() {
+    assert!(!ends_with_combining(""));
+    assert!(!ends_with_combining("cafe"));
+    assert!(!ends_with_combining("caf\u{E9}"));
+    assert!(ends_with_combining("cafe\u{301}"));
+    assert!(ends_with_combining("\u{301}"));
+    // Spacing (Mc) and enclosing (Me) marks count as well.
+    assert!(ends_with_combining("\u{915}\u{93E}"));
+    assert!(ends_with_combining("1\u{20E3}"));
+    assert!(!ends_with_combining("e\u{301}x"));
+
+    let s = decode(b"cafe\xCC\x81").unwrap();
+    assert!(ends_with_combining(s));
+}
+
+#[test]
+fn test_retain_valid_in_place() {
+    let mut buf = b"\xFF\xFE".to_vec();
+    retain_valid_in_place(&mut buf);
+    assert!(buf.is_empty());
+
+    let mut buf = "ศไทย中华".as_bytes().to_vec();
+    retain_valid_in_place(&mut buf);
+    assert_eq!(buf, "ศไทย中华".as_bytes());
+
+    let mut buf = b"\xF0\x9F\x8C\x8D\x80\xE4\xB8\xAD\xF0\x9F\xE5\x8D\x8E\xED\xA0\x80!".to_vec();
+    retain_valid_in_place(&mut buf);
+    assert_eq!(buf, "\u{1F30D}中华!".as_bytes());
+
+    for &(input, expected) in DECODED_LOSSY {
+        let mut buf = input.to_vec();
+        retain_valid_in_place(&mut buf);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            expected.replace('\u{FFFD}', "")
+        );
+    }
+}
+
+#[test]
+fn test_byte_len_of_lines() {
+    let input = "ศไทย\n中华\n\nViệt Nam".as_bytes();
+    let expected = [0, 13, 20, 21, input.len(), input.len()];
+    for (n, &expected) in expected.iter().enumerate() {
+        let len = byte_len_of_lines(input, n).unwrap();
+        assert_eq!(len, expected);
+        assert!(decode(&input[..len]).is_ok());
+    }
+    assert_eq!(byte_len_of_lines(b"", 3).unwrap(), 0);
+
+    // Only the requested lines are validated.
+    assert_eq!(byte_len_of_lines(b"ok\n\xFF\n", 1).unwrap(), 3);
+    assert!(matches!(
+        byte_len_of_lines(b"ok\n\xFF\n", 2),
+        Err(DecodeError::Invalid {
+            valid_prefix: "ok\n",
+            ..
+        })
+    ));
+    assert!(matches!(
+        byte_len_of_lines(b"ok\n\xE2\x82", 2),
+        Err(DecodeError::Incomplete { .. })
+    ));
+}
+
+#[test]
+fn test_repair_double_encoded() {
+    fn double_encode(s: &str) -> Vec<u8> {
+        s.bytes().map(char::from).collect::<String>().into_bytes()
+    }
+
+    assert_eq!(repair_double_encoded(&double_encode("é")), "é");
+    assert_eq!(
+        repair_double_encoded(&double_encode("Việt Nam")),
+        "Việt Nam"
+    );
+    assert_eq!(
+        repair_double_encoded(&double_encode("\u{1F30D}")),
+        "\u{1F30D}"
+    );
+    assert!(matches!(
+        repair_double_encoded(b"ascii only"),
+        Cow::Borrowed("ascii only")
+    ));
+
+    // Not double-encoded: left untouched.
+    assert!(matches!(
+        repair_double_encoded("café".as_bytes()),
+        Cow::Borrowed("café")
+    ));
+    assert_eq!(repair_double_encoded("中华".as_bytes()), "中华");
+    assert_eq!(repair_double_encoded("Ã© and €".as_bytes()), "Ã© and €");
+    assert_eq!(repair_double_encoded(b"bad \xFF"), "bad \u{FFFD}");
+}
+
+#[cfg(feature = "unicode-segmentation")]
+#[test]
+fn test_decode_grapheme_count() {
+    let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+    let (count, s) = decode_grapheme_count(family.as_bytes()).unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(s, family);
+    assert_eq!(s.chars().count(), 7);
+
+    let input = format!("hi {}!\r\n", family);
+    assert_eq!(decode_grapheme_count(input.as_bytes()).unwrap().0, 6);
+    assert_eq!(decode_grapheme_count(b"").unwrap(), (0, ""));
+    assert!(decode_grapheme_count(b"a\xFF").is_err());
+}
+
+#[test]
+fn test_decode_to_channel() {
+    /// A reader that returns at most `n` bytes per call.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(buf.len()).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let input: Vec<u8> = DECODED_LOSSY
+        .iter()
+        .flat_map(|&(input, _)| input.iter().copied())
+        .collect();
+    let expected = String::from_utf8_lossy(&input).into_owned();
+    let input: &'static [u8] = input.leak();
+
+    for (step, chunk) in [(1, 1), (3, 5), (7, 16), (64, 1000)] {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let producer =
+            std::thread::spawn(move || decode_to_channel(Trickle(input, step), tx, chunk));
+        let consumer = std::thread::spawn(move || {
+            let mut output = String::new();
+            for s in rx {
+                assert!(!s.is_empty());
+                output.push_str(&s);
+            }
+            output
+        });
+        producer.join().unwrap().unwrap();
+        assert_eq!(consumer.join().unwrap(), expected);
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    drop(rx);
+    let error = decode_to_channel(&b"abc"[..], tx, 1).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn test_lossy_decoder_forensics() {
+    let input = b"ab\xFFc\xC0\x80d\xE2\x82e\xF0\x9F\x8C\x8D\xF0\x9F";
+    let expected_bad: &[(usize, &[u8])] = &[
+        (2, b"\xFF"),
+        (4, b"\xC0"),
+        (5, b"\x80"),
+        (7, b"\xE2\x82"),
+        (14, b"\xF0\x9F"),
+    ];
+    all_partitions(input, |chunks| {
+        let mut output = String::new();
+        let mut bad = Vec::new();
+        {
+            let mut decoder = LossyDecoder::with_forensics(
+                |s| output.push_str(s),
+                |offset, bytes: &[u8]| bad.push((offset, bytes.to_vec())),
+            );
+            for &chunk in chunks {
+                decoder.feed(chunk);
+            }
+        }
+        assert_eq!(output, String::from_utf8_lossy(input));
+        let bad: Vec<_> = bad.iter().map(|(o, b)| (*o, &b[..])).collect();
+        assert_eq!(bad, expected_bad);
+    });
+}
+
+#[test]
+fn test_decode_skipping_reported() {
+    assert_eq!(decode_skipping_reported(b""), ("".into(), false));
+    assert_eq!(
+        decode_skipping_reported(b"\xFF\x80\xC0\x80"),
+        ("".into(), true)
+    );
+    assert_eq!(decode_skipping_reported(b"\xE2\x82"), ("".into(), true));
+    assert!(matches!(
+        decode_skipping_reported(b"valid"),
+        (Cow::Borrowed("valid"), false)
+    ));
+    assert!(matches!(
+        decode_skipping_reported(b"valid\xF0\x9F"),
+        (Cow::Borrowed("valid"), true)
+    ));
+
+    for &(input, expected) in DECODED_LOSSY {
+        let (s, dropped) = decode_skipping_reported(input);
+        assert_eq!(s, expected.replace('\u{FFFD}', ""));
+        assert_eq!(dropped, expected.contains('\u{FFFD}'));
+
+        let mut buf = input.to_vec();
+        retain_valid_in_place(&mut buf);
+        assert_eq!(s.as_bytes(), buf);
+    }
+}
+
+#[test]
+fn test_decode_in_ranges() {
+    let lowercase = ['a'..='z'];
+    assert_eq!(decode_in_ranges(b"", &lowercase).unwrap(), "");
+    assert_eq!(decode_in_ranges(b"abcxyz", &lowercase).unwrap(), "abcxyz");
+    match decode_in_ranges(b"abc1def", &lowercase) {
+        Err(PolicyError::Rejected {
+            valid_prefix: "abc",
+            rejection:
+                PolicyRejection {
+                    byte_offset: 3,
+                    reason: PolicyReason::OutOfRange,
+                },
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+
+    let ranges = ['-'..='-', '0'..='9', 'A'..='Z', '\u{E0}'..='\u{FF}'];
+    assert!(decode_in_ranges("AZ-09\u{E9}".as_bytes(), &ranges).is_ok());
+    match decode_in_ranges("OK-\u{E9}\u{20AC}!".as_bytes(), &ranges) {
+        Err(PolicyError::Rejected {
+            valid_prefix: "OK-\u{E9}",
+            rejection,
+        }) => assert_eq!(rejection.byte_offset, 5),
+        other => panic!("{:?}", other),
+    }
+    assert!(decode_in_ranges(b"a", &[]).is_err());
+
+    // UTF-8 errors are reported as usual.
+    assert!(matches!(
+        decode_in_ranges(b"ab\xFF", &lowercase),
+        Err(PolicyError::Decode(DecodeError::Invalid {
+            valid_prefix: "ab",
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_line_aligned_output() {
+    let input = b"first line\r\nsecond \xFF\nthird\n\nlast \xF0\x9F\x8C";
+    for chunk_size in 1..input.len() {
+        let mut callbacks = Vec::new();
+        {
+            let mut decoder =
+                LossyDecoder::with_line_aligned_output(|s| callbacks.push(s.to_owned()));
+            for chunk in input.chunks(chunk_size) {
+                decoder.feed(chunk);
+            }
+        }
+        let (last, rest) = callbacks.split_last().unwrap();
+        assert_eq!(last, "last \u{FFFD}");
+        for s in rest {
+            assert!(s.ends_with('\n'), "{:?}", s);
+        }
+        assert_eq!(callbacks.concat(), String::from_utf8_lossy(input));
+    }
+
+    // A line longer than the limit is split rather than held back in full.
+    let input = b"short\nthis line is too long\nend\xFF";
+    for chunk_size in 1..input.len() {
+        let callbacks = std::cell::RefCell::new(Vec::new());
+        {
+            let mut decoder =
+                LossyDecoder::new(|s: &str| callbacks.borrow_mut().push(s.to_owned()))
+                    .line_aligned(8);
+            for chunk in input.chunks(chunk_size) {
+                decoder.feed(chunk);
+                let pushed = callbacks.borrow().concat().len();
+                assert!(decoder.byte_position().saturating_sub(pushed) <= 8);
+            }
+        }
+        let callbacks = callbacks.into_inner();
+        assert!(callbacks[0].starts_with("short\n"));
+        if chunk_size <= 8 {
+            assert!(callbacks.len() > 3, "{:?}", callbacks);
+        }
+        assert_eq!(callbacks.concat(), String::from_utf8_lossy(input));
+    }
+}
+
+#[test]
+fn test_decode_max_len() {
+    let s = "aé€\u{1F30D}";
+    assert_eq!(decode_max_len::<4>(s.as_bytes()).unwrap(), s);
+    assert_eq!(
+        decode_max_len::<2>(s.as_bytes()),
+        Err(MaxLenError::TooLong {
+            valid_prefix: "aé",
+            sequence_len: 3,
+        })
+    );
+    assert_eq!(
+        decode_max_len::<3>(s.as_bytes()),
+        Err(MaxLenError::TooLong {
+            valid_prefix: "aé€",
+            sequence_len: 4,
+        })
+    );
+
+    // A disallowed lead byte is rejected even when incomplete or followed by garbage.
+    for input in [&b"ab\xF0\x9F"[..], b"ab\xF0\x9F!", b"ab\xF0"] {
+        assert_eq!(
+            decode_max_len::<3>(input),
+            Err(MaxLenError::TooLong {
+                valid_prefix: "ab",
+                sequence_len: 4,
+            })
+        );
+    }
+    // Before it, UTF-8 errors are reported as usual.
+    assert_eq!(
+        decode_max_len::<3>(b"a\xFFb\xF0"),
+        Err(MaxLenError::Invalid {
+            valid_prefix: "a",
+            invalid_sequence: b"\xFF",
+            remaining_input: b"b\xF0",
+        })
+    );
+
+    // An incomplete suffix is carried in an `IncompleteN<MAX>`.
+    match decode_max_len::<3>(b"ab\xE2\x82") {
+        Err(MaxLenError::Incomplete {
+            valid_prefix: "ab",
+            incomplete_suffix,
+        }) => {
+            assert_eq!(incomplete_suffix.as_bytes(), b"\xE2\x82");
+            let back: Incomplete = incomplete_suffix.into();
+            assert_eq!(back.buffer_len, 2);
+            assert_eq!(&back.buffer[..2], b"\xE2\x82");
+        }
+        other => panic!("{:?}", other),
+    }
+    let three = Incomplete::new(b"\xF0\x9F\x8C");
+    assert!(IncompleteN::<2>::from_incomplete(&three).is_none());
+    assert_eq!(
+        IncompleteN::<3>::from_incomplete(&three).unwrap(),
+        IncompleteN::<3>::new(&three.buffer[..3])
+    );
+
+    assert!(IncompleteN::<3>::empty().is_empty());
+    assert_eq!(std::mem::size_of::<IncompleteN<3>>(), 4);
+}
+
+#[test]
+fn test_decode_without_nul() {
+    assert_eq!(decode_without_nul(b"").unwrap(), "");
+    assert_eq!(decode_without_nul("a\u{E9}".as_bytes()).unwrap(), "a\u{E9}");
+    match decode_without_nul("\u{E9}\0x".as_bytes()) {
+        Err(PolicyError::Rejected {
+            valid_prefix: "\u{E9}",
+            rejection,
+        }) => assert_eq!(
+            rejection,
+            PolicyRejection {
+                byte_offset: 2,
+                reason: PolicyReason::Nul
+            }
+        ),
+        other => panic!("{:?}", other),
+    }
+
+    // A genuine UTF-8 error is a `DecodeError`, unless a violation comes before it.
+    assert!(matches!(
+        decode_without_nul(b"ab\xFF\0"),
+        Err(PolicyError::Decode(DecodeError::Invalid {
+            valid_prefix: "ab",
+            ..
+        }))
+    ));
+    assert!(matches!(
+        decode_without_nul(b"ab\xE2\x82"),
+        Err(PolicyError::Decode(DecodeError::Incomplete { .. }))
+    ));
+    assert!(matches!(
+        decode_without_nul(b"a\0\xFF"),
+        Err(PolicyError::Rejected { .. })
+    ));
+}
+
+#[test]
+fn test_valid_runs() {
+    assert_eq!(
+        valid_runs(b"ab\xFF\xC0\x80cd\xE2\x82\xACe"),
+        ["ab", "cd\u{20AC}e"]
+    );
+    assert_eq!(valid_runs("\u{E9}t\u{E9}".as_bytes()), ["\u{E9}t\u{E9}"]);
+    assert_eq!(valid_runs(b"\xFFx\xFF\xFFy\xF0\x9F"), ["x", "y"]);
+    assert!(valid_runs(b"").is_empty());
+    assert!(valid_runs(b"\xFF\xFE").is_empty());
+    for &(input, expected) in DECODED_LOSSY {
+        assert_eq!(valid_runs(input).concat(), expected.replace('\u{FFFD}', ""));
+    }
+}
+
+#[test]
+fn test_stream_decoder() {
+    fn decode_chunks(chunks: &[&[u8]]) -> String {
+        let mut decoder = StreamDecoder::new();
+        let mut output = String::new();
+        for &chunk in chunks {
+            output.extend(decoder.feed(chunk));
+        }
+        output.extend(decoder.finish());
+        output
+    }
+
+    for &(input, expected) in DECODED_LOSSY {
+        assert_eq!(decode_chunks(&[input]), expected);
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        assert_eq!(decode_chunks(&bytes), expected, "{:?}", input);
+        assert_eq!(decode_chunks(&bytes), String::from_utf8_lossy(input));
+    }
+
+    let input = b"a\xC3\xA9\xFF\xF0\x9F\x8C\x8D\xE2\x82";
+    all_partitions(input, |chunks| {
+        assert_eq!(decode_chunks(chunks), String::from_utf8_lossy(input));
+    });
+}
+
+#[test]
+fn test_decode_carry() {
+    assert_eq!(decode_carry(b""), (Ok(""), 0));
+    assert_eq!(decode_carry("ab\u{20AC}".as_bytes()), (Ok("ab\u{20AC}"), 0));
+    assert_eq!(decode_carry(b"ab\xFF"), (Err(&b"\xFF"[..]), 0));
+    assert_eq!(decode_carry(b"ab\xF0\x9F\xFF"), (Err(&b"\xF0\x9F"[..]), 0));
+    assert_eq!(decode_carry(b"ab\xC3"), (Ok("ab"), 1));
+    assert_eq!(decode_carry(b"ab\xE2\x82"), (Ok("ab"), 2));
+    assert_eq!(decode_carry(b"ab\xF0\x9F\x8C"), (Ok("ab"), 3));
+    // A hard error before the carried bytes is still reported, as is a lead byte cut
+    // short by the carried sequence.
+    assert_eq!(decode_carry(b"a\xFFb\xC3"), (Err(&b"\xFF"[..]), 1));
+    assert_eq!(decode_carry(b"a\xC3\xE2"), (Err(&b"\xC3"[..]), 1));
+
+    // Driving a chunked decode with it reproduces lossy decoding.
+    for &(input, expected) in DECODED_LOSSY {
+        for chunk_len in 1..5 {
+            let mut buffer = Vec::new();
+            let mut output = String::new();
+            for chunk in input.chunks(chunk_len) {
+                buffer.extend_from_slice(chunk);
+                let mut rest = &buffer[..];
+                loop {
+                    let (result, carry) = decode_carry(rest);
+                    match result {
+                        Ok(s) => {
+                            output.push_str(s);
+                            rest = &rest[rest.len() - carry..];
+                            break;
+                        }
+                        Err(invalid) => {
+                            let offset = invalid.as_ptr() as usize - rest.as_ptr() as usize;
+                            output.push_str(std::str::from_utf8(&rest[..offset]).unwrap());
+                            output.push_str(REPLACEMENT_CHARACTER);
+                            rest = &rest[offset + invalid.len()..];
+                        }
+                    }
+                }
+                buffer = rest.to_vec();
+            }
+            if !buffer.is_empty() {
+                output.push_str(REPLACEMENT_CHARACTER);
+            }
+            assert_eq!(output, expected);
+        }
+    }
+}
+
+#[test]
+fn test_next_valid_offset() {
+    for &(input, expected) in DECODED_LOSSY {
+        let mut rest = input;
+        let mut output = String::new();
+        loop {
+            match decode(rest) {
+                Ok(s) => {
+                    output.push_str(s);
+                    break;
+                }
+                Err(error) => {
+                    let offset = error.next_valid_offset();
+                    match error {
+                        DecodeError::Invalid {
+                            valid_prefix,
+                            remaining_input,
+                            ..
+                        } => {
+                            assert_eq!(offset, rest.len() - remaining_input.len());
+                            output.push_str(valid_prefix);
+                        }
+                        DecodeError::Incomplete { valid_prefix, .. } => {
+                            assert_eq!(offset, rest.len());
+                            output.push_str(valid_prefix);
+                        }
+                    }
+                    output.push_str(REPLACEMENT_CHARACTER);
+                    rest = &rest[offset..];
+                }
+            }
+        }
+        assert_eq!(output, expected);
+    }
+}
+
+#[cfg(feature = "unicode-bidi")]
+#[test]
+fn test_decode_text_direction() {
+    let direction = |s: &str| decode_text_direction(s.as_bytes()).unwrap().0;
+    assert_eq!(direction("Hello"), TextDirection::Ltr);
+    assert_eq!(
+        direction("\u{645}\u{631}\u{62D}\u{628}\u{627}"),
+        TextDirection::Rtl
+    );
+    assert_eq!(
+        direction("\u{5E9}\u{5DC}\u{5D5}\u{5DD} world"),
+        TextDirection::Rtl
+    );
+    assert_eq!(direction("42, (7)! abc"), TextDirection::Ltr);
+    assert_eq!(direction("2024-01-01"), TextDirection::Neutral);
+    assert_eq!(direction(""), TextDirection::Neutral);
+    // Text inside an isolate doesn't count.
+    assert_eq!(direction("\u{2067}abc\u{2069}\u{5D0}"), TextDirection::Rtl);
+
+    let (_, s) = decode_text_direction("\u{5D0}b".as_bytes()).unwrap();
+    assert_eq!(s, "\u{5D0}b");
+    assert!(decode_text_direction(b"\xD7\x90\xFF").is_err());
+}
+
+#[test]
+fn test_decode_to_json_string() {
+    let json = |input: &[u8]| {
+        let mut out = String::from("prefix:");
+        decode_to_json_string(input, &mut out);
+        out
+    };
+    assert_eq!(json(b""), "prefix:");
+    assert_eq!(
+        json("caf\u{E9} \u{1F30D}".as_bytes()),
+        "prefix:caf\u{E9} \u{1F30D}"
+    );
+    assert_eq!(
+        json(br#"a "quoted" \path"#),
+        r#"prefix:a \"quoted\" \\path"#
+    );
+    assert_eq!(
+        json(b"\x00\x01\x08\x09\x0A\x0C\x0D\x1B\x1F\x20\x7F"),
+        "prefix:\\u0000\\u0001\\b\\t\\n\\f\\r\\u001b\\u001f \x7F"
+    );
+    assert_eq!(
+        json(b"a\xFFb\xC0\x80c\xE2\x82"),
+        r"prefix:a\ufffdb\ufffd\ufffdc\ufffd"
+    );
+
+    // Invalid sequences are escaped where lossy decoding inserts U+FFFD.
+    for &(input, expected) in DECODED_LOSSY {
+        let mut out = String::new();
+        decode_to_json_string(input, &mut out);
+        assert_eq!(out.replace(r"\ufffd", "\u{FFFD}"), expected);
+    }
+}
+
+#[test]
+fn test_decode_slices() {
+    assert_eq!(decode_slices(&[]), Ok(()));
+    assert_eq!(decode_slices(&[b"", b"abc", b""]), Ok(()));
+    let error = |slice_index, local_offset, kind| {
+        Err(SliceError {
+            slice_index,
+            local_offset,
+            kind,
+        })
+    };
+
+    assert_eq!(
+        decode_slices(&[b"abc", b"de\xC0\x80f", b"ghi"]),
+        error(1, 2, DecodeErrorKind::Invalid)
+    );
+    // The invalid sequence starts in the first slice.
+    assert_eq!(
+        decode_slices(&[b"ab\xE2", b"\x82", b"x"]),
+        error(0, 2, DecodeErrorKind::Invalid)
+    );
+    assert_eq!(
+        decode_slices(&[b"ab\xE2", b"", b"\x82\xAC", b"\xFF"]),
+        error(3, 0, DecodeErrorKind::Invalid)
+    );
+    // Incomplete at the very end reports the last slice.
+    assert_eq!(
+        decode_slices(&[b"ab\xF0", b"\x9F", b"\x8C"]),
+        error(2, 1, DecodeErrorKind::Incomplete)
+    );
+    assert_eq!(
+        decode_slices(&[b"ab\xF0\x9F", b""]),
+        error(1, 0, DecodeErrorKind::Incomplete)
+    );
+
+    let input = "a\u{E9}\u{20AC}\u{1F30D}".as_bytes();
+    all_partitions(input, |chunks| assert_eq!(decode_slices(chunks), Ok(())));
+    let input = b"a\xC3\xA9\xF0\x9F\xFFb";
+    all_partitions(input, |chunks| assert!(decode_slices(chunks).is_err()));
+}
+
+#[test]
+fn test_error_ranges() {
+    let mut errors = vec![(99, 9)];
+    error_ranges(b"", &mut errors);
+    error_ranges("ok \u{E9}".as_bytes(), &mut errors);
+    assert_eq!(errors, [(99, 9)]);
+
+    for &(input, expected) in DECODED_LOSSY {
+        let mut errors = Vec::new();
+        error_ranges(input, &mut errors);
+        // Splicing U+FFFD over each range reproduces lossy decoding.
+        let mut lossy = String::new();
+        let mut end = 0;
+        for &(offset, len) in &errors {
+            let offset = offset as usize;
+            assert!(offset >= end && (1..=3).contains(&len));
+            lossy.push_str(std::str::from_utf8(&input[end..offset]).unwrap());
+            lossy.push_str(REPLACEMENT_CHARACTER);
+            end = offset + len as usize;
+        }
+        lossy.push_str(std::str::from_utf8(&input[end..]).unwrap());
+        assert_eq!(lossy, expected, "{:?}", input);
+    }
+}
+
+#[test]
+fn test_last_char() {
+    assert!(matches!(last_char(b""), Ok(None)));
+    assert!(matches!(last_char(b"a"), Ok(Some(('a', 1)))));
+    assert!(matches!(
+        last_char("a\u{E9}".as_bytes()),
+        Ok(Some(('\u{E9}', 2)))
+    ));
+    assert!(matches!(
+        last_char("\u{1F30D}\u{1F30D}".as_bytes()),
+        Ok(Some(('\u{1F30D}', 4)))
+    ));
+    // The rest of the input is not looked at.
+    assert!(matches!(last_char(b"\xFFz"), Ok(Some(('z', 1)))));
+
+    for input in [
+        &b"ab\x80"[..],
+        b"\x80",
+        b"\xC3\xA9\xA9",
+        b"\x9F\x8C\x8D\x8D",
+        b"a\xFF",
+    ] {
+        match last_char(input) {
+            Err(DecodeError::Invalid {
+                valid_prefix: "",
+                invalid_sequence,
+                remaining_input: b"",
+            }) => assert_eq!(invalid_sequence, &input[input.len() - 1..]),
+            other => panic!("{:?} {:?}", input, other),
+        }
+    }
+    match last_char(b"ab\xF0\x9F\x8C") {
+        Err(DecodeError::Incomplete {
+            valid_prefix: "",
+            incomplete_suffix,
+        }) => assert_eq!(&incomplete_suffix.buffer[..3], b"\xF0\x9F\x8C"),
+        other => panic!("{:?}", other),
+    }
+
+    // Walking backward char by char gives the chars in reverse.
+    let s = "a\u{E9}\u{20AC}\u{1F30D}z";
+    let mut input = s.as_bytes();
+    let mut reversed = String::new();
+    while let Some((c, len)) = last_char(input).unwrap() {
+        reversed.push(c);
+        input = &input[..input.len() - len];
+    }
+    assert_eq!(reversed, s.chars().rev().collect::<String>());
+}
+
+#[test]
+fn test_decode_with_rolling() {
+    let s = "a\u{E9}\u{20AC}\u{1F30D}";
+    let mut chars = Vec::new();
+    decode_with_rolling(s.as_bytes(), |c| chars.push(c)).unwrap();
+    assert_eq!(chars, s.chars().collect::<Vec<_>>());
+
+    let mut chars = Vec::new();
+    decode_with_rolling(b"", |c| chars.push(c)).unwrap();
+    assert!(chars.is_empty());
+
+    let mut chars = String::new();
+    match decode_with_rolling(b"ab\xFFcd", |c| chars.push(c)) {
+        Err(DecodeError::Invalid {
+            valid_prefix: "ab",
+            remaining_input: b"cd",
+            ..
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(chars, "ab");
+
+    let mut chars = String::new();
+    assert!(decode_with_rolling(b"x\xE2\x82", |c| chars.push(c)).is_err());
+    assert_eq!(chars, "x");
+}
+
+#[test]
+fn test_lossy_truncation_marker() {
+    let decode = |chunks: &[&[u8]]| {
+        let mut output = String::new();
+        {
+            let mut decoder = LossyDecoder::with_truncation_marker("...", |s| output.push_str(s));
+            for chunk in chunks {
+                decoder.feed(chunk);
+            }
+        }
+        output
+    };
+    assert_eq!(decode(&[b"abc"]), "abc");
+    assert_eq!(decode(&[b"ab\xF0\x9F\x8C"]), "ab...");
+    assert_eq!(decode(&[b"ab\xF0", b"\x9F", b"\x8C"]), "ab...");
+    // Interior errors, including a split sequence that turns out invalid, use U+FFFD.
+    assert_eq!(decode(&[b"a\xFFb\xC0\x80c"]), "a\u{FFFD}b\u{FFFD}\u{FFFD}c");
+    assert_eq!(decode(&[b"a\xE2\x82", b"b"]), "a\u{FFFD}b");
+    assert_eq!(decode(&[b"\xFF\xE2"]), "\u{FFFD}...");
+    // A lone continuation byte at the end is invalid, not truncated.
+    assert_eq!(decode(&[b"a\x82"]), "a\u{FFFD}");
+}
+
+#[test]
+fn test_utf8_chunks() {
+    assert_eq!(utf8_chunks(b"").next(), None);
+    let chunks: Vec<_> = utf8_chunks(b"ab\xFF\xFEc\xC3\xA9\xF0\x9Fd\xE2\x82").collect();
+    assert_eq!(
+        chunks,
+        [
+            Utf8Chunk {
+                valid: "ab",
+                invalid: b"\xFF"
+            },
+            Utf8Chunk {
+                valid: "",
+                invalid: b"\xFE"
+            },
+            Utf8Chunk {
+                valid: "c\u{E9}",
+                invalid: b"\xF0\x9F"
+            },
+            Utf8Chunk {
+                valid: "d",
+                invalid: b"\xE2\x82"
+            },
+        ]
+    );
+    let chunks: Vec<_> = utf8_chunks(b"\xFFok").collect();
+    assert_eq!(chunks.last().unwrap().invalid, b"");
+
+    for &(input, expected) in DECODED_LOSSY {
+        let mut lossy = String::new();
+        let mut bytes = Vec::new();
+        let chunks: Vec<_> = utf8_chunks(input).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(!chunk.invalid.is_empty() || i == chunks.len() - 1);
+            lossy.push_str(chunk.valid);
+            if !chunk.invalid.is_empty() {
+                lossy.push_str(REPLACEMENT_CHARACTER);
+            }
+            bytes.extend_from_slice(chunk.valid.as_bytes());
+            bytes.extend_from_slice(chunk.invalid);
+        }
+        assert_eq!(lossy, expected);
+        assert_eq!(bytes, input);
+    }
+}
+
+#[test]
+fn test_decode_lossy() {
+    assert!(matches!(decode_lossy(b""), Cow::Borrowed("")));
+    assert!(matches!(
+        decode_lossy("\u{E9}t\u{E9}".as_bytes()),
+        Cow::Borrowed("\u{E9}t\u{E9}")
+    ));
+    assert!(matches!(decode_lossy(b"a\xE2\x82"), Cow::Owned(_)));
+    for &(input, expected) in DECODED_LOSSY {
+        assert_eq!(decode_lossy(input), expected);
+        assert_eq!(decode_lossy(input), String::from_utf8_lossy(input));
+    }
+}
+
+#[test]
+fn test_error_offset_and_invalid_len() {
+    for &(input, _) in DECODED_LOSSY {
+        let error = match decode(input) {
+            Ok(_) => continue,
+            Err(error) => error,
+        };
+        let std_error = std::str::from_utf8(input).unwrap_err();
+        assert_eq!(error.error_offset(), std_error.valid_up_to());
+        assert_eq!(error.invalid_len(), std_error.error_len());
+    }
+}
+
+#[test]
+fn test_decode_error_accessors() {
+    fn describe(error: &DecodeError) -> (String, Option<Vec<u8>>, Vec<u8>) {
+        (
+            error.valid_prefix().to_owned(),
+            error.invalid_sequence().map(<[u8]>::to_vec),
+            error.remaining().to_vec(),
+        )
+    }
+    let error = decode(b"ab\xF0\x9F!cd").unwrap_err();
+    assert_eq!(
+        describe(&error),
+        ("ab".into(), Some(b"\xF0\x9F".to_vec()), b"!cd".to_vec())
+    );
+    let error = decode(b"ab\xF0\x9F").unwrap_err();
+    assert_eq!(describe(&error), ("ab".into(), None, vec![]));
+
+    // The slices borrow from the input, not from the error.
+    let input = b"x\xFFy".to_vec();
+    let (valid, remaining) = {
+        let error = decode(&input).unwrap_err();
+        (error.valid_prefix(), error.remaining())
+    };
+    assert_eq!((valid, remaining), ("x", &b"y"[..]));
+}
+
+#[test]
+fn test_decode_error_kind() {
+    let mut counts = [0; 2];
+    for input in [
+        &b"a\xFF"[..],
+        b"\xC0\x80",
+        b"a\xC3",
+        b"\xF0\x9F\x8C",
+        b"\xED\xA0\x80",
+    ] {
+        let error = decode(input).unwrap_err();
+        let is_incomplete = matches!(error, DecodeError::Incomplete { .. });
+        assert_eq!(error.is_incomplete(), is_incomplete);
+        assert_eq!(error.is_invalid(), !is_incomplete);
+        match error.kind() {
+            DecodeErrorKind::Invalid => counts[0] += 1,
+            DecodeErrorKind::Incomplete => counts[1] += 1,
+            _ => unreachable!(),
+        }
+    }
+    assert_eq!(counts, [3, 2]);
+}
+
+#[test]
+fn test_invalid_reason() {
+    use InvalidReason::*;
+    let cases: &[(&[u8], InvalidReason)] = &[
+        (b"\xC0\x80", Overlong),
+        (b"a\xC1\xBF", Overlong),
+        (b"\xE0\x80\x80", Overlong),
+        (b"\xE0\x9F\xBF", Overlong),
+        (b"\xF0\x80\x80\x80", Overlong),
+        (b"\xF0\x8F\xBF\xBF", Overlong),
+        (b"\xED\xA0\x80", Surrogate),
+        (b"\xED\xBF\xBF", Surrogate),
+        (b"\xF4\x90\x80\x80", OutOfRange),
+        (b"\xF5\x80\x80\x80", OutOfRange),
+        (b"\xF7", OutOfRange),
+        (b"\x80", UnexpectedContinuation),
+        (b"a\xBFb", UnexpectedContinuation),
+        (b"\xC3a", UnexpectedEnd),
+        (b"\xE2\x82a", UnexpectedEnd),
+        (b"\xF0\x9F\x8Ca", UnexpectedEnd),
+        (b"\xE0a", UnexpectedEnd),
+        (b"\xF8", InvalidByte),
+        (b"\xFF\x80", InvalidByte),
+    ];
+    for &(input, expected) in cases {
+        let error = decode(input).unwrap_err();
+        assert!(error.is_invalid(), "{:?}", input);
+        assert_eq!(error.invalid_reason(), Some(expected), "{:?}", input);
+    }
+
+    assert_eq!(decode(b"\xE2\x82").unwrap_err().invalid_reason(), None);
+    // Valid neighbours of the invalid ranges.
+    for valid in [
+        &b"\xC2\x80"[..],
+        b"\xE0\xA0\x80",
+        b"\xED\x9F\xBF",
+        b"\xF0\x90\x80\x80",
+        b"\xF4\x8F\xBF\xBF",
+    ] {
+        assert!(decode(valid).is_ok());
+    }
+}
+
+#[test]
+fn test_decode_error_eq() {
+    assert_eq!(
+        decode(b"ab\xFFcd"),
+        Err(DecodeError::Invalid {
+            valid_prefix: "ab",
+            invalid_sequence: b"\xFF",
+            remaining_input: b"cd",
+        })
+    );
+    assert_eq!(
+        decode(b"\xC3"),
+        Err(DecodeError::Incomplete {
+            valid_prefix: "",
+            incomplete_suffix: Incomplete::new(b"\xC3"),
+        })
+    );
+    assert_ne!(decode(b"\xC3"), decode(b"\xE2"));
+    assert_ne!(decode(b"a\xC3"), decode(b"\xC3"));
+
+    // Bytes past `buffer_len` don't take part in the comparison.
+    let stale = Incomplete {
+        buffer: [0xE2, 0x82, 0xAC, 0xFF],
+        buffer_len: 2,
+    };
+    assert_eq!(stale, Incomplete::new(b"\xE2\x82"));
+    assert_ne!(stale, Incomplete::new(b"\xE2\x82\xAC"));
+    assert_eq!(
+        Incomplete::empty(),
+        Incomplete {
+            buffer: [1, 2, 3, 4],
+            buffer_len: 0
+        }
+    );
+}
+
+#[test]
+fn test_owned_decode_error() {
+    fn owned(input: Vec<u8>) -> OwnedDecodeError {
+        decode(&input).unwrap_err().into()
+    }
+    for input in [&b"ab\xF0\x9F!cd"[..], b"ab\xE2\x82", b"\xFF"] {
+        let error = owned(input.to_vec());
+        let borrowed = decode(input).unwrap_err();
+        assert_eq!(error.to_string(), borrowed.to_string());
+        assert_eq!(error.as_decode_error(), borrowed);
+        assert_eq!(error, borrowed.into_owned());
+    }
+    assert_eq!(
+        owned(b"ab\xFFcd".to_vec()),
+        OwnedDecodeError::Invalid {
+            valid_prefix: "ab".into(),
+            invalid_sequence: vec![0xFF],
+            remaining_input: b"cd".to_vec(),
+        }
+    );
+    let error: Box<dyn std::error::Error> = Box::new(owned(b"\xC3".to_vec()));
+    assert!(error
+        .to_string()
+        .starts_with("found incomplete byte sequence"));
+}
+
+#[test]
+fn test_validate() {
+    assert!(validate(b""));
+    assert!(validate("a\u{E9}\u{20AC}\u{1F30D}".as_bytes()));
+    assert!(!validate(b"\xFF"));
+    assert!(!validate(b"a\xF0\x9F\x8C"));
+    for &(input, _) in DECODED_LOSSY {
+        assert_eq!(validate(input), decode(input).is_ok());
+    }
+}
+
+#[test]
+fn test_first_invalid_offset() {
+    assert_eq!(first_invalid_offset(b""), None);
+    assert_eq!(first_invalid_offset("a\u{E9}\u{1F30D}".as_bytes()), None);
+    // Truncated in the middle of a code point.
+    assert_eq!(first_invalid_offset(b"a\xF0\x9F\x8C"), Some(1));
+    assert_eq!(first_invalid_offset(b"a\xF0\x9F\x8Cb"), Some(1));
+    // Stray continuation byte.
+    assert_eq!(first_invalid_offset("\u{E9}\u{A9}".as_bytes()), None);
+    assert_eq!(first_invalid_offset(b"\xC3\xA9\xA9"), Some(2));
+    assert_eq!(first_invalid_offset(b"\x80"), Some(0));
+}
+
+#[test]
+fn test_count_replacements() {
+    assert_eq!(count_replacements(b""), 0);
+    assert_eq!(count_replacements("\u{FFFD}".as_bytes()), 0);
+    // One per maximal invalid subpart, not per byte.
+    assert_eq!(count_replacements(b"\xF0\x9F\x8C"), 1);
+    assert_eq!(count_replacements(b"\xF0\x9F\x8C!"), 1);
+    assert_eq!(count_replacements(b"\xC0\x80"), 2);
+    assert_eq!(count_replacements(b"\xED\xA0\x80"), 3);
+    assert_eq!(count_replacements(b"\xFF\xFE\xFD"), 3);
+    for &(input, expected) in DECODED_LOSSY {
+        let already_there = input.windows(3).filter(|w| *w == b"\xEF\xBF\xBD").count();
+        assert_eq!(
+            count_replacements(input),
+            expected.matches('\u{FFFD}').count() - already_there,
+            "{:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_decode_up_to() {
+    let input = "ab\u{20AC}cd".as_bytes();
+    assert_eq!(decode_up_to(input, 0).unwrap(), "");
+    assert_eq!(decode_up_to(input, 2).unwrap(), "ab");
+    // The limit lands inside the 3-byte sequence.
+    assert_eq!(decode_up_to(input, 3).unwrap(), "ab");
+    assert_eq!(decode_up_to(input, 4).unwrap(), "ab");
+    assert_eq!(decode_up_to(input, 5).unwrap(), "ab\u{20AC}");
+    assert_eq!(decode_up_to(input, 7).unwrap(), "ab\u{20AC}cd");
+    assert_eq!(decode_up_to(input, 100).unwrap(), "ab\u{20AC}cd");
+    assert_eq!(decode_up_to("\u{1F30D}".as_bytes(), 3).unwrap(), "");
+
+    // Errors before the limit are reported, those after it are not looked at.
+    assert_eq!(
+        decode_up_to(b"a\xFFbcd", 3),
+        Err(DecodeError::Invalid {
+            valid_prefix: "a",
+            invalid_sequence: b"\xFF",
+            remaining_input: b"bcd",
+        })
+    );
+    assert_eq!(
+        decode_up_to(b"a\xE2\x82bcd", 4),
+        Err(DecodeError::Invalid {
+            valid_prefix: "a",
+            invalid_sequence: b"\xE2\x82",
+            remaining_input: b"bcd",
+        })
+    );
+    assert!(decode_up_to(b"ab\xE2\x82", 10).unwrap_err().is_incomplete());
+    assert_eq!(decode_up_to(b"abc\xE2\x82", 3).unwrap(), "abc");
+    assert_eq!(decode_up_to(b"abc\xFF\xFF", 3).unwrap(), "abc");
+}
+
+#[test]
+fn test_next_code_point() {
+    assert_eq!(next_code_point(b""), Ok(None));
+    assert_eq!(next_code_point(b"ab"), Ok(Some(('a', 1))));
+    assert_eq!(
+        next_code_point("\u{E9}b".as_bytes()),
+        Ok(Some(('\u{E9}', 2)))
+    );
+    assert_eq!(
+        next_code_point("\u{20AC}".as_bytes()),
+        Ok(Some(('\u{20AC}', 3)))
+    );
+    assert_eq!(
+        next_code_point("\u{1F30D}\u{1F30D}".as_bytes()),
+        Ok(Some(('\u{1F30D}', 4)))
+    );
+    // Only the first code point is validated.
+    assert_eq!(next_code_point(b"a\xFF"), Ok(Some(('a', 1))));
+    assert_eq!(
+        next_code_point(b"\xC3\xA9\xE2\x82"),
+        Ok(Some(('\u{E9}', 2)))
+    );
+
+    assert_eq!(
+        next_code_point(b"\xF0\x9F\x8C"),
+        Err(DecodeError::Incomplete {
+            valid_prefix: "",
+            incomplete_suffix: Incomplete::new(b"\xF0\x9F\x8C"),
+        })
+    );
+    assert_eq!(
+        next_code_point(b"\xF0\x9F!abcd"),
+        Err(DecodeError::Invalid {
+            valid_prefix: "",
+            invalid_sequence: b"\xF0\x9F",
+            remaining_input: b"!abcd",
+        })
+    );
+    assert_eq!(
+        next_code_point(b"\x80"),
+        Err(DecodeError::Invalid {
+            valid_prefix: "",
+            invalid_sequence: b"\x80",
+            remaining_input: b"",
+        })
+    );
+
+    let s = "a\u{E9}\u{20AC}\u{1F30D}z";
+    let mut input = s.as_bytes();
+    let mut chars = String::new();
+    while let Some((c, len)) = next_code_point(input).unwrap() {
+        chars.push(c);
+        input = &input[len..];
+    }
+    assert_eq!(chars, s);
+}
+
+#[test]
+fn test_to_utf8_error() {
+    let inputs = [
+        &b"ab\xFFcd"[..],
+        b"\xFF",
+        b"ab\xF0\x9F!",
+        b"ab\xE2\x82",
+        b"\xC0\x80",
+        b"\xED\xA0\x80x",
+        b"\xC3",
+    ];
+    let lossy_inputs = DECODED_LOSSY.iter().map(|&(input, _)| input);
+    for input in inputs.into_iter().chain(lossy_inputs) {
+        let expected = match std::str::from_utf8(input) {
+            Ok(_) => continue,
+            Err(error) => error,
+        };
+        let error = decode(input).unwrap_err().to_utf8_error().unwrap();
+        assert_eq!(error, expected, "{:?}", input);
+        assert_eq!(error.valid_up_to(), expected.valid_up_to());
+        assert_eq!(error.error_len(), expected.error_len());
+        let converted = std::str::Utf8Error::try_from(decode(input).unwrap_err());
+        assert_eq!(converted, Ok(expected));
+    }
+}
+
+#[test]
+fn test_to_utf8_error_valid_input() {
+    // Restricted decoders that reject valid UTF-8 report it with their own errors.
+    let four_byte = "a\u{1F30D}".as_bytes();
+    assert!(matches!(
+        decode_max_len::<3>(four_byte),
+        Err(MaxLenError::TooLong { .. })
+    ));
+    assert!(matches!(
+        decode_profiled(
+            four_byte,
+            ConformanceProfile {
+                allow_four_byte: false,
+                ..ConformanceProfile::default()
+            }
+        ),
+        Err(ProfileError::FourByte { .. })
+    ));
+
+    // A hand-built error can still describe valid UTF-8.
+    let error = DecodeError::Invalid {
+        valid_prefix: "a",
+        invalid_sequence: "\u{1F30D}".as_bytes(),
+        remaining_input: b"",
+    };
+    assert_eq!(error.to_utf8_error(), None);
+    assert_eq!(std::str::Utf8Error::try_from(error), Err(error));
+    let error = DecodeError::Incomplete {
+        valid_prefix: "a",
+        incomplete_suffix: Incomplete::empty(),
+    };
+    assert_eq!(error.to_utf8_error(), None);
+}
+
+#[test]
+fn test_incomplete_needed() {
+    assert_eq!(Incomplete::new(b"\xC3").needed(), 1);
+    assert_eq!(Incomplete::new(b"\xE2").needed(), 2);
+    assert_eq!(Incomplete::new(b"\xE2\x82").needed(), 1);
+    assert_eq!(Incomplete::new(b"\xF0\x9F").needed(), 2);
+    assert_eq!(Incomplete::new(b"\xF0\x9F\x8C").needed(), 1);
+    assert_eq!(Incomplete::empty().needed(), 0);
+    assert_eq!(Incomplete::new(b"\x80").needed(), 0);
+    assert_eq!(Incomplete::new(b"\xFF").needed(), 0);
+    assert_eq!(Incomplete::new(b"a").needed(), 0);
+
+    // Feeding exactly `needed()` bytes is enough to complete it.
+    let input = "\u{1F30D}".as_bytes();
+    for split in 1..input.len() {
+        let mut incomplete = Incomplete::new(&input[..split]);
+        let needed = incomplete.needed();
+        assert_eq!(needed, input.len() - split);
+        assert!(matches!(
+            incomplete.try_complete(&input[split..split + needed]),
+            Some((Ok("\u{1F30D}"), b""))
+        ));
+    }
+}
+
+#[test]
+fn test_incomplete_expected_len() {
+    for lead in 0..=255u8 {
+        let expected = match lead {
+            0xC2..=0xDF => Some(2),
+            0xE0..=0xEF => Some(3),
+            0xF0..=0xF4 => Some(4),
+            _ => None,
+        };
+        assert_eq!(
+            Incomplete::new(&[lead]).expected_len(),
+            expected,
+            "{:x}",
+            lead
+        );
+    }
+    // Agrees with the length std encodes with, for one code point of each length.
+    for c in ['\u{E9}', '\u{20AC}', '\u{1F30D}'] {
+        let mut buffer = [0; 4];
+        let lead = c.encode_utf8(&mut buffer).as_bytes()[0];
+        assert_eq!(Incomplete::new(&[lead]).expected_len(), Some(c.len_utf8()));
+    }
+    assert_eq!(Incomplete::new(b"\x80").expected_len(), None);
+    assert_eq!(Incomplete::new(b"\xFF").expected_len(), None);
+    // `buffer_len` is not looked at.
+    let stale = Incomplete {
+        buffer: [0xF0, 0, 0, 0],
+        buffer_len: 0,
+    };
+    assert_eq!(stale.expected_len(), Some(4));
+}
+
+#[test]
+fn test_incomplete_push() {
+    let mut incomplete = Incomplete::empty();
+    for &byte in b"\xF0\x9F\x8C" {
+        incomplete.push(byte).unwrap();
+        assert!(incomplete.try_complete(&[]).is_none());
+    }
+    assert_eq!(incomplete, Incomplete::new(b"\xF0\x9F\x8C"));
+    incomplete.push(0x8D).unwrap();
+    assert_eq!(incomplete.push(b'!'), Err(()));
+    assert_eq!(incomplete.buffer_len, 4);
+    assert!(matches!(
+        incomplete.try_complete(&[]),
+        Some((Ok("\u{1F30D}"), b""))
+    ));
+}
+
+#[test]
+fn test_incomplete_as_bytes() {
+    assert_eq!(Incomplete::empty().as_bytes(), b"");
+    for bytes in [
+        &b"\xC3"[..],
+        b"\xE2\x82",
+        b"\xF0\x9F\x8C",
+        b"\xF0\x9F\x8C\x8D",
+    ] {
+        assert_eq!(Incomplete::new(bytes).as_bytes(), bytes);
+    }
+    match decode(b"ab\xE2\x82") {
+        Err(DecodeError::Incomplete {
+            incomplete_suffix, ..
+        }) => assert_eq!(incomplete_suffix.as_bytes(), b"\xE2\x82"),
+        other => panic!("{:?}", other),
+    }
+    // A `buffer_len` that is out of range doesn't panic.
+    let bad = Incomplete {
+        buffer: [1, 2, 3, 4],
+        buffer_len: 200,
+    };
+    assert_eq!(bad.as_bytes(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn test_incomplete_default() {
+    #[derive(Default)]
+    struct Reader {
+        state: Incomplete,
+    }
+    assert!(Incomplete::default().is_empty());
+    assert_eq!(Incomplete::default(), Incomplete::empty());
+    assert!(Reader::default().state.is_empty());
+}
+
+#[test]
+fn test_incomplete_hash() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(incomplete: &Incomplete) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        incomplete.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Same prefix, different history: this one held a complete code point before.
+    let mut reused = Incomplete::new(b"\xF0\x9F\x8C");
+    assert!(reused.try_complete(b"\x8D").is_some());
+    reused.push(0xE2).unwrap();
+    let fresh = Incomplete::new(b"\xE2");
+    assert_ne!(reused.buffer, fresh.buffer);
+    assert_eq!(reused, fresh);
+    assert_eq!(hash(&reused), hash(&fresh));
+
+    let mut cache = HashMap::new();
+    cache.insert(fresh, "euro?");
+    assert_eq!(cache.get(&reused), Some(&"euro?"));
+    assert_eq!(cache.get(&Incomplete::new(b"\xE2\x82")), None);
+}
+
+#[test]
+fn test_incomplete_clear() {
+    let mut incomplete = Incomplete::new(b"\xE2\x82");
+    incomplete.clear();
+    assert!(incomplete.is_empty());
+    assert_eq!(incomplete, Incomplete::empty());
+    // It can be reused afterwards.
+    assert!(incomplete.try_complete(b"\xC3").is_none());
+    assert!(matches!(
+        incomplete.try_complete(b"\xA9"),
+        Some((Ok("\u{E9}"), b""))
+    ));
+}
+
+#[test]
+fn test_incomplete_try_new() {
+    assert_eq!(Incomplete::try_new(&[]), Some(Incomplete::empty()));
+    assert_eq!(Incomplete::try_new(&[0; 4]), Some(Incomplete::new(&[0; 4])));
+    assert_eq!(Incomplete::try_new(b"\xC3"), Some(Incomplete::new(b"\xC3")));
+    assert_eq!(Incomplete::try_new(&[0; 5]), None);
+    assert_eq!(Incomplete::try_new(&[0; 100]), None);
+}
+
+#[test]
+fn test_incomplete_try_complete_with_consumed() {
+    let mut incomplete = Incomplete::new(b"\xF0");
+    assert!(incomplete.try_complete_with_consumed(b"").is_none());
+    assert!(incomplete.try_complete_with_consumed(b"\x9F\x8C").is_none());
+    assert_eq!(incomplete.as_bytes(), b"\xF0\x9F\x8C");
+    assert!(matches!(
+        incomplete.try_complete_with_consumed(b"\x8Drest"),
+        Some((Ok("\u{1F30D}"), 1))
+    ));
+    assert!(incomplete.is_empty());
+
+    // An invalid continuation consumes nothing from the input.
+    let mut incomplete = Incomplete::new(b"\xE2\x82");
+    assert!(matches!(
+        incomplete.try_complete_with_consumed(b"abc"),
+        Some((Err(b"\xE2\x82"), 0))
+    ));
+    let mut incomplete = Incomplete::new(b"\xE2");
+    assert!(matches!(
+        incomplete.try_complete_with_consumed(b"\x82\xFF"),
+        Some((Err(b"\xE2\x82"), 1))
+    ));
+
+    // The count agrees with `try_complete`.
+    for &(input, _) in DECODED_LOSSY {
+        for split in 1..std::cmp::min(4, input.len()) {
+            let mut a = Incomplete::new(&input[..split]);
+            let mut b = a;
+            let rest = &input[split..];
+            let with_remaining = a
+                .try_complete(rest)
+                .map(|(r, remaining)| (r.is_ok(), rest.len() - remaining.len()));
+            let with_consumed = b
+                .try_complete_with_consumed(rest)
+                .map(|(r, consumed)| (r.is_ok(), consumed));
+            assert_eq!(with_remaining, with_consumed);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_incomplete_serde() {
+    for bytes in [&b""[..], b"\xC3", b"\xE2\x82", b"\xF0\x9F\x8C"] {
+        let incomplete = Incomplete::new(bytes);
+        let json = serde_json::to_string(&incomplete).unwrap();
+        assert_eq!(json, serde_json::to_string(bytes).unwrap());
+        let back: Incomplete = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, incomplete);
+    }
+
+    // Stale bytes past `buffer_len` are not serialized.
+    let stale = Incomplete {
+        buffer: [0xE2, 0x82, 0xAC, 0xFF],
+        buffer_len: 1,
+    };
+    assert_eq!(serde_json::to_string(&stale).unwrap(), "[226]");
+
+    assert!(serde_json::from_str::<Incomplete>("[1,2,3,4]").is_ok());
+    assert!(serde_json::from_str::<Incomplete>("[1,2,3,4,5]").is_err());
+    assert!(serde_json::from_str::<Incomplete>("[256]").is_err());
+    assert!(serde_json::from_str::<Incomplete>("\"abcde\"").is_err());
+}
+
+#[test]
+fn test_lossy_feed_counted() {
+    let mut output = String::new();
+    {
+        let mut decoder = LossyDecoder::new(|s| output.push_str(s));
+        assert_eq!(decoder.feed_counted(b"one\xFFtwo\xF0\x9Fthree"), 2);
+        assert_eq!(decoder.feed_counted(b""), 0);
+        // The split sequence only counts once it is known to be invalid.
+        assert_eq!(decoder.feed_counted(b"\xE2\x82"), 0);
+        assert_eq!(decoder.feed_counted(b"!"), 1);
+    }
+    assert_eq!(output, "one\u{FFFD}two\u{FFFD}three\u{FFFD}!");
+
+    // Per-byte mode counts every replacement.
+    let mut output = String::new();
+    let count = LossyDecoder::with_per_byte_replacement(|s| output.push_str(s))
+        .feed_counted(b"\xF0\x9F\xFF");
+    assert_eq!(count, 3);
+
+    // The trailing incomplete sequence is counted at the end.
+    let mut decoder = LossyDecoder::to_string();
+    assert_eq!(decoder.feed_counted(b"ok"), 0);
+    assert_eq!(decoder.finish_counted(), ("ok".to_owned(), 0));
+    let mut decoder = LossyDecoder::to_string();
+    assert_eq!(decoder.feed_counted(b"\xFFok\xF0\x9F\x8C"), 1);
+    assert_eq!(decoder.finish_counted().1, 1);
+    let mut decoder = LossyDecoder::with_per_byte_replacement(|_| {});
+    decoder.feed(b"\xF0\x9F");
+    assert_eq!(decoder.finish_counted().1, 2);
+}
+
+#[test]
+fn test_lossy_finish() {
+    let mut pieces = Vec::new();
+    let mut decoder = LossyDecoder::new(|s: &str| pieces.push(s.to_owned()));
+    decoder.feed(b"ab\xC3");
+    let _ = decoder.finish();
+    assert_eq!(pieces, ["ab", "\u{FFFD}"]);
+
+    // Nothing buffered, nothing pushed.
+    let mut pieces = Vec::new();
+    let mut decoder = LossyDecoder::new(|s: &str| pieces.push(s.to_owned()));
+    decoder.feed(b"ab");
+    let _ = decoder.finish();
+    assert_eq!(pieces, ["ab"]);
+
+    // The callback is returned and can still be called.
+    let mut count = 0;
+    let mut decoder = LossyDecoder::new(|_: &str| count += 1);
+    decoder.feed(b"a\xE2\x82");
+    let mut push_str = decoder.finish();
+    push_str("more");
+    assert_eq!(count, 3);
+
+    // Forensics and held-back output are flushed too.
+    let mut output = String::new();
+    let mut bad = Vec::new();
+    let mut decoder = LossyDecoder::with_forensics(
+        |s| output.push_str(s),
+        |offset, bytes: &[u8]| bad.push((offset, bytes.to_vec())),
+    );
+    decoder.feed(b"x\xE2\x82");
+    let _ = decoder.finish();
+    assert_eq!(output, "x\u{FFFD}");
+    assert_eq!(bad, [(1, b"\xE2\x82".to_vec())]);
+
+    let mut output = String::new();
+    let mut decoder = LossyDecoder::with_line_aligned_output(|s| output.push_str(s));
+    decoder.feed(b"one\ntwo\xC3");
+    let _ = decoder.finish();
+    assert_eq!(output, "one\ntwo\u{FFFD}");
+}
+
+#[test]
+fn test_lossy_reset() {
+    let mut output = String::new();
+    {
+        let mut bad = Vec::new();
+        let mut decoder = LossyDecoder::with_forensics(
+            |s| output.push_str(s),
+            |offset, bytes: &[u8]| bad.push((offset, bytes.to_vec())),
+        );
+        // Message A ends in the middle of "é"; message B starts with a continuation byte
+        // that would complete it if A's bytes were carried over.
+        decoder.feed(b"A\xC3");
+        decoder.reset();
+        decoder.feed(b"\xA9B\xFF");
+        decoder.reset();
+        drop(decoder);
+        assert_eq!(bad, [(0, vec![0xA9]), (2, vec![0xFF])]);
+    }
+    // Only the lone continuation byte and the `FF` are replaced, not the discarded `C3`.
+    assert_eq!(output, "A\u{FFFD}B\u{FFFD}");
+
+    let mut output = String::new();
+    {
+        let mut decoder = LossyDecoder::with_line_aligned_output(|s| output.push_str(s));
+        decoder.feed(b"one\npartial");
+        decoder.reset();
+        decoder.feed(b"two\n");
+    }
+    assert_eq!(output, "one\ntwo\n");
+}
+
+#[test]
+fn test_lossy_with_replacement() {
+    for (replacement, expected) in [
+        ("?", "a?b??c?"),
+        ("", "abc"),
+        ("<bad>", "a<bad>b<bad><bad>c<bad>"),
+    ] {
+        for chunk_len in 1..4 {
+            let input = b"a\xFFb\xC0\x80c\xE2\x82";
+            let mut output = String::new();
+            {
+                let mut decoder =
+                    LossyDecoder::with_replacement(replacement, |s| output.push_str(s));
+                for chunk in input.chunks(chunk_len) {
+                    decoder.feed(chunk);
+                }
+            }
+            assert_eq!(output, expected);
+        }
+    }
+}
+
+#[test]
+fn test_lossy_new_with_sink() {
+    #[derive(Default)]
+    struct Accumulator {
+        text: String,
+        bytes: usize,
+        calls: usize,
+    }
+
+    impl Utf8Sink for Accumulator {
+        fn push_str(&mut self, s: &str) {
+            self.text.push_str(s);
+            self.bytes += s.len();
+            self.calls += 1;
+        }
+    }
+
+    for &(input, expected) in DECODED_LOSSY {
+        for chunk_size in 1..=4 {
+            let mut decoder = LossyDecoder::new_with_sink(Accumulator::default());
+            for chunk in input.chunks(chunk_size) {
+                decoder.feed(chunk);
+            }
+            let sink = decoder.finish();
+            assert_eq!(sink.text, expected, "input = {:?}", input);
+            assert_eq!(sink.bytes, expected.len());
+            assert!(sink.calls <= sink.bytes);
+        }
+    }
+}
+
+#[test]
+fn test_lossy_from_fmt_write() {
+    for &(input, _) in DECODED_LOSSY {
+        for chunk_size in 1..=4 {
+            let mut decoder = LossyDecoder::from_fmt_write(String::new());
+            for chunk in input.chunks(chunk_size) {
+                decoder.feed(chunk);
+            }
+            let sink = decoder.finish();
+            assert_eq!(sink.result(), Ok(()));
+            assert_eq!(sink.into_inner(), String::from_utf8_lossy(input));
+        }
+    }
+
+    struct Limited(String);
+
+    impl std::fmt::Write for Limited {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            if self.0.len() + s.len() > 4 {
+                return Err(std::fmt::Error);
+            }
+            self.0.push_str(s);
+            Ok(())
+        }
+    }
+
+    let mut decoder = LossyDecoder::from_fmt_write(Limited(String::new()));
+    decoder.feed(b"ab");
+    decoder.feed(b"\xFFcd");
+    decoder.feed(b"e");
+    let sink = decoder.finish();
+    assert_eq!(sink.result(), Err(std::fmt::Error));
+    assert_eq!(sink.get_ref().0, "ab");
+}
+
+#[test]
+fn test_utf8_lossy_writer() {
+    for &(input, expected) in DECODED_LOSSY {
+        let mut output = Vec::new();
+        let copied = io::copy(&mut &*input, &mut Utf8LossyWriter::new(&mut output)).unwrap();
+        assert_eq!(copied, input.len() as u64);
+        assert_eq!(output, expected.as_bytes(), "input = {:?}", input);
+
+        for chunk_size in 1..=4 {
+            let mut output = Vec::new();
+            {
+                let mut writer = Utf8LossyWriter::new(&mut output);
+                for chunk in input.chunks(chunk_size) {
+                    io::Write::write_all(&mut writer, chunk).unwrap();
+                }
+            }
+            assert_eq!(output, expected.as_bytes(), "input = {:?}", input);
+        }
+    }
+
+    let mut output = Vec::new();
+    let mut writer = Utf8LossyWriter::new(&mut output);
+    io::Write::write_all(&mut writer, b"caf\xC3").unwrap();
+    io::Write::flush(&mut writer).unwrap();
+    io::Write::write_all(&mut writer, b"\xA9").unwrap();
+    drop(writer);
+    assert_eq!(output, "caf\u{FFFD}\u{FFFD}".as_bytes());
+}
+
+#[test]
+fn test_strict_decoder() {
+    fn run(chunks: &[&[u8]]) -> (String, Vec<StrictDecoderError>) {
+        let mut output = String::new();
+        let mut errors = Vec::new();
+        let mut decoder = StrictDecoder::new(|s| output.push_str(s));
+        for &chunk in chunks {
+            let mut start = decoder.position();
+            let mut chunk = chunk;
+            while let Err(error) = decoder.feed(chunk) {
+                errors.push(error);
+                chunk = &chunk[decoder.position() - start..];
+                start = decoder.position();
+            }
+        }
+        if let Err(error) = decoder.finish() {
+            errors.push(error);
+        }
+        (output, errors)
+    }
+
+    fn invalid(byte_offset: usize, invalid_len: usize) -> StrictDecoderError {
+        StrictDecoderError {
+            byte_offset,
+            invalid_len,
+            kind: DecodeErrorKind::Invalid,
+        }
+    }
+
+    let input = "aé€\u{1F30D}".as_bytes();
+    all_partitions(input, |chunks| {
+        assert_eq!(
+            run(chunks),
+            (std::str::from_utf8(input).unwrap().into(), vec![])
+        );
+    });
+
+    // Error at the start of a chunk.
+    assert_eq!(
+        run(&[b"ab", b"\xFFcd"]),
+        ("abcd".into(), vec![invalid(2, 1)])
+    );
+    // Error at the end of a chunk.
+    assert_eq!(
+        run(&[b"ab\xFF", b"cd"]),
+        ("abcd".into(), vec![invalid(2, 1)])
+    );
+    // Incomplete sequence shown to be invalid by the next chunk.
+    assert_eq!(
+        run(&[b"ab\xE2\x82", b"cd"]),
+        ("abcd".into(), vec![invalid(2, 2)])
+    );
+    assert_eq!(
+        run(&[b"ab\xE2", b"\x82", b"\xFF"]),
+        ("ab".into(), vec![invalid(2, 2), invalid(4, 1)])
+    );
+    assert_eq!(
+        run(&[b"ab\xF0\x9F"]),
+        (
+            "ab".into(),
+            vec![StrictDecoderError {
+                byte_offset: 2,
+                invalid_len: 2,
+                kind: DecodeErrorKind::Incomplete,
+            }]
+        )
+    );
+    assert_eq!(
+        invalid(5, 1).to_string(),
+        "found invalid byte sequence at offset 5"
+    );
+}
+
+#[test]
+fn test_lossy_positions() {
+    let mut chunks_seen = Vec::new();
+    let mut decoder = LossyDecoder::new(|s| chunks_seen.push(s.to_owned())).track_char_position();
+    let mut positions = Vec::new();
+    for chunk in [
+        &b"ab\xE2"[..],
+        b"\x82",
+        b"\xAC\xFF",
+        b"",
+        b"\xF0\x9F\x8C\x8D",
+        b"z",
+    ] {
+        decoder.feed(chunk);
+        positions.push((decoder.byte_position(), decoder.char_position()));
+    }
+    assert_eq!(
+        positions,
+        [
+            (2, Some(2)),
+            (2, Some(2)),
+            (6, Some(4)),
+            (6, Some(4)),
+            (10, Some(5)),
+            (11, Some(6))
+        ]
+    );
+    drop(decoder);
+    assert_eq!(chunks_seen.concat(), "ab\u{20AC}\u{FFFD}\u{1F30D}z");
+
+    for &(input, _) in DECODED_LOSSY {
+        let mut decoder = LossyDecoder::new(|_| {}).track_char_position();
+        for chunk in input.chunks(3) {
+            decoder.feed(chunk);
+        }
+        // A trailing incomplete sequence is buffered and not yet counted.
+        let consumed = decoder.byte_position();
+        assert!(input.len() - consumed < 4);
+        let decoded = String::from_utf8_lossy(&input[..consumed]);
+        assert_eq!(decoder.char_position(), Some(decoded.chars().count()));
+    }
+
+    // Chars are only counted when asked for.
+    let mut decoder = LossyDecoder::new(|_| {});
+    decoder.feed(b"abc");
+    assert_eq!(
+        (decoder.byte_position(), decoder.char_position()),
+        (3, None)
+    );
+}
+
+#[test]
+fn test_lossy_pending() {
+    let mut output = String::new();
+    {
+        let mut decoder = LossyDecoder::new(|s| output.push_str(s));
+        assert!(decoder.pending().is_empty());
+        decoder.feed(b"\xE2");
+        assert_eq!(decoder.pending(), [0xE2]);
+        assert_eq!(decoder.pending(), [0xE2]);
+        decoder.feed(b"\x82");
+        assert_eq!(decoder.pending(), [0xE2, 0x82]);
+        decoder.feed(b"x");
+        assert!(decoder.pending().is_empty());
+        decoder.feed(b"\xF0\x9F\x8C");
+        assert_eq!(decoder.pending(), [0xF0, 0x9F, 0x8C]);
+    }
+    assert_eq!(output, "\u{FFFD}x\u{FFFD}");
+}
+
+#[test]
+fn test_replacement_policy() {
+    let chunks: &[&[u8]] = &[b"a\xFFb\xE2", b"\x82c\xF0\x9F"];
+    let lossy = |policy| {
+        let mut output = String::new();
+        let error;
+        {
+            let mut decoder = LossyDecoder::with_policy(policy, |s| output.push_str(s));
+            let mut result = Ok(());
+            for &chunk in chunks {
+                result = decoder.try_feed(chunk);
+                if result.is_err() {
+                    break;
+                }
+            }
+            error = result.err();
+        }
+        (output, error)
+    };
+    let strict = |policy| {
+        let mut output = String::new();
+        let mut decoder = StrictDecoder::with_policy(policy, |s| output.push_str(s));
+        let mut error = None;
+        for &chunk in chunks {
+            if let Err(e) = decoder.feed(chunk) {
+                error = Some(e);
+                break;
+            }
+        }
+        if error.is_none() {
+            error = decoder.finish().err();
+        }
+        (output, error)
+    };
+
+    assert_eq!(
+        ReplacementPolicy::default(),
+        ReplacementPolicy::Lossy("\u{FFFD}")
+    );
+    let expected = ("a\u{FFFD}b\u{FFFD}c\u{FFFD}".to_owned(), None);
+    assert_eq!(lossy(ReplacementPolicy::default()), expected);
+    assert_eq!(strict(ReplacementPolicy::default()), expected);
+    let expected = ("a?b?c?".to_owned(), None);
+    assert_eq!(lossy(ReplacementPolicy::Lossy("?")), expected);
+    assert_eq!(strict(ReplacementPolicy::Lossy("?")), expected);
+    let expected = ("a?b??c??".to_owned(), None);
+    assert_eq!(lossy(ReplacementPolicy::LossyPerByte("?")), expected);
+    assert_eq!(strict(ReplacementPolicy::LossyPerByte("?")), expected);
+    let expected = ("a?b?c?".to_owned(), None);
+    assert_eq!(lossy(ReplacementPolicy::LossyCoalesced("?")), expected);
+    assert_eq!(strict(ReplacementPolicy::LossyCoalesced("?")), expected);
+    let truncation = ReplacementPolicy::TruncationMarker {
+        replacement: "?",
+        marker: "...",
+    };
+    let expected = ("a?b?c...".to_owned(), None);
+    assert_eq!(lossy(truncation), expected);
+    assert_eq!(strict(truncation), expected);
+    let expected = ("abc".to_owned(), None);
+    assert_eq!(lossy(ReplacementPolicy::Skip), expected);
+    assert_eq!(strict(ReplacementPolicy::Skip), expected);
+    let expected = (
+        "a".to_owned(),
+        Some(StrictDecoderError {
+            byte_offset: 1,
+            invalid_len: 1,
+            kind: DecodeErrorKind::Invalid,
+        }),
+    );
+    assert_eq!(lossy(ReplacementPolicy::Strict), expected);
+    assert_eq!(strict(ReplacementPolicy::Strict), expected);
+
+    // Both decoders stop at the error, even mid-chunk, and carry on after it with the
+    // next chunk.
+    let mut lossy_output = String::new();
+    let mut strict_output = String::new();
+    {
+        let mut decoder =
+            LossyDecoder::with_policy(ReplacementPolicy::Strict, |s| lossy_output.push_str(s));
+        let mut strict_decoder = StrictDecoder::new(|s| strict_output.push_str(s));
+        for chunk in [&b"ab\xE2"[..], b"\x82c\xFF", b"c\xFF", b"d"] {
+            assert_eq!(decoder.try_feed(chunk), strict_decoder.feed(chunk));
+            let position = decoder.byte_position() + decoder.pending().len();
+            assert_eq!(position, strict_decoder.position());
+        }
+        assert_eq!(decoder.error(), None);
+        assert_eq!(
+            decoder.try_feed(b"\xFF"),
+            Err(StrictDecoderError {
+                byte_offset: 7,
+                invalid_len: 1,
+                kind: DecodeErrorKind::Invalid,
+            })
+        );
+        assert!(decoder.pending().is_empty());
+        decoder.reset();
+        assert_eq!(decoder.error(), None);
+        assert_eq!(decoder.try_feed(b"more"), Ok(()));
+    }
+    assert_eq!(strict_output, "abcd");
+    assert_eq!(lossy_output, "abcdmore");
+}
+
+#[test]
+fn test_lossy_skipping() {
+    let mut output = String::new();
+    LossyDecoder::skipping(|s| output.push_str(s)).feed(b"a\xFFb");
+    assert_eq!(output, "ab");
+
+    for &(input, expected) in DECODED_LOSSY {
+        for chunk_size in 1..=4 {
+            let mut lossy = Vec::new();
+            let mut skipped = Vec::new();
+            {
+                let mut decoder = LossyDecoder::new(|s| lossy.push(s.to_owned()));
+                let mut skipping = LossyDecoder::skipping(|s| skipped.push(s.to_owned()));
+                for chunk in input.chunks(chunk_size) {
+                    decoder.feed(chunk);
+                    skipping.feed(chunk);
+                }
+            }
+            lossy.retain(|s| s != "\u{FFFD}");
+            assert_eq!(skipped, lossy, "input = {:?}", input);
+            assert_eq!(skipped.concat(), expected.replace('\u{FFFD}', ""));
+        }
+    }
+}
+
+#[test]
+fn test_lossy_to_string() {
+    for &(input, _) in DECODED_LOSSY {
+        for chunk_size in 1..=4 {
+            let mut decoder = LossyDecoder::to_string();
+            for chunk in input.chunks(chunk_size) {
+                decoder.feed(chunk);
+            }
+            assert_eq!(decoder.finish(), String::from_utf8_lossy(input));
+        }
+    }
+}
+
+#[test]
+fn test_bufread_decoder_next_strict() {
+    let input = b"a\xE2\x82\xFFb\xF0\x9F\x8C";
+    all_partitions(input, |chunks| {
+        let mut decoder = BufReadDecoder::new(Chunks(chunks.to_vec().into()));
+        let mut events: Vec<Result<String, Vec<u8>>> = Vec::new();
+        while let Some(result) = decoder.next_strict() {
+            match (result, events.last_mut()) {
+                (Ok(s), Some(Ok(last))) => last.push_str(s),
+                (Ok(s), _) => events.push(Ok(s.to_owned())),
+                (Err(BufReadDecoderError::InvalidByteSequence(bytes)), _) => {
+                    events.push(Err(bytes.to_vec()))
+                }
+                (Err(BufReadDecoderError::Io(error)), _) => panic!("{}", error),
+            }
+        }
+        assert_eq!(
+            events,
+            [
+                Ok("a".to_owned()),
+                Err(vec![0xE2, 0x82]),
+                Err(vec![0xFF]),
+                Ok("b".to_owned()),
+                Err(vec![0xF0, 0x9F, 0x8C]),
+            ],
+            "chunks = {:?}",
+            chunks
+        );
+        assert!(decoder.next_strict().is_none());
+    });
+}
+
+#[test]
+fn test_bufread_decoder_lines_lossy() {
+    fn lines(chunks: &[&[u8]]) -> Vec<String> {
+        BufReadDecoder::new(Chunks(chunks.to_vec().into()))
+            .lines_lossy()
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    all_partitions(b"a\r\n\xE2\nb\xFF\r\n\nc", |chunks| {
+        assert_eq!(lines(chunks), ["a", "\u{FFFD}", "b\u{FFFD}", "", "c"]);
+    });
+    all_partitions(b"\xF0\x9F\nx\r\n", |chunks| {
+        assert_eq!(lines(chunks), ["\u{FFFD}", "x"]);
+    });
+    assert_eq!(lines(&[b"x\ry\r"]), ["x\ry\r"]);
+    assert!(lines(&[b""]).is_empty());
+}
+
+#[test]
+fn test_bufread_decoder_read_to_string_strict() {
+    fn strict(chunks: &[&[u8]]) -> Result<String, (Vec<u8>, usize)> {
+        match BufReadDecoder::read_to_string_strict(Chunks(chunks.to_vec().into())) {
+            Ok(string) => Ok(string),
+            Err(ReadToStringError::InvalidByteSequence { bytes, position }) => {
+                Err((bytes, position))
+            }
+            Err(ReadToStringError::Io(error)) => panic!("{}", error),
+        }
+    }
+
+    all_partitions("aé€\u{1F30D}".as_bytes(), |chunks| {
+        assert_eq!(strict(chunks), Ok("aé€\u{1F30D}".to_owned()));
+    });
+    all_partitions(b"ab\xE2\x82cd", |chunks| {
+        assert_eq!(strict(chunks), Err((vec![0xE2, 0x82], 2)));
+    });
+    all_partitions(b"\xC3\xA9\xFF\xFF", |chunks| {
+        assert_eq!(strict(chunks), Err((vec![0xFF], 2)));
+    });
+    // A truncated ending is invalid, not dropped.
+    all_partitions(b"ab\xF0\x9F\x8C", |chunks| {
+        assert_eq!(strict(chunks), Err((vec![0xF0, 0x9F, 0x8C], 2)));
+    });
+    assert_eq!(strict(&[b""]), Ok(String::new()));
+
+    struct Failing;
+
+    impl io::Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("boom"))
+        }
+    }
+
+    match BufReadDecoder::read_to_string_strict(io::BufReader::new(Failing)) {
+        Err(ReadToStringError::Io(error)) => assert_eq!(error.to_string(), "boom"),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_bufread_decoder_into_lossy_chunks() {
+    for &(input, _) in DECODED_LOSSY {
+        for capacity in 1..=5 {
+            let reader = io::BufReader::with_capacity(capacity, input);
+            let chunks: Vec<String> = BufReadDecoder::new(reader)
+                .into_lossy_chunks()
+                .collect::<io::Result<_>>()
+                .unwrap();
+            assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+            assert_eq!(chunks.concat(), String::from_utf8_lossy(input));
+        }
+    }
+}
+
+#[test]
+fn test_bufread_decoder_read_to_string_lossy_with() {
+    for &(input, expected) in DECODED_LOSSY {
+        all_partitions(input, |chunks| {
+            let with = |replacement| {
+                let chunks = Chunks(chunks.to_vec().into());
+                BufReadDecoder::read_to_string_lossy_with(chunks, replacement).unwrap()
+            };
+            assert_eq!(with("\u{FFFD}"), expected);
+            for replacement in ["?", ""] {
+                let mut string = String::new();
+                LossyDecoder::with_replacement(replacement, |s| string.push_str(s)).feed(input);
+                assert_eq!(with(replacement), string);
+            }
+        });
+    }
+    let input = &b"a\xFFb\xF0\x9F\x8Cc\xC3"[..];
+    assert_eq!(
+        BufReadDecoder::read_to_string_lossy_with(input, "?").unwrap(),
+        "a?b?c?"
+    );
+    assert_eq!(
+        BufReadDecoder::read_to_string_lossy_with(input, "").unwrap(),
+        "abc"
+    );
+}
+
+#[test]
+fn test_bufread_decoder_chars_lossy() {
+    let input = "a\u{1F30D}b".as_bytes();
+    for capacity in 1..=6 {
+        let reader = io::BufReader::with_capacity(capacity, input);
+        let chars: Vec<char> = BufReadDecoder::new(reader)
+            .chars_lossy()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(chars, ['a', '\u{1F30D}', 'b']);
+    }
+
+    for &(input, expected) in DECODED_LOSSY {
+        all_partitions(input, |chunks| {
+            let chars: String = BufReadDecoder::new(Chunks(chunks.to_vec().into()))
+                .chars_lossy()
+                .collect::<io::Result<_>>()
+                .unwrap();
+            assert_eq!(chars, expected);
+        });
+    }
+}
+
+#[test]
+fn test_bufread_decoder_into_inner() {
+    let input = &b"header\r\n\xC3\xA9\xE2"[..];
+    let mut decoder = BufReadDecoder::new(input);
+    assert_eq!(decoder.next_strict().unwrap().unwrap(), "header\r\n\u{E9}");
+    assert_eq!(*decoder.get_ref(), input);
+    assert_eq!(*decoder.get_mut(), b"\xE2");
+    let (rest, incomplete) = decoder.into_inner();
+    assert_eq!(rest, b"\xE2");
+    assert!(incomplete.is_empty());
+
+    let input = &b"text\xFF\x00raw"[..];
+    let mut decoder = BufReadDecoder::new(input);
+    assert_eq!(decoder.next_strict().unwrap().unwrap(), "text");
+    assert!(decoder.next_strict().unwrap().is_err());
+    let (mut rest, _) = decoder.into_inner();
+    let mut raw = Vec::new();
+    io::Read::read_to_end(&mut rest, &mut raw).unwrap();
+    assert_eq!(raw, b"\x00raw");
+
+    // An I/O error in the middle of a code point leaves its first bytes in the decoder.
+    struct FailAfter<'a>(&'a [u8]);
+
+    impl io::Read for FailAfter<'_> {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            unimplemented!()
+        }
+    }
+
+    impl io::BufRead for FailAfter<'_> {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            if self.0.is_empty() {
+                Err(io::Error::other("boom"))
+            } else {
+                Ok(self.0)
+            }
+        }
+
+        fn consume(&mut self, bytes: usize) {
+            self.0 = &self.0[bytes..];
+        }
+    }
+
+    let mut decoder = BufReadDecoder::new(FailAfter(b"head\xF0\x9F"));
+    assert_eq!(decoder.next_strict().unwrap().unwrap(), "head");
+    assert!(matches!(
+        decoder.next_strict(),
+        Some(Err(BufReadDecoderError::Io(_)))
+    ));
+    let (rest, incomplete) = decoder.into_inner();
+    assert!(rest.0.is_empty());
+    assert_eq!(incomplete.as_bytes(), b"\xF0\x9F");
+}
+
+#[test]
+fn test_bufread_decoder_error_io_conversions() {
+    fn fails() -> Result<(), BufReadDecoderError<'static>> {
+        Err(io::Error::new(io::ErrorKind::UnexpectedEof, "cut short"))?;
+        Ok(())
+    }
+
+    let error = fails().unwrap_err();
+    let io_error = error.as_io_error().unwrap();
+    assert_eq!(io_error.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(io_error.to_string(), "cut short");
+    assert_eq!(
+        error.lossy().unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+
+    let error = BufReadDecoderError::InvalidByteSequence(b"\xFF");
+    assert!(error.as_io_error().is_none());
+}
+
+#[test]
+fn test_utf8_lossy_reader() {
+    for &(input, _) in DECODED_LOSSY {
+        let mut output = String::new();
+        io::Read::read_to_string(&mut Utf8LossyReader::new(input), &mut output).unwrap();
+        assert_eq!(
+            output,
+            String::from_utf8_lossy(input),
+            "input = {:?}",
+            input
+        );
+    }
+
+    // Small buffers: reads end on code point boundaries where one fits.
+    let input = b"a\xF0\x9F\x8C\x8D\xFFb";
+    for size in 1..=8 {
+        let mut reader = Utf8LossyReader::new(&input[..]);
+        let mut output = Vec::new();
+        let mut buf = [0; 8];
+        loop {
+            let len = io::Read::read(&mut reader, &mut buf[..size]).unwrap();
+            if len == 0 {
+                break;
+            }
+            if size >= 4 {
+                assert!(std::str::from_utf8(&buf[..len]).is_ok());
+            }
+            output.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(output, "a\u{1F30D}\u{FFFD}b".as_bytes());
+    }
+}
+
+#[test]
+fn test_bufread_decoder_position() {
+    let input = b"a\xFF\xE2\x82\xC3\xA9\xF0";
+    all_partitions(input, |chunks| {
+        let mut decoder = BufReadDecoder::new(Chunks(chunks.to_vec().into()));
+        let mut errors = Vec::new();
+        let mut ends = Vec::new();
+        loop {
+            let len = match decoder.next_strict() {
+                None => break,
+                Some(Ok(s)) => {
+                    ends.push(s.len());
+                    continue;
+                }
+                Some(Err(BufReadDecoderError::InvalidByteSequence(bytes))) => bytes.len(),
+                Some(Err(BufReadDecoderError::Io(error))) => panic!("{}", error),
+            };
+            errors.push(decoder.position() - len);
+        }
+        assert_eq!(errors, [1, 2, 6]);
+        assert_eq!(decoder.position(), input.len());
+        assert_eq!(ends.iter().sum::<usize>(), 3);
+    });
+}
+
+#[test]
+fn test_bufread_decoder_read_to_string_lossy_into() {
+    let mut out = String::new();
+    let first = &b"caf\xC3\xA9 \xFF"[..];
+    let second = &b"\xA9then\xF0\x9F"[..];
+    assert_eq!(
+        BufReadDecoder::read_to_string_lossy_into(first, &mut out).unwrap(),
+        9
+    );
+    assert_eq!(
+        BufReadDecoder::read_to_string_lossy_into(second, &mut out).unwrap(),
+        10
+    );
+    assert_eq!(out, "caf\u{E9} \u{FFFD}\u{FFFD}then\u{FFFD}");
+    assert_eq!(
+        out,
+        String::from_utf8_lossy(first) + String::from_utf8_lossy(second)
+    );
+
+    // The allocation is reused.
+    out.clear();
+    let capacity = out.capacity();
+    BufReadDecoder::read_to_string_lossy_into(&b"short"[..], &mut out).unwrap();
+    assert_eq!(out, "short");
+    assert_eq!(out.capacity(), capacity);
+}
+
+#[test]
+fn test_skip_bom() {
+    assert_eq!(skip_bom(b"\xEF\xBB\xBFhello"), b"hello");
+    assert_eq!(skip_bom(b"hello"), b"hello");
+    assert_eq!(skip_bom(b"\xEF\xBB\xBF"), b"");
+    assert_eq!(skip_bom(b""), b"");
+    // Only one leading BOM is removed, and a partial one is kept.
+    assert_eq!(skip_bom(b"\xEF\xBB\xBF\xEF\xBB\xBF"), b"\xEF\xBB\xBF");
+    assert_eq!(skip_bom(b"\xEF\xBB"), b"\xEF\xBB");
+    assert_eq!(skip_bom(b"a\xEF\xBB\xBF"), b"a\xEF\xBB\xBF");
+}
+
+#[test]
+fn test_bufread_decoder_new_skip_bom() {
+    fn read(chunks: &[&[u8]]) -> (String, usize) {
+        let mut decoder = BufReadDecoder::new_skip_bom(Chunks(chunks.to_vec().into()));
+        let mut string = String::new();
+        while let Some(result) = decoder.next_lossy() {
+            string.push_str(result.unwrap());
+        }
+        (string, decoder.position())
+    }
+
+    all_partitions(b"\xEF\xBB\xBFa\xEF\xBB\xBF", |chunks| {
+        assert_eq!(
+            read(chunks),
+            ("a\u{FEFF}".to_owned(), 7),
+            "chunks = {:?}",
+            chunks
+        );
+    });
+    all_partitions(b"\xEF\xBB\xBF\xEF\xBB\xBF", |chunks| {
+        assert_eq!(read(chunks), ("\u{FEFF}".to_owned(), 6));
+    });
+    all_partitions(b"\xEF\xBBa", |chunks| {
+        assert_eq!(read(chunks), ("\u{FFFD}a".to_owned(), 3));
+    });
+    assert_eq!(read(&[b"\xEF", b"\xBB\xBF"]), (String::new(), 3));
+    assert_eq!(read(&[b"a\xEF\xBB\xBF"]), ("a\u{FEFF}".to_owned(), 4));
+    assert_eq!(read(&[b""]), (String::new(), 0));
+}
+
+#[test]
+fn test_decode_modified_utf8() {
+    assert!(matches!(
+        decode_modified_utf8(b"plain \xC3\xA9"),
+        Ok(Cow::Borrowed("plain \u{E9}"))
+    ));
+    assert_eq!(decode_modified_utf8(b"").unwrap(), "");
+
+    // Embedded NUL.
+    assert_eq!(decode_modified_utf8(b"a\xC0\x80b").unwrap(), "a\0b");
+    assert_eq!(decode_modified_utf8(b"\xC0\x80\xC0\x80").unwrap(), "\0\0");
+
+    // Astral code points as surrogate pairs: U+1F30D and U+10FFFF.
+    let pair = b"x\xED\xA0\xBC\xED\xBC\x8Dy\xED\xAF\xBF\xED\xBF\xBF";
+    assert_eq!(decode_modified_utf8(pair).unwrap(), "x\u{1F30D}y\u{10FFFF}");
+    // Standard 4-byte form is accepted too.
+    assert_eq!(
+        decode_modified_utf8("\u{1F30D}".as_bytes()).unwrap(),
+        "\u{1F30D}"
+    );
+
+    // Lone or reversed surrogates and other malformed input are rejected.
+    match decode_modified_utf8(b"a\xC0\x80b\xED\xA0\xBCc") {
+        Err(DecodeError::Invalid {
+            valid_prefix: "b",
+            invalid_sequence: b"\xED",
+            remaining_input: b"\xA0\xBCc",
+        }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(decode_modified_utf8(b"\xED\xBC\x8D\xED\xA0\xBC").is_err());
+    assert!(decode_modified_utf8(b"\xC0\x81").is_err());
+    assert!(decode_modified_utf8(b"\xFF").is_err());
+    assert!(matches!(
+        decode_modified_utf8(b"a\xC0\x80\xE2\x82"),
+        Err(DecodeError::Incomplete {
+            valid_prefix: "",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_wtf8() {
+    let surrogate_free = "aé€\u{1F30D}";
+    assert!(is_valid_wtf8(surrogate_free.as_bytes()));
+    assert_eq!(
+        decode_wtf8(surrogate_free.as_bytes()).unwrap(),
+        surrogate_free.encode_utf16().collect::<Vec<_>>()
+    );
+
+    // A lone high surrogate, U+D83D, alone, in text, and at the end.
+    for (input, expected) in [
+        (&b"\xED\xA0\xBD"[..], &[0xD83D][..]),
+        (b"a\xED\xA0\xBDb", &[0x61, 0xD83D, 0x62]),
+        (b"ab\xED\xA0\xBD", &[0x61, 0x62, 0xD83D]),
+        // Lone low surrogate, and a low followed by a high one: not a pair.
+        (b"\xED\xBC\x8D", &[0xDF0D]),
+        (b"\xED\xBC\x8D\xED\xA0\xBD", &[0xDF0D, 0xD83D]),
+    ] {
+        assert!(is_valid_wtf8(input));
+        assert!(std::str::from_utf8(input).is_err());
+        assert_eq!(decode_wtf8(input).unwrap(), expected);
+    }
+
+    // Errors: a surrogate pair that should have been one code point, truncated
+    // surrogates and ordinary invalid UTF-8.
+    assert_eq!(decode_wtf8(b"a\xED\xA0\xBC\xED\xBC\x8D"), Err(4));
+    assert_eq!(decode_wtf8(b"a\xED\xA0"), Err(1));
+    assert_eq!(decode_wtf8(b"\xED\xA0\xBDa\xFF"), Err(4));
+    assert_eq!(decode_wtf8(b"\xC0\x80"), Err(0));
+    assert!(!is_valid_wtf8(b"a\xED\xA0\xBC\xED\xBC\x8D"));
+    assert!(!is_valid_wtf8(b"\xE2\x82"));
+}
+
+#[test]
+fn test_decode_latin1_fallback() {
+    assert!(matches!(
+        decode_latin1_fallback("caf\u{E9}".as_bytes()),
+        Cow::Borrowed("caf\u{E9}")
+    ));
+    assert_eq!(decode_latin1_fallback(b""), "");
+
+    // Stray Latin-1 bytes mixed with UTF-8.
+    assert_eq!(
+        decode_latin1_fallback(b"na\xEFve \xC3\xA9t\xE9"),
+        "na\u{EF}ve \u{E9}t\u{E9}"
+    );
+    assert_eq!(
+        decode_latin1_fallback(b"\xA3100 \xE2\x82\xAC"),
+        "\u{A3}100 \u{20AC}"
+    );
+    // Every byte of a multi-byte invalid sequence is mapped, including one cut off at the end.
+    assert_eq!(decode_latin1_fallback(b"\xF0\x9F!"), "\u{F0}\u{9F}!");
+    assert_eq!(decode_latin1_fallback(b"ok\xE2\x82"), "ok\u{E2}\u{82}");
+    assert_eq!(decode_latin1_fallback(b"\xFF\xFE"), "\u{FF}\u{FE}");
+
+    // Pure Latin-1 high bytes that aren't valid UTF-8 round-trip through `as u8`.
+    let latin1: Vec<u8> = (0x80..=0xFF).collect();
+    let decoded = decode_latin1_fallback(&latin1);
+    assert_eq!(decoded.chars().map(|c| c as u8).collect::<Vec<_>>(), latin1);
+}
+
+/// What `count_code_points()` reports, in the terms `str::from_utf8` uses, to check
+/// the state machine behind it against std.
+fn counted_like_std(input: &[u8]) -> (Result<usize, usize>, Option<usize>) {
+    match count_code_points(input) {
+        Ok(count) => (Ok(count), None),
+        Err(DecodeError::Invalid {
+            valid_prefix,
+            invalid_sequence,
+            ..
+        }) => (Err(valid_prefix.len()), Some(invalid_sequence.len())),
+        Err(DecodeError::Incomplete { valid_prefix, .. }) => (Err(valid_prefix.len()), None),
+    }
+}
+
+fn std_counted(input: &[u8]) -> (Result<usize, usize>, Option<usize>) {
+    match std::str::from_utf8(input) {
+        Ok(s) => (Ok(s.chars().count()), None),
+        Err(error) => (Err(error.valid_up_to()), error.error_len()),
+    }
+}
+
+#[test]
+fn test_count_code_points_matches_std() {
+    fn check(input: &[u8]) {
+        assert_eq!(
+            counted_like_std(input),
+            std_counted(input),
+            "input = {:?}",
+            input
+        );
+    }
+
+    if cfg!(miri) {
+        return;
+    }
+    for &(input, _) in DECODED_LOSSY {
+        check(input);
+    }
+    let mut input = Vec::new();
+    for first in 0..=255u8 {
+        for second in 0..=255u8 {
+            check(&[first, second]);
+            // After a run of ASCII long enough for the word-at-a-time path.
+            input.clear();
+            input.extend_from_slice(b"abcdefghij");
+            input.extend_from_slice(&[first, second]);
+            check(&input);
+            if (0xE0..=0xF4).contains(&first) && (0x80..=0xBF).contains(&second) {
+                for third in 0..=255u8 {
+                    check(&[first, second, third]);
+                    check(&[first, second, third, 0x80]);
+                    check(&[first, second, third, b'!']);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_count_code_points_ascii_runs() {
+    // A non-ASCII byte at every offset into a run of ASCII, so that the fast path stops at
+    // every position within a word.
+    for len in 0..70 {
+        for at in 0..len {
+            for &byte in &[0x80, 0xC3, 0xFF] {
+                let mut input = vec![b'x'; len];
+                input[at] = byte;
+                let expected = std_counted(&input);
+                assert_eq!(counted_like_std(&input), expected, "len {} at {}", len, at);
+                assert_eq!(decode(&input).is_ok(), expected.0.is_ok());
+            }
+        }
+        assert_eq!(counted_like_std(&vec![b'x'; len]), (Ok(len), None));
+    }
+}
+
+#[test]
+fn test_count_code_points() {
+    assert_eq!(count_code_points(b"").unwrap(), 0);
+    assert_eq!(count_code_points(b"ascii").unwrap(), 5);
+    let mixed = "aé€\u{1F30D}中华\u{10FFFF}";
+    assert_eq!(count_code_points(mixed.as_bytes()).unwrap(), 7);
+    assert_eq!(
+        count_code_points(mixed.as_bytes()).unwrap(),
+        mixed.chars().count()
+    );
+
+    let error = count_code_points(b"ab\xC3\xA9\xFFcd").unwrap_err();
+    assert!(error.is_invalid());
+    assert_eq!(error.valid_prefix(), "abé");
+    assert!(count_code_points(b"ab\xE2\x82")
+        .unwrap_err()
+        .is_incomplete());
+}
+
+#[test]
+fn test_count_code_points_lossy() {
+    assert_eq!(count_code_points_lossy(b""), 0);
+    assert_eq!(count_code_points_lossy("aé€\u{1F30D}".as_bytes()), 4);
+    // One per maximal invalid subpart, and one for the incomplete suffix.
+    assert_eq!(count_code_points_lossy(b"\xF0\x9F\xFF!\xE2\x82"), 4);
+    assert_eq!(count_code_points_lossy(b"\xC0\x80"), 2);
+    for &(input, expected) in DECODED_LOSSY {
+        assert_eq!(
+            count_code_points_lossy(input),
+            expected.chars().count(),
+            "input = {:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn test_decode_lossy_into() {
+    fn decode_in_steps(input: &[u8], out_len: usize) -> String {
+        let mut input = input;
+        let mut result = String::new();
+        let mut out = vec![0; out_len];
+        while !input.is_empty() {
+            let (consumed, written) = decode_lossy_into(input, &mut out);
+            assert!(
+                consumed > 0 && written > 0,
+                "no progress with {} bytes",
+                out_len
+            );
+            result.push_str(std::str::from_utf8(&out[..written]).unwrap());
+            input = &input[consumed..];
+        }
+        result
+    }
+
+    for &(input, expected) in DECODED_LOSSY {
+        for out_len in 4..12 {
+            assert_eq!(
+                decode_in_steps(input, out_len),
+                expected,
+                "input = {:?}",
+                input
+            );
+        }
+    }
+    assert_eq!(decode_lossy_into(b"", &mut []), (0, 0));
+}
+
+#[test]
+fn test_decode_lossy_into_too_small() {
+    // The next code point does not fit, so nothing is written.
+    let mut out = [0; 3];
+    assert_eq!(decode_lossy_into("\u{1F30D}".as_bytes(), &mut out), (0, 0));
+    assert_eq!(decode_lossy_into("a\u{1F30D}".as_bytes(), &mut out), (1, 1));
+    assert_eq!(&out[..1], b"a");
+
+    // Nor is a replacement character split.
+    let mut out = [0; 2];
+    assert_eq!(decode_lossy_into(b"\xFF", &mut out), (0, 0));
+    assert_eq!(decode_lossy_into(b"\xC3\xA9\xFF", &mut out), (2, 2));
+    assert_eq!(&out, "é".as_bytes());
+
+    let mut out = [0; 5];
+    assert_eq!(decode_lossy_into(b"\xC3\xA9\xFF", &mut out), (3, 5));
+    assert_eq!(&out, "é\u{FFFD}".as_bytes());
+    // A trailing incomplete sequence is replaced once, consuming all of it.
+    assert_eq!(decode_lossy_into(b"\xF0\x9F\x8C", &mut out), (3, 3));
+    assert_eq!(&out[..3], "\u{FFFD}".as_bytes());
+
+    // Only what fits is looked at: a code point cut off beyond it, or an error further
+    // on, does not matter yet.
+    let mut out = [0; 4];
+    assert_eq!(
+        decode_lossy_into("abcd\u{1F30D}".as_bytes(), &mut out),
+        (4, 4)
+    );
+    assert_eq!(decode_lossy_into(b"abcdefgh\xFF", &mut out), (4, 4));
+    assert_eq!(&out, b"abcd");
+}
+
+#[test]
+fn test_decode_steps() {
+    fn steps(input: &[u8]) -> Vec<DecodeStep<'_>> {
+        decode_steps(input).collect()
+    }
+
+    assert_eq!(steps(b""), []);
+    // Ending in a valid run.
+    assert_eq!(steps(b"abc"), [DecodeStep::Valid("abc")]);
+    assert_eq!(
+        steps(b"\xFFa\xC3\xA9"),
+        [DecodeStep::Invalid(b"\xFF"), DecodeStep::Valid("aé")]
+    );
+    // Ending in an invalid byte, which more input could not fix.
+    assert_eq!(
+        steps(b"a\xC0\x80"),
+        [
+            DecodeStep::Valid("a"),
+            DecodeStep::Invalid(b"\xC0"),
+            DecodeStep::Invalid(b"\x80"),
+        ]
+    );
+    assert_eq!(
+        steps(b"a\xF0\x9F!"),
+        [
+            DecodeStep::Valid("a"),
+            DecodeStep::Invalid(b"\xF0\x9F"),
+            DecodeStep::Valid("!"),
+        ]
+    );
+    // Ending in a truncated sequence, which more input could complete.
+    assert_eq!(
+        steps(b"a\xF0\x9F\x8C"),
+        [
+            DecodeStep::Valid("a"),
+            DecodeStep::Incomplete(Incomplete::new(b"\xF0\x9F\x8C")),
+        ]
+    );
+    assert_eq!(
+        steps(b"\xE2"),
+        [DecodeStep::Incomplete(Incomplete::new(b"\xE2"))]
+    );
+
+    // The steps cover the whole input, the same way lossy decoding does.
+    for &(input, expected) in DECODED_LOSSY {
+        let mut lossy = String::new();
+        let mut bytes = Vec::new();
+        for step in decode_steps(input) {
+            match step {
+                DecodeStep::Valid(s) => {
+                    lossy.push_str(s);
+                    bytes.extend_from_slice(s.as_bytes());
+                }
+                DecodeStep::Invalid(b) => {
+                    lossy.push_str(REPLACEMENT_CHARACTER);
+                    bytes.extend_from_slice(b);
+                }
+                DecodeStep::Incomplete(incomplete) => {
+                    lossy.push_str(REPLACEMENT_CHARACTER);
+                    bytes.extend_from_slice(incomplete.as_bytes());
+                }
+            }
+        }
+        assert_eq!(lossy, expected, "input = {:?}", input);
+        assert_eq!(bytes, input);
+    }
+}
+
+/// An in-memory `AsyncBufRead` that returns one chunk per fill, and `Pending` before each.
+#[cfg(feature = "futures")]
+struct AsyncChunks<'a> {
+    chunks: VecDeque<&'a [u8]>,
+    ready: bool,
+}
+
+#[cfg(feature = "futures")]
+impl<'a> AsyncChunks<'a> {
+    fn new(chunks: &[&'a [u8]]) -> Self {
+        AsyncChunks {
+            chunks: chunks.iter().copied().filter(|c| !c.is_empty()).collect(),
+            ready: false,
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncRead for AsyncChunks<'_> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        _: &mut [u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        unimplemented!()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncBufRead for AsyncChunks<'_> {
+    fn poll_fill_buf(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if !std::mem::replace(&mut this.ready, true) {
+            cx.waker().wake_by_ref();
+            return std::task::Poll::Pending;
+        }
+        std::task::Poll::Ready(Ok(this.chunks.front().copied().unwrap_or(&[])))
+    }
+
+    fn consume(self: std::pin::Pin<&mut Self>, bytes: usize) {
+        let this = self.get_mut();
+        let front = this.chunks.front_mut().unwrap();
+        *front = &front[bytes..];
+        if front.is_empty() {
+            this.chunks.pop_front();
+            this.ready = false;
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn test_async_bufread_decoder() {
+    use futures::executor::block_on;
+
+    fn decode_async(chunks: &[&[u8]]) -> String {
+        let mut decoder = AsyncBufReadDecoder::new(AsyncChunks::new(chunks));
+        let mut output = String::new();
+        block_on(async {
+            while let Some(chunk) = decoder.next_lossy().await {
+                output.push_str(&chunk.unwrap());
+            }
+        });
+        output
+    }
+
+    for &(input, expected) in DECODED_LOSSY {
+        assert_eq!(decode_async(&[input]), expected, "input = {:?}", input);
+        assert_eq!(String::from_utf8_lossy(input), expected);
+        for i in 0..input.len() {
+            let (a, b) = input.split_at(i);
+            assert_eq!(
+                decode_async(&[a, b]),
+                expected,
+                "split at {} of {:?}",
+                i,
+                input
+            );
+        }
+    }
+    let input = "aé€\u{1F30D}".as_bytes();
+    all_partitions(input, |chunks| {
+        assert_eq!(decode_async(chunks), "aé€\u{1F30D}");
+    });
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn test_async_bufread_decoder_matches_sync() {
+    use futures::executor::block_on;
+
+    let input: &[u8] = b"ab\xE2\x82\xACcd\xF0\x9F\xFFe\xC3";
+    let (a, rest) = input.split_at(3);
+    let (b, c) = rest.split_at(6);
+
+    let mut sync = BufReadDecoder::new(Chunks(vec![a, b, c].into()));
+    let mut expected = Vec::new();
+    while let Some(chunk) = sync.next_lossy() {
+        expected.push(chunk.unwrap().to_owned());
+    }
+
+    let mut decoder = AsyncBufReadDecoder::new(AsyncChunks::new(&[a, b, c]));
+    let mut chunks = Vec::new();
+    block_on(async {
+        while let Some(chunk) = decoder.next_lossy().await {
+            chunks.push(chunk.unwrap());
+        }
+    });
+    assert_eq!(chunks, expected);
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn test_async_bufread_decoder_stream() {
+    use futures::executor::block_on;
+    use futures::{StreamExt, TryStreamExt};
+
+    for &(input, expected) in DECODED_LOSSY {
+        let baseline = String::from_utf8_lossy(input);
+        for i in 0..input.len() {
+            let (a, b) = input.split_at(i);
+            let decoder = AsyncBufReadDecoder::new(AsyncChunks::new(&[a, b]));
+            let output: String = block_on(decoder.try_collect()).unwrap();
+            assert_eq!(output, baseline, "split at {} of {:?}", i, input);
+            assert_eq!(output, expected);
+        }
+    }
+
+    // A trailing incomplete sequence, split across fills, gives exactly one U+FFFD.
+    let decoder = AsyncBufReadDecoder::new(AsyncChunks::new(&[b"ab\xF0", b"\x9F", b"\x8C"]));
+    let chunks: Vec<String> = block_on(decoder.map(Result::unwrap).collect());
+    assert_eq!(chunks, ["ab", "\u{FFFD}"]);
+
+    // And the stream stays ended.
+    let mut decoder = AsyncBufReadDecoder::new(AsyncChunks::new(&[b"\xE2\x82"]));
+    block_on(async {
+        assert_eq!(decoder.next().await.unwrap().unwrap(), "\u{FFFD}");
+        assert!(decoder.next().await.is_none());
+        assert!(decoder.next().await.is_none());
+    });
+}
+
+#[test]
+fn test_lossy_coalesced() {
+    fn coalesced(chunks: &[&[u8]]) -> (String, String, usize) {
+        let policy = ReplacementPolicy::LossyCoalesced("\u{FFFD}");
+        let mut lossy = String::new();
+        let mut replacements = 0;
+        {
+            let mut decoder = LossyDecoder::with_policy(policy, |s| lossy.push_str(s));
+            for &chunk in chunks {
+                replacements += decoder.feed_counted(chunk);
+            }
+        }
+        let mut strict = String::new();
+        let mut decoder = StrictDecoder::with_policy(policy, |s| strict.push_str(s));
+        for &chunk in chunks {
+            decoder.feed(chunk).unwrap();
+        }
+        decoder.finish().unwrap();
+        (lossy, strict, replacements)
+    }
+
+    // A 3-byte garbage run is one replacement, where the other modes give 2 or 3.
+    let (lossy, strict, replacements) = coalesced(&[b"a\xF0\x9F\xFFb"]);
+    assert_eq!(
+        (&*lossy, &*strict, replacements),
+        ("a\u{FFFD}b", "a\u{FFFD}b", 1)
+    );
+
+    // Runs split across chunks are still coalesced, and a trailing incomplete sequence
+    // joins the run before it.
+    all_partitions(b"a\xFF\xC0\x80b\xFE\xE2\x82", |chunks| {
+        let (lossy, strict, _) = coalesced(chunks);
+        assert_eq!(lossy, "a\u{FFFD}b\u{FFFD}");
+        assert_eq!(strict, lossy);
+    });
+    let (lossy, _, _) = coalesced(&[b"\xFF", b"", b"\xFFok\xC3"]);
+    assert_eq!(lossy, "\u{FFFD}ok\u{FFFD}");
+}
+
+#[test]
+fn test_lossy_builder_with_sink() {
+    #[derive(Default)]
+    struct Lines(Vec<String>);
+
+    impl Utf8Sink for Lines {
+        fn push_str(&mut self, s: &str) {
+            self.0.push(s.to_owned());
+        }
+    }
+
+    let mut bad = Vec::new();
+    let mut decoder = LossyDecoder::new_with_sink(Lines::default())
+        .policy(ReplacementPolicy::Lossy("?"))
+        .line_aligned(64)
+        .track_char_position()
+        .forensics(|offset, bytes: &[u8]| bad.push((offset, bytes.to_vec())));
+    decoder.feed(b"one\xFF\ntw");
+    decoder.feed(b"o\n\xE2\x82");
+    assert_eq!(decoder.char_position(), Some(9));
+    assert_eq!(decoder.finish().0, ["one?\n", "two\n", "?"]);
+    assert_eq!(bad, [(3, vec![0xFF]), (9, vec![0xE2, 0x82])]);
+
+    // Setting forensics mid-stream keeps the buffered sequence for the new callback.
+    let mut bad = Vec::new();
+    let mut decoder = LossyDecoder::to_string();
+    decoder.feed(b"a\xC3");
+    let decoder = decoder.forensics(|offset, bytes: &[u8]| bad.push((offset, bytes.to_vec())));
+    assert_eq!(decoder.finish(), "a\u{FFFD}");
+    assert_eq!(bad, [(1, vec![0xC3])]);
+}
+
+#[test]
+fn test_utf8_lossy_writer_inner_error() {
+    /// Fails any write that contains a `!`.
+    struct NoBang(Vec<u8>);
+
+    impl io::Write for NoBang {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.contains(&b'!') {
+                return Err(io::Error::other("no bangs"));
+            }
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut inner = NoBang(Vec::new());
+    {
+        let mut writer = Utf8LossyWriter::new(&mut inner);
+        io::Write::write_all(&mut writer, b"ab").unwrap();
+        let error = io::Write::write(&mut writer, b"c!\xFFd").unwrap_err();
+        assert_eq!(error.to_string(), "no bangs");
+        // The writer recovers for the next write.
+        io::Write::write_all(&mut writer, b"e\xC3").unwrap();
+        io::Write::flush(&mut writer).unwrap();
+    }
+    assert_eq!(inner.0, "abe\u{FFFD}".as_bytes());
+}
+
+#[test]
+fn test_incomplete_push_invalid() {
+    // An invalid lead byte followed by ASCII: the ASCII byte is kept, not dropped.
+    let mut incomplete = Incomplete::empty();
+    incomplete.push(0xC3).unwrap();
+    assert!(incomplete.try_complete(&[]).is_none());
+    incomplete.push(b'a').unwrap();
+    assert!(matches!(
+        incomplete.try_complete(&[]),
+        Some((Err(b"\xC3"), b""))
+    ));
+    assert_eq!(incomplete.as_bytes(), b"a");
+    assert!(matches!(incomplete.try_complete(&[]), Some((Ok("a"), b""))));
+    assert!(incomplete.is_empty());
+
+    // Every byte pushed comes back out, one way or the other, in order.
+    for bytes in [
+        &b"\xC3ab"[..],
+        b"\x80\x80a",
+        b"\xE2\x82a\x82",
+        b"a\xC3",
+        b"\xF0\x9F\xC3\xA9",
+        b"\xFF\xFE\xFD\xFC",
+    ] {
+        let mut incomplete = Incomplete::empty();
+        let mut output = Vec::new();
+        for &b in bytes {
+            incomplete.push(b).unwrap();
+            while !incomplete.is_empty() {
+                match incomplete.try_complete(&[]) {
+                    Some((Ok(s), _)) => output.extend_from_slice(s.as_bytes()),
+                    Some((Err(invalid), _)) => output.extend_from_slice(invalid),
+                    None => break,
+                }
+            }
+        }
+        output.extend_from_slice(incomplete.as_bytes());
+        assert_eq!(output, bytes, "bytes = {:?}", bytes);
+    }
+}